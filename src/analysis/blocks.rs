@@ -0,0 +1,360 @@
+//! Reconstruction of the trips each vehicle serves back-to-back.
+//!
+//! A [`BlockTracker`] follows each vehicle across snapshots and notes each trip it is seen
+//! serving, building up the run of trips the vehicle serves during a service day. A delay on one
+//! trip of a run often carries over to the next, so the [`TripLink`]s between consecutive trips
+//! are useful for studying how delays propagate.
+//!
+//! The realtime feed does not include the block of a trip, so runs can also be grouped into the
+//! [`Block`]s of the published schedule by passing the tracker the static trips, fetched with
+//! [`Realtime::fetch_trip`] for the IDs returned by [`BlockTracker::unknown_trips`].
+//!
+//! [`Realtime::fetch_trip`]: crate::Realtime::fetch_trip
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::{analysis::vehicle_id, intern::Interner, types::schedule::Trip, CombinedResponse};
+
+/// A trip served by a vehicle.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ServedTrip {
+    /// The full ID of the trip.
+    pub trip_id: String,
+    /// The full ID of the route of the trip, if known.
+    pub route_id: Option<String>,
+    /// The UNIX timestamp at which the vehicle was first seen serving the trip.
+    pub first_seen: u64,
+    /// The UNIX timestamp at which the vehicle was last seen serving the trip.
+    pub last_seen: u64,
+    /// The delay of the trip when it was last seen, in seconds.
+    pub delay: Option<i32>,
+}
+
+/// The trips a vehicle has been seen serving during a service day, in the order it served them.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct VehicleRun {
+    /// The ID of the vehicle.
+    pub vehicle_id: String,
+    /// The service day of the trips, in the form `YYYYMMDD`, if AT reported one.
+    pub start_date: Option<String>,
+    /// The trips served by the vehicle.
+    pub trips: Vec<ServedTrip>,
+}
+
+/// A vehicle moving on from one trip to the next.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TripLink {
+    /// The ID of the vehicle.
+    pub vehicle_id: String,
+    /// The trip the vehicle finished.
+    pub previous: ServedTrip,
+    /// The full ID of the trip the vehicle started.
+    pub next_trip_id: String,
+}
+
+/// The trips of a block of the published schedule which have been seen served, in the order they
+/// were served.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Block {
+    /// The ID of the block.
+    pub block_id: String,
+    /// The service day of the trips, in the form `YYYYMMDD`, if AT reported one.
+    pub start_date: Option<String>,
+    /// The trips of the block which have been seen served.
+    pub trips: Vec<ServedTrip>,
+    /// The IDs of the vehicles which served the trips. More than one vehicle serves a block if
+    /// a vehicle was swapped out part way through.
+    pub vehicle_ids: Vec<String>,
+}
+
+/// Follows vehicles across snapshots and reconstructs the runs of trips they serve.
+///
+/// Runs are kept until [`BlockTracker::clear`] is called, so a long-running tracker should be
+/// cleared once a day.
+#[derive(Debug, Clone, Default)]
+pub struct BlockTracker {
+    interner: Interner,
+    runs: HashMap<(Arc<str>, Option<Arc<str>>), VehicleRun>,
+    /// The block of each trip in the published schedule, or [`None`] for trips without a block.
+    blocks: HashMap<Arc<str>, Option<Arc<str>>>,
+}
+
+impl BlockTracker {
+    /// Creates a tracker which has not observed a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the pool the vehicle, trip and block IDs kept by the tracker are interned in, so
+    /// several trackers can share one copy of each ID. By default the tracker has a pool of its
+    /// own.
+    ///
+    /// # Parameters
+    ///
+    /// * `interner` - The pool to intern IDs in.
+    pub fn interner(mut self, interner: Interner) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    /// Observes a snapshot, returning a link for each vehicle which has started a new trip since
+    /// it was last seen. Vehicle positions without a trip or timestamp are ignored.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to observe.
+    pub fn observe(&mut self, combined: &CombinedResponse) -> Vec<TripLink> {
+        let mut links = vec![];
+
+        let entities = combined.entities.iter().chain(combined.unmatched.iter());
+        for entity in entities {
+            let (vehicle_id, vehicle) = match (vehicle_id(entity), &entity.vehicle) {
+                (Some(id), Some(vehicle)) => (id, vehicle),
+                _ => continue,
+            };
+            let (trip, timestamp) = match (&vehicle.trip, vehicle.timestamp) {
+                (Some(trip), Some(timestamp)) => (trip, timestamp),
+                _ => continue,
+            };
+            let trip_id = match trip.trip_id.as_deref() {
+                Some(trip_id) => trip_id,
+                None => continue,
+            };
+            let delay = entity.trip_update.as_ref().and_then(|tu| tu.delay);
+
+            let key = (
+                self.interner.intern(vehicle_id),
+                trip.start_date
+                    .as_deref()
+                    .map(|date| self.interner.intern(date)),
+            );
+            let run = self.runs.entry(key).or_insert_with(|| VehicleRun {
+                vehicle_id: vehicle_id.to_string(),
+                start_date: trip.start_date.clone(),
+                trips: vec![],
+            });
+
+            match run.trips.last_mut() {
+                Some(last) if last.trip_id == trip_id => {
+                    last.last_seen = last.last_seen.max(timestamp);
+                    last.delay = delay.or(last.delay);
+                    continue;
+                }
+                Some(last) => links.push(TripLink {
+                    vehicle_id: vehicle_id.to_string(),
+                    previous: last.clone(),
+                    next_trip_id: trip_id.to_string(),
+                }),
+                None => {}
+            }
+
+            run.trips.push(ServedTrip {
+                trip_id: trip_id.to_string(),
+                route_id: trip.route_id.clone(),
+                first_seen: timestamp,
+                last_seen: timestamp,
+                delay,
+            });
+        }
+
+        self.interner.shrink();
+        links
+    }
+
+    /// Records the blocks of trips from the published schedule, such as those returned by
+    /// [`Realtime::fetch_trip`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trips` - The trips from the static GTFS API.
+    ///
+    /// [`Realtime::fetch_trip`]: crate::Realtime::fetch_trip
+    pub fn add_trips(&mut self, trips: &[Trip]) {
+        for trip in trips {
+            let block_id = trip.block_id.as_deref().map(|id| self.interner.intern(id));
+            self.blocks
+                .insert(self.interner.intern(&trip.trip_id), block_id);
+        }
+    }
+
+    /// Returns the IDs of the trips which have been seen served but whose block is not known,
+    /// so they can be fetched from the static GTFS API.
+    pub fn unknown_trips(&self) -> Vec<&str> {
+        let mut trip_ids: Vec<&str> = self
+            .runs
+            .values()
+            .flat_map(|run| &run.trips)
+            .map(|trip| trip.trip_id.as_str())
+            .filter(|trip_id| !self.blocks.contains_key(*trip_id))
+            .collect();
+        trip_ids.sort_unstable();
+        trip_ids.dedup();
+        trip_ids
+    }
+
+    /// Returns every run which has been reconstructed.
+    pub fn runs(&self) -> impl Iterator<Item = &VehicleRun> {
+        self.runs.values()
+    }
+
+    /// Returns the runs of a vehicle, one for each service day it has been seen on.
+    ///
+    /// # Parameters
+    ///
+    /// * `vehicle_id` - The ID of the vehicle.
+    pub fn runs_of<'a>(&'a self, vehicle_id: &'a str) -> impl Iterator<Item = &'a VehicleRun> {
+        self.runs
+            .values()
+            .filter(move |run| run.vehicle_id == vehicle_id)
+    }
+
+    /// Groups the trips which have been seen served into the blocks of the published schedule.
+    /// Trips whose block is not known, or which have no block, are left out.
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks: BTreeMap<(&str, &Option<String>), Block> = BTreeMap::new();
+        for run in self.runs.values() {
+            for trip in &run.trips {
+                let block_id = match self.blocks.get(trip.trip_id.as_str()) {
+                    Some(Some(block_id)) => block_id,
+                    _ => continue,
+                };
+                let block = blocks
+                    .entry((block_id, &run.start_date))
+                    .or_insert_with(|| Block {
+                        block_id: block_id.to_string(),
+                        start_date: run.start_date.clone(),
+                        trips: vec![],
+                        vehicle_ids: vec![],
+                    });
+                block.trips.push(trip.clone());
+                if !block.vehicle_ids.contains(&run.vehicle_id) {
+                    block.vehicle_ids.push(run.vehicle_id.clone());
+                }
+            }
+        }
+
+        blocks
+            .into_values()
+            .map(|mut block| {
+                block.trips.sort_by_key(|trip| trip.first_seen);
+                block
+            })
+            .collect()
+    }
+
+    /// Forgets every run, keeping the blocks of trips which have been recorded.
+    pub fn clear(&mut self) {
+        self.runs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::combined::tests::snapshot;
+
+    fn serving(vehicle_id: &str, trip_id: &str, start_date: &str, timestamp: u64) -> Value {
+        let trip = json!({"trip_id": trip_id, "route_id": "r1", "start_date": start_date});
+        json!({
+            "id": vehicle_id,
+            "vehicle": {
+                "trip": trip,
+                "vehicle": {"id": vehicle_id},
+                "timestamp": timestamp
+            },
+            "trip_update": {"trip": trip, "delay": timestamp / 10}
+        })
+    }
+
+    fn trip(trip_id: &str, block_id: Option<&str>) -> Trip {
+        Trip {
+            trip_id: trip_id.to_string(),
+            route_id: "r1".to_string(),
+            service_id: None,
+            trip_headsign: None,
+            direction_id: None,
+            block_id: block_id.map(String::from),
+            shape_id: None,
+        }
+    }
+
+    #[test]
+    fn links_consecutive_trips() {
+        let mut tracker = BlockTracker::new();
+        assert!(tracker
+            .observe(&snapshot(json!([serving("v1", "t1", "20240115", 100)])))
+            .is_empty());
+        assert!(tracker
+            .observe(&snapshot(json!([serving("v1", "t1", "20240115", 200)])))
+            .is_empty());
+
+        let links = tracker.observe(&snapshot(json!([serving("v1", "t2", "20240115", 300)])));
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].vehicle_id, "v1");
+        assert_eq!(links[0].next_trip_id, "t2");
+        let previous = &links[0].previous;
+        assert_eq!(previous.trip_id, "t1");
+        assert_eq!((previous.first_seen, previous.last_seen), (100, 200));
+        assert_eq!(previous.delay, Some(20));
+
+        assert!(tracker
+            .observe(&snapshot(json!([serving("v1", "t2", "20240115", 400)])))
+            .is_empty());
+        let runs: Vec<_> = tracker.runs_of("v1").collect();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].trips.len(), 2);
+    }
+
+    #[test]
+    fn groups_runs_into_blocks() {
+        let mut tracker = BlockTracker::new();
+        tracker.observe(&snapshot(json!([
+            serving("v1", "t1", "20240115", 100),
+            serving("v3", "t4", "20240115", 100),
+        ])));
+        tracker.observe(&snapshot(json!([serving("v1", "t2", "20240115", 200)])));
+        // v1 is swapped out for v2 part way through the block.
+        tracker.observe(&snapshot(json!([serving("v2", "t3", "20240115", 300)])));
+        // The same block is served again the next day.
+        tracker.observe(&snapshot(json!([serving("v1", "t1", "20240116", 400)])));
+
+        assert_eq!(tracker.unknown_trips(), ["t1", "t2", "t3", "t4"]);
+        assert!(tracker.blocks().is_empty());
+
+        tracker.add_trips(&[
+            trip("t1", Some("b1")),
+            trip("t2", Some("b1")),
+            trip("t3", Some("b1")),
+            trip("t4", None),
+        ]);
+        assert!(tracker.unknown_trips().is_empty());
+
+        let mut blocks = tracker.blocks();
+        assert_eq!(blocks.len(), 2);
+        for block in &mut blocks {
+            assert_eq!(block.block_id, "b1");
+            block.vehicle_ids.sort_unstable();
+        }
+
+        assert_eq!(blocks[0].start_date.as_deref(), Some("20240115"));
+        let trip_ids: Vec<_> = blocks[0].trips.iter().map(|t| t.trip_id.as_str()).collect();
+        assert_eq!(trip_ids, ["t1", "t2", "t3"]);
+        assert_eq!(blocks[0].vehicle_ids, ["v1", "v2"]);
+
+        assert_eq!(blocks[1].start_date.as_deref(), Some("20240116"));
+        assert_eq!(blocks[1].trips.len(), 1);
+        assert_eq!(blocks[1].vehicle_ids, ["v1"]);
+
+        tracker.clear();
+        assert_eq!(tracker.runs().count(), 0);
+    }
+}
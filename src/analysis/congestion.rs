@@ -0,0 +1,414 @@
+//! Reports of where vehicles get stuck along a corridor.
+//!
+//! A [`Corridor`] is a path along the road network, such as the shape of a route from
+//! [`Realtime::fetch_shape`], divided into road segments of equal length. A [`CorridorReport`]
+//! places the speed samples of a [`SpeedTracker`] onto the segments of a corridor, and gathers
+//! their speeds and the congestion AT reported into statistics per segment and hour of the day,
+//! so the slow parts of a corridor and the times they are slow stand out.
+//!
+//! [`Realtime::fetch_shape`]: crate::Realtime::fetch_shape
+//! [`SpeedTracker`]: crate::analysis::speed::SpeedTracker
+
+use std::collections::BTreeMap;
+
+use crate::{
+    analysis::speed::{SegmentSpeed, SpeedSample},
+    arrivals::local_hour,
+    geo::{distance, project},
+    types::{gtfs::CongestionLevel, schedule::ShapePoint},
+};
+
+/// The default length of the road segments of a corridor, in metres.
+const DEFAULT_SEGMENT_LENGTH: f64 = 250.0;
+
+/// The default distance from a corridor within which a sample is placed onto it, in metres.
+const DEFAULT_TOLERANCE: f64 = 30.0;
+
+/// A path along the road network, divided into road segments.
+#[derive(Debug, Clone)]
+pub struct Corridor {
+    name: String,
+    points: Vec<(f32, f32)>,
+    /// The distance along the corridor to each point, in metres.
+    offsets: Vec<f64>,
+    segment_length: f64,
+    tolerance: f64,
+}
+
+impl Corridor {
+    /// Creates a corridor along a path.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the corridor, used in reports.
+    /// * `points` - The latitude and longitude of the points of the path, in order.
+    pub fn new<S: Into<String>>(name: S, points: Vec<(f32, f32)>) -> Self {
+        let mut offsets = Vec::with_capacity(points.len());
+        let mut travelled = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                travelled += distance(points[i - 1], *point);
+            }
+            offsets.push(travelled);
+        }
+
+        Self {
+            name: name.into(),
+            points,
+            offsets,
+            segment_length: DEFAULT_SEGMENT_LENGTH,
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Creates a corridor along a shape from the static GTFS API.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the corridor, used in reports.
+    /// * `shape` - The points of the shape, in any order.
+    pub fn from_shape<S: Into<String>>(name: S, shape: &[ShapePoint]) -> Self {
+        let mut shape: Vec<&ShapePoint> = shape.iter().collect();
+        shape.sort_by_key(|point| point.shape_pt_sequence);
+        let points = shape
+            .iter()
+            .map(|point| (point.shape_pt_lat as f32, point.shape_pt_lon as f32))
+            .collect();
+        Self::new(name, points)
+    }
+
+    /// Sets the length of the road segments of the corridor, in metres. Defaults to 250 m.
+    ///
+    /// # Parameters
+    ///
+    /// * `segment_length` - The length of each segment.
+    pub fn segment_length(mut self, segment_length: f64) -> Self {
+        self.segment_length = segment_length.max(1.0);
+        self
+    }
+
+    /// Sets how far from the corridor a sample can be and still be placed onto it, in metres.
+    /// Defaults to 30 m.
+    ///
+    /// # Parameters
+    ///
+    /// * `tolerance` - The greatest distance from the corridor.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Returns the name of the corridor.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the length of the corridor, in metres.
+    pub fn length(&self) -> f64 {
+        self.offsets.last().copied().unwrap_or(0.0)
+    }
+
+    /// Returns the number of road segments the corridor is divided into.
+    pub fn segments(&self) -> usize {
+        ((self.length() / self.segment_length).ceil() as usize).max(1)
+    }
+
+    /// Returns the index of the road segment nearest to a point, or [`None`] if the point is
+    /// further from the corridor than the tolerance.
+    ///
+    /// [`None`]: std::option::Option::None
+    fn locate(&self, point: (f32, f32)) -> Option<usize> {
+        let (along, offset) = match self.points.len() {
+            0 => return None,
+            1 => (0.0, distance(point, self.points[0])),
+            _ => self
+                .points
+                .windows(2)
+                .zip(&self.offsets)
+                .map(|(w, start)| {
+                    let (along, offset) = project(point, w[0], w[1]);
+                    (start + along, offset)
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))?,
+        };
+
+        (offset <= self.tolerance)
+            .then(|| ((along / self.segment_length) as usize).min(self.segments() - 1))
+    }
+}
+
+/// The statistics of the samples on a road segment in an hour of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CorridorCell {
+    /// The speeds measured.
+    pub speed: SegmentSpeed,
+    /// The number of samples for which AT reported a congestion level.
+    pub congestion_reported: u64,
+    /// The number of samples for which AT reported [`Congestion`] or [`SevereCongestion`].
+    ///
+    /// [`Congestion`]: crate::types::gtfs::CongestionLevel::Congestion
+    /// [`SevereCongestion`]: crate::types::gtfs::CongestionLevel::SevereCongestion
+    pub congested: u64,
+}
+
+impl CorridorCell {
+    /// Returns the share of samples reporting a congestion level which reported congestion, or
+    /// [`None`] if none reported a level.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn congested_share(&self) -> Option<f64> {
+        (self.congestion_reported > 0)
+            .then(|| self.congested as f64 / self.congestion_reported as f64)
+    }
+}
+
+/// A row of a corridor report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct CorridorRow {
+    /// The index of the road segment, counting from the start of the corridor.
+    pub segment: usize,
+    /// The distance along the corridor to the start of the segment, in metres.
+    pub start: f64,
+    /// The distance along the corridor to the end of the segment, in metres.
+    pub end: f64,
+    /// The hour of the day in Auckland, from 0 to 23.
+    pub hour: u8,
+    /// The statistics of the segment in the hour.
+    pub cell: CorridorCell,
+}
+
+/// Gathers speed samples along a corridor into statistics per road segment and hour of the day.
+///
+/// Feed the report the samples returned by [`SpeedTracker::observe`]. One tracker can feed the
+/// reports of several corridors, and samples which are not on the corridor are ignored.
+///
+/// [`SpeedTracker::observe`]: crate::analysis::speed::SpeedTracker::observe
+#[derive(Debug, Clone)]
+pub struct CorridorReport {
+    corridor: Corridor,
+    cells: BTreeMap<(usize, u8), CorridorCell>,
+}
+
+impl CorridorReport {
+    /// Creates an empty report of a corridor.
+    ///
+    /// # Parameters
+    ///
+    /// * `corridor` - The corridor to report on.
+    pub fn new(corridor: Corridor) -> Self {
+        Self {
+            corridor,
+            cells: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the corridor the report is of.
+    pub fn corridor(&self) -> &Corridor {
+        &self.corridor
+    }
+
+    /// Records a speed sample if it is on the corridor.
+    ///
+    /// # Parameters
+    ///
+    /// * `sample` - The sample to record.
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the sample was on the corridor.
+    pub fn record(&mut self, sample: &SpeedSample) -> bool {
+        let segment = match self.corridor.locate((sample.latitude, sample.longitude)) {
+            Some(segment) => segment,
+            None => return false,
+        };
+        let hour = local_hour(sample.timestamp as i64);
+
+        let cell = self.cells.entry((segment, hour)).or_default();
+        cell.speed.record(sample);
+        match sample.congestion_level {
+            None | Some(CongestionLevel::UnknownCongestionLevel) => {}
+            Some(level) => {
+                cell.congestion_reported += 1;
+                if matches!(
+                    level,
+                    CongestionLevel::Congestion | CongestionLevel::SevereCongestion
+                ) {
+                    cell.congested += 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the statistics of a road segment in an hour of the day, or [`None`] if no sample
+    /// was recorded there.
+    ///
+    /// # Parameters
+    ///
+    /// * `segment` - The index of the road segment.
+    /// * `hour` - The hour of the day in Auckland, from 0 to 23.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn cell(&self, segment: usize, hour: u8) -> Option<&CorridorCell> {
+        self.cells.get(&(segment, hour))
+    }
+
+    /// Returns a row for each road segment and hour of the day in which a sample was recorded,
+    /// ordered by segment and then by hour.
+    pub fn rows(&self) -> Vec<CorridorRow> {
+        let segment_length = self.corridor.segment_length;
+        let length = self.corridor.length();
+        self.cells
+            .iter()
+            .map(|(&(segment, hour), cell)| CorridorRow {
+                segment,
+                start: segment as f64 * segment_length,
+                end: ((segment + 1) as f64 * segment_length).min(length),
+                hour,
+                cell: *cell,
+            })
+            .collect()
+    }
+
+    /// Returns the rows with the lowest average speed, slowest first, for finding where vehicles
+    /// get stuck.
+    ///
+    /// # Parameters
+    ///
+    /// * `count` - The greatest number of rows to return.
+    pub fn slowest(&self, count: usize) -> Vec<CorridorRow> {
+        let mut rows = self.rows();
+        rows.sort_by(|a, b| a.cell.speed.average().total_cmp(&b.cell.speed.average()));
+        rows.truncate(count);
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// 13:00 in Auckland.
+    const ONE_PM: u64 = 1_705_276_800;
+
+    fn sample(
+        point: (f32, f32),
+        timestamp: u64,
+        congestion_level: Option<CongestionLevel>,
+        speed: f64,
+    ) -> SpeedSample {
+        SpeedSample {
+            vehicle_id: "v1".to_string(),
+            trip_id: None,
+            route_id: None,
+            segment: None,
+            timestamp,
+            latitude: point.0,
+            longitude: point.1,
+            congestion_level,
+            distance: speed * 10.0,
+            elapsed: Duration::from_secs(10),
+            instantaneous: speed,
+            smoothed: speed,
+        }
+    }
+
+    /// A corridor running about 1.1 km due south, in segments of 500 m.
+    fn report() -> CorridorReport {
+        let corridor =
+            Corridor::new("south", vec![(-36.85, 174.76), (-36.86, 174.76)]).segment_length(500.0);
+        CorridorReport::new(corridor)
+    }
+
+    #[test]
+    fn places_samples_onto_segments() {
+        let mut report = report();
+        assert_eq!(report.corridor().segments(), 3);
+
+        assert!(report.record(&sample((-36.851, 174.76), ONE_PM, None, 10.0)));
+        assert!(report.record(&sample((-36.8555, 174.76), ONE_PM, None, 10.0)));
+        assert!(report.record(&sample((-36.8595, 174.76), ONE_PM, None, 10.0)));
+        // About 20 m east of the corridor.
+        assert!(report.record(&sample((-36.851, 174.7602), ONE_PM, None, 10.0)));
+        // About 900 m east of the corridor.
+        assert!(!report.record(&sample((-36.855, 174.77), ONE_PM, None, 10.0)));
+
+        assert_eq!(report.cell(0, 13).unwrap().speed.samples, 2);
+        assert_eq!(report.cell(1, 13).unwrap().speed.samples, 1);
+        assert_eq!(report.cell(2, 13).unwrap().speed.samples, 1);
+
+        let rows = report.rows();
+        let bounds: Vec<_> = rows.iter().map(|row| (row.start, row.end)).collect();
+        let length = report.corridor().length();
+        assert!((length - 1112.0).abs() < 5.0);
+        assert_eq!(bounds, [(0.0, 500.0), (500.0, 1000.0), (1000.0, length)]);
+    }
+
+    #[test]
+    fn gathers_congestion_by_hour() {
+        let mut report = report();
+        let point = (-36.851, 174.76);
+        report.record(&sample(
+            point,
+            ONE_PM,
+            Some(CongestionLevel::Congestion),
+            5.0,
+        ));
+        report.record(&sample(
+            point,
+            ONE_PM,
+            Some(CongestionLevel::RunningSmoothly),
+            15.0,
+        ));
+        report.record(&sample(
+            point,
+            ONE_PM,
+            Some(CongestionLevel::SevereCongestion),
+            1.0,
+        ));
+        report.record(&sample(
+            point,
+            ONE_PM,
+            Some(CongestionLevel::RunningSmoothly),
+            3.0,
+        ));
+        report.record(&sample(
+            point,
+            ONE_PM,
+            Some(CongestionLevel::UnknownCongestionLevel),
+            6.0,
+        ));
+        report.record(&sample(point, ONE_PM + 3600, None, 12.0));
+
+        let cell = report.cell(0, 13).unwrap();
+        assert_eq!(cell.speed.samples, 5);
+        assert_eq!(cell.congestion_reported, 4);
+        assert_eq!(cell.congested, 2);
+        assert_eq!(cell.congested_share(), Some(0.5));
+        assert!((cell.speed.average() - 6.0).abs() < 1e-9);
+
+        let cell = report.cell(0, 14).unwrap();
+        assert_eq!(cell.speed.samples, 1);
+        assert_eq!(cell.congested_share(), None);
+        assert!(report.cell(0, 15).is_none());
+    }
+
+    #[test]
+    fn finds_the_slowest_rows() {
+        let mut report = report();
+        report.record(&sample((-36.851, 174.76), ONE_PM, None, 8.0));
+        report.record(&sample((-36.8555, 174.76), ONE_PM, None, 2.0));
+        report.record(&sample((-36.8595, 174.76), ONE_PM, None, 12.0));
+        report.record(&sample((-36.8595, 174.76), ONE_PM + 3600, None, 4.0));
+
+        let slowest: Vec<_> = report
+            .slowest(3)
+            .iter()
+            .map(|row| (row.segment, row.hour))
+            .collect();
+        assert_eq!(slowest, [(1, 13), (2, 14), (0, 13)]);
+    }
+}
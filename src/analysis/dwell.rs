@@ -0,0 +1,252 @@
+//! Measurement of how long vehicles dwell at stops.
+//!
+//! AT reports a vehicle as [`StoppedAt`] a stop while it is standing there. A [`DwellTracker`]
+//! notes when each vehicle is first seen stopped at a stop and when it is first seen no longer
+//! stopped there, and collects the time between into statistics per stop.
+//!
+//! [`StoppedAt`]: crate::types::gtfs::VehicleStopStatus::StoppedAt
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::{
+    analysis::vehicles, intern::Interner, types::gtfs::VehicleStopStatus, CombinedResponse,
+};
+
+/// A completed dwell of a vehicle at a stop.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Dwell {
+    /// The ID of the vehicle.
+    pub vehicle_id: String,
+    /// The full ID of the stop.
+    pub stop_id: String,
+    /// The full ID of the trip the vehicle was serving, if any.
+    pub trip_id: Option<String>,
+    /// The UNIX timestamp at which the vehicle was first seen stopped at the stop.
+    pub arrived_at: u64,
+    /// The UNIX timestamp at which the vehicle was first seen no longer stopped at the stop.
+    pub departed_at: u64,
+}
+
+impl Dwell {
+    /// Returns how long the vehicle dwelled at the stop.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.departed_at.saturating_sub(self.arrived_at))
+    }
+}
+
+/// Statistics of the dwells measured at a stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DwellStats {
+    /// The number of dwells measured.
+    pub count: u64,
+    /// The sum of the dwell times.
+    pub total: Duration,
+    /// The shortest dwell time.
+    pub min: Duration,
+    /// The longest dwell time.
+    pub max: Duration,
+}
+
+impl DwellStats {
+    /// Returns the mean dwell time.
+    pub fn mean(&self) -> Duration {
+        Duration::from_secs_f64(self.total.as_secs_f64() / self.count.max(1) as f64)
+    }
+
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+    }
+}
+
+/// A vehicle which is currently stopped at a stop.
+#[derive(Debug, Clone)]
+struct Stopped {
+    stop_id: Arc<str>,
+    trip_id: Option<Arc<str>>,
+    since: u64,
+}
+
+/// Tracks vehicles stopping at stops across snapshots and measures their dwell times.
+///
+/// A vehicle which leaves the feed while stopped is forgotten without completing its dwell.
+#[derive(Debug, Clone, Default)]
+pub struct DwellTracker {
+    interner: Interner,
+    stopped: HashMap<Arc<str>, Stopped>,
+    stats: HashMap<Arc<str>, DwellStats>,
+}
+
+impl DwellTracker {
+    /// Creates a tracker which has not observed a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the pool the vehicle, stop and trip IDs kept between snapshots are interned in, so
+    /// several trackers can share one copy of each ID. By default the tracker has a pool of its
+    /// own.
+    ///
+    /// # Parameters
+    ///
+    /// * `interner` - The pool to intern IDs in.
+    pub fn interner(mut self, interner: Interner) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    /// Observes a snapshot, returning the dwells which were completed since the last snapshot.
+    /// Vehicle positions without a timestamp are ignored, and a vehicle stopped at a stop stays
+    /// stopped there until a position with a timestamp is seen.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to observe.
+    pub fn observe(&mut self, combined: &CombinedResponse) -> Vec<Dwell> {
+        let mut dwells = vec![];
+        let mut stopped = HashMap::new();
+
+        let positions = vehicles(&combined.entities).chain(vehicles(&combined.unmatched));
+        for (vehicle_id, vehicle) in positions {
+            let previous = self.stopped.remove(vehicle_id);
+            let timestamp = match vehicle.timestamp {
+                Some(timestamp) => timestamp,
+                None => {
+                    if let Some(previous) = previous {
+                        stopped.insert(self.interner.intern(vehicle_id), previous);
+                    }
+                    continue;
+                }
+            };
+            let stop_id = match vehicle.current_status {
+                VehicleStopStatus::StoppedAt => vehicle.stop_id.as_deref(),
+                _ => None,
+            };
+
+            match previous {
+                Some(previous) if Some(&*previous.stop_id) == stop_id => {
+                    stopped.insert(self.interner.intern(vehicle_id), previous);
+                    continue;
+                }
+                Some(previous) => {
+                    let dwell = Dwell {
+                        vehicle_id: vehicle_id.to_string(),
+                        stop_id: previous.stop_id.to_string(),
+                        trip_id: previous.trip_id.as_deref().map(str::to_string),
+                        arrived_at: previous.since,
+                        departed_at: timestamp.max(previous.since),
+                    };
+                    self.stats
+                        .entry(previous.stop_id)
+                        .or_insert(DwellStats {
+                            count: 0,
+                            total: Duration::ZERO,
+                            min: Duration::MAX,
+                            max: Duration::ZERO,
+                        })
+                        .record(dwell.duration());
+                    dwells.push(dwell);
+                }
+                None => {}
+            }
+
+            if let Some(stop_id) = stop_id {
+                let trip_id = vehicle.trip.as_ref().and_then(|t| t.trip_id.as_deref());
+                stopped.insert(
+                    self.interner.intern(vehicle_id),
+                    Stopped {
+                        stop_id: self.interner.intern(stop_id),
+                        trip_id: trip_id.map(|id| self.interner.intern(id)),
+                        since: timestamp,
+                    },
+                );
+            }
+        }
+
+        self.stopped = stopped;
+        self.interner.shrink();
+        dwells
+    }
+
+    /// Returns the dwell statistics of a stop, or [`None`] if no dwell has been measured there.
+    ///
+    /// # Parameters
+    ///
+    /// * `stop_id` - The full ID of the stop.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn stats(&self, stop_id: &str) -> Option<&DwellStats> {
+        self.stats.get(stop_id)
+    }
+
+    /// Returns the dwell statistics of every stop a dwell has been measured at.
+    pub fn all_stats(&self) -> impl Iterator<Item = (&str, &DwellStats)> {
+        self.stats
+            .iter()
+            .map(|(stop_id, stats)| (&**stop_id, stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::combined::tests::unmatched;
+
+    fn position(timestamp: Option<u64>, status: u8, stop_id: &str) -> CombinedResponse {
+        let mut vehicle = json!({
+            "trip": {"trip_id": "t1"},
+            "vehicle": {"id": "v1"},
+            "current_status": status,
+            "stop_id": stop_id
+        });
+        if let Some(timestamp) = timestamp {
+            vehicle["timestamp"] = Value::from(timestamp);
+        }
+        unmatched(json!([{"id": "e1", "vehicle": vehicle}]))
+    }
+
+    #[test]
+    fn measures_dwells_from_arrival_to_departure() {
+        let mut tracker = DwellTracker::new();
+        assert!(tracker.observe(&position(Some(100), 2, "s1")).is_empty());
+        assert!(tracker.observe(&position(Some(130), 1, "s1")).is_empty());
+        assert!(tracker.observe(&position(Some(160), 1, "s1")).is_empty());
+        // A position without a timestamp does not end the dwell.
+        assert!(tracker.observe(&position(None, 2, "s2")).is_empty());
+
+        let dwells = tracker.observe(&position(Some(190), 2, "s2"));
+        assert_eq!(dwells.len(), 1);
+        let dwell = &dwells[0];
+        assert_eq!(
+            (dwell.vehicle_id.as_str(), dwell.stop_id.as_str()),
+            ("v1", "s1")
+        );
+        assert_eq!(dwell.trip_id.as_deref(), Some("t1"));
+        assert_eq!(dwell.duration(), Duration::from_secs(60));
+
+        tracker.observe(&position(Some(200), 1, "s1"));
+        tracker.observe(&position(Some(220), 1, "s2"));
+        let stats = tracker.stats("s1").unwrap();
+        assert_eq!(stats.count, 2);
+        assert_eq!(
+            (stats.min, stats.max),
+            (Duration::from_secs(20), Duration::from_secs(60))
+        );
+        assert_eq!(stats.mean(), Duration::from_secs(40));
+        assert_eq!(tracker.all_stats().count(), 1);
+    }
+
+    #[test]
+    fn forgets_vehicles_which_leave_the_feed() {
+        let mut tracker = DwellTracker::new();
+        tracker.observe(&position(Some(100), 1, "s1"));
+        tracker.observe(&unmatched(json!([])));
+        assert!(tracker.observe(&position(Some(200), 2, "s2")).is_empty());
+        assert!(tracker.stats("s1").is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! Statistics of the vehicles active in a snapshot, from their vehicle descriptors.
+//!
+//! AT labels vehicles with the code of their operator followed by a fleet number, such as
+//! `NB 3245`. [`fleet_stats`] counts the active vehicles by the prefix of their label, and flags
+//! descriptors which are missing, incomplete or shared by more than one vehicle, which usually
+//! points to a problem in the feed.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::CombinedResponse;
+
+/// A field of a vehicle descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum DescriptorField {
+    /// The internal ID of the vehicle.
+    Id,
+    /// The label shown to passengers.
+    Label,
+    /// The licence plate of the vehicle.
+    LicensePlate,
+}
+
+/// A problem with the vehicle descriptor of a single vehicle position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DescriptorIssue {
+    /// The vehicle position has no vehicle descriptor.
+    Missing,
+    /// The descriptor has no ID.
+    MissingId,
+    /// The descriptor has no label, or a label which is blank.
+    MissingLabel,
+    /// The label has no fleet number.
+    MalformedLabel,
+}
+
+/// A vehicle position with a problem in its vehicle descriptor.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MalformedDescriptor {
+    /// The ID of the entity with the vehicle position.
+    pub entity_id: String,
+    /// The problem with the descriptor.
+    pub issue: DescriptorIssue,
+}
+
+/// A descriptor value which is reported by more than one vehicle position.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DuplicateDescriptor {
+    /// The field which is duplicated.
+    pub field: DescriptorField,
+    /// The duplicated value.
+    pub value: String,
+    /// The IDs of the entities reporting the value.
+    pub entity_ids: Vec<String>,
+}
+
+/// Statistics of the vehicles active in a snapshot.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct FleetStats {
+    /// The number of vehicle positions.
+    pub active: usize,
+    /// The number of active vehicles by the prefix of their label, such as `NB`.
+    pub by_prefix: BTreeMap<String, usize>,
+    /// The number of active vehicles whose label has no prefix.
+    pub unprefixed: usize,
+    /// The descriptor values reported by more than one vehicle position.
+    pub duplicates: Vec<DuplicateDescriptor>,
+    /// The vehicle positions with a problem in their descriptor.
+    pub malformed: Vec<MalformedDescriptor>,
+}
+
+/// Returns the operator prefix of a vehicle label, the letters before the fleet number, or
+/// [`None`] if the label does not start with a letter.
+///
+/// # Parameters
+///
+/// * `label` - The label of the vehicle, such as `NB 3245`.
+///
+/// [`None`]: std::option::Option::None
+pub fn label_prefix(label: &str) -> Option<&str> {
+    let label = label.trim_start();
+    let end = label
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(label.len());
+    (end > 0).then(|| &label[..end])
+}
+
+/// Collects statistics of the vehicles active in a snapshot, from the vehicle positions in both
+/// the merged and unmatched entities.
+///
+/// # Parameters
+///
+/// * `combined` - The snapshot.
+pub fn fleet_stats(combined: &CombinedResponse) -> FleetStats {
+    let mut stats = FleetStats::default();
+    let mut seen: HashMap<(DescriptorField, &str), Vec<&str>> = HashMap::new();
+
+    let entities = combined.entities.iter().chain(combined.unmatched.iter());
+    for entity in entities {
+        let vehicle = match &entity.vehicle {
+            Some(vehicle) => vehicle,
+            None => continue,
+        };
+        stats.active += 1;
+
+        let mut flag = |issue| {
+            stats.malformed.push(MalformedDescriptor {
+                entity_id: entity.id.clone(),
+                issue,
+            })
+        };
+        let descriptor = match &vehicle.vehicle {
+            Some(descriptor) => descriptor,
+            None => {
+                flag(DescriptorIssue::Missing);
+                stats.unprefixed += 1;
+                continue;
+            }
+        };
+        if descriptor.id.is_none() {
+            flag(DescriptorIssue::MissingId);
+        }
+        let label = descriptor
+            .label
+            .as_deref()
+            .map(str::trim)
+            .filter(|label| !label.is_empty());
+        match label {
+            Some(label) if !label.contains(|c: char| c.is_ascii_digit()) => {
+                flag(DescriptorIssue::MalformedLabel)
+            }
+            Some(_) => {}
+            None => flag(DescriptorIssue::MissingLabel),
+        }
+
+        match label.and_then(label_prefix) {
+            Some(prefix) => *stats.by_prefix.entry(prefix.to_string()).or_default() += 1,
+            None => stats.unprefixed += 1,
+        }
+
+        let fields = [
+            (DescriptorField::Id, descriptor.id.as_deref()),
+            (DescriptorField::Label, label),
+            (
+                DescriptorField::LicensePlate,
+                descriptor.license_plate.as_deref(),
+            ),
+        ];
+        for (field, value) in fields.iter() {
+            if let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) {
+                seen.entry((*field, value)).or_default().push(&entity.id);
+            }
+        }
+    }
+
+    stats.duplicates = seen
+        .into_iter()
+        .filter(|(_, entity_ids)| entity_ids.len() > 1)
+        .map(|((field, value), entity_ids)| DuplicateDescriptor {
+            field,
+            value: value.to_string(),
+            entity_ids: entity_ids.into_iter().map(str::to_string).collect(),
+        })
+        .collect();
+    stats
+        .duplicates
+        .sort_by(|a, b| (a.field, &a.value).cmp(&(b.field, &b.value)));
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::combined::tests::snapshot;
+
+    fn position(entity_id: &str, descriptor: Value) -> Value {
+        json!({"id": entity_id, "vehicle": {"vehicle": descriptor}})
+    }
+
+    #[test]
+    fn finds_label_prefixes() {
+        assert_eq!(label_prefix("NB 3245"), Some("NB"));
+        assert_eq!(label_prefix("  RT1"), Some("RT"));
+        assert_eq!(label_prefix("Depot"), Some("Depot"));
+        assert_eq!(label_prefix("3245"), None);
+        assert_eq!(label_prefix(""), None);
+    }
+
+    #[test]
+    fn flags_duplicate_and_malformed_descriptors() {
+        let combined = snapshot(json!([
+            position("e1", json!({"id": "v1", "label": "NB 3245", "license_plate": "ABC123"})),
+            position("e2", json!({"id": "v2", "label": "NB 3246", "license_plate": "ABC123"})),
+            position("e3", json!({"id": "v1", "label": "  RT 10 "})),
+            position("e4", json!({"label": "Depot"})),
+            position("e5", json!({"id": "v5", "label": " "})),
+            position("e6", Value::Null),
+            position("e7", json!({"id": "v7", "label": "1234"})),
+            {"id": "e8", "trip_update": {"trip": {"trip_id": "t1"}}},
+        ]));
+        let stats = fleet_stats(&combined);
+
+        assert_eq!(stats.active, 7);
+        let by_prefix: Vec<_> = stats
+            .by_prefix
+            .iter()
+            .map(|(prefix, count)| (prefix.as_str(), *count))
+            .collect();
+        assert_eq!(by_prefix, [("Depot", 1), ("NB", 2), ("RT", 1)]);
+        assert_eq!(stats.unprefixed, 3);
+
+        let duplicates: Vec<_> = stats
+            .duplicates
+            .iter()
+            .map(|d| (d.field, d.value.as_str(), d.entity_ids.clone()))
+            .collect();
+        assert_eq!(
+            duplicates,
+            [
+                (
+                    DescriptorField::Id,
+                    "v1",
+                    vec!["e1".to_string(), "e3".into()]
+                ),
+                (
+                    DescriptorField::LicensePlate,
+                    "ABC123",
+                    vec!["e1".to_string(), "e2".into()]
+                ),
+            ]
+        );
+
+        let malformed: Vec<_> = stats
+            .malformed
+            .iter()
+            .map(|m| (m.entity_id.as_str(), m.issue))
+            .collect();
+        assert_eq!(
+            malformed,
+            [
+                ("e4", DescriptorIssue::MissingId),
+                ("e4", DescriptorIssue::MalformedLabel),
+                ("e5", DescriptorIssue::MissingLabel),
+                ("e6", DescriptorIssue::Missing),
+            ]
+        );
+    }
+}
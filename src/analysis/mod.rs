@@ -0,0 +1,36 @@
+//! Analysis of vehicle behaviour across realtime snapshots.
+//!
+//! The trackers in this module are fed one snapshot at a time, such as from
+//! [`Realtime::stream`], and keep the state they need between snapshots. Their measurements are
+//! only as precise as the interval between snapshots.
+//!
+//! [`Realtime::stream`]: crate::Realtime::stream
+
+pub mod blocks;
+pub mod congestion;
+pub mod dwell;
+pub mod fleet;
+pub mod occupancy;
+pub mod speed;
+
+use crate::types::gtfs::{Entity, VehiclePosition};
+
+/// Returns the vehicle ID and position of each entity with a vehicle position.
+pub(crate) fn vehicles(entities: &[Entity]) -> impl Iterator<Item = (&str, &VehiclePosition)> {
+    entities
+        .iter()
+        .filter_map(|entity| Some((vehicle_id(entity)?, entity.vehicle.as_ref()?)))
+}
+
+/// Returns the vehicle ID of an entity with a vehicle position, using the entity ID for vehicles
+/// without a vehicle descriptor.
+pub(crate) fn vehicle_id(entity: &Entity) -> Option<&str> {
+    let vehicle = entity.vehicle.as_ref()?;
+    Some(
+        vehicle
+            .vehicle
+            .as_ref()
+            .and_then(|v| v.id.as_deref())
+            .unwrap_or(&entity.id),
+    )
+}
@@ -0,0 +1,347 @@
+//! Forecasts of how full a departure will be, from the occupancy recorded in past snapshots.
+//!
+//! An [`OccupancyModel`] counts the occupancy AT reported for vehicles approaching each stop, by
+//! route, stop and hour of the day. Feed it a recorded history, such as the stream of a
+//! [`Replayer`], and it forecasts the likely occupancy of an upcoming [`Arrival`] as the status
+//! reported most often in the same circumstances.
+//!
+//! Routes and stops are keyed by their ID with the GTFS version truncated, so history carries
+//! over when a new version of the schedule is published.
+//!
+//! [`Replayer`]: crate::recorder::Replayer
+//! [`Arrival`]: crate::arrivals::Arrival
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    analysis::vehicle_id,
+    arrivals::local_hour,
+    intern::Interner,
+    types::gtfs::{OccupancyStatus, VehiclePosition},
+    CombinedResponse,
+};
+
+/// The default number of samples needed before a forecast is made from them.
+const DEFAULT_MIN_SAMPLES: u64 = 5;
+
+/// Every occupancy status, in the order of their values.
+const STATUSES: [OccupancyStatus; 7] = [
+    OccupancyStatus::Empty,
+    OccupancyStatus::ManySeatsAvailable,
+    OccupancyStatus::FewSeatsAvailable,
+    OccupancyStatus::StandingRoomOnly,
+    OccupancyStatus::CrushedStandingRoomOnly,
+    OccupancyStatus::Full,
+    OccupancyStatus::NotAcceptingPassengers,
+];
+
+/// The history a forecast was made from, from the most to the least specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ForecastBasis {
+    /// Vehicles on the route approaching the stop in the same hour of the day.
+    StopAndHour,
+    /// Vehicles on the route anywhere in the same hour of the day.
+    RouteAndHour,
+    /// Vehicles on the route at any time.
+    Route,
+}
+
+/// The forecast occupancy of a departure.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct OccupancyForecast {
+    /// The occupancy reported most often.
+    pub status: OccupancyStatus,
+    /// The share of the samples which reported `status`, from 0 to 1.
+    pub confidence: f64,
+    /// The number of samples the forecast was made from.
+    pub samples: u64,
+    /// The history the forecast was made from.
+    pub basis: ForecastBasis,
+}
+
+/// The number of samples which reported each occupancy status.
+#[derive(Debug, Clone, Copy, Default)]
+struct Histogram([u64; STATUSES.len()]);
+
+impl Histogram {
+    fn record(&mut self, status: OccupancyStatus) {
+        self.0[status as usize] += 1;
+    }
+
+    fn forecast(&self, basis: ForecastBasis, min_samples: u64) -> Option<OccupancyForecast> {
+        let samples: u64 = self.0.iter().sum();
+        if samples == 0 || samples < min_samples {
+            return None;
+        }
+
+        let (index, count) = self
+            .0
+            .iter()
+            .enumerate()
+            .max_by_key(|(index, count)| (**count, std::cmp::Reverse(*index)))?;
+        Some(OccupancyForecast {
+            status: STATUSES[index],
+            confidence: *count as f64 / samples as f64,
+            samples,
+            basis,
+        })
+    }
+}
+
+/// Learns the occupancy of routes from snapshots and forecasts the occupancy of departures.
+#[derive(Debug, Clone)]
+pub struct OccupancyModel {
+    min_samples: u64,
+    interner: Interner,
+    by_stop: HashMap<(Arc<str>, Arc<str>, u8), Histogram>,
+    by_hour: HashMap<(Arc<str>, u8), Histogram>,
+    by_route: HashMap<Arc<str>, Histogram>,
+    /// The trip and stop each vehicle was last counted at, so a vehicle is counted once per stop.
+    last_counted: HashMap<Arc<str>, Counted>,
+}
+
+/// The trip and stop a vehicle was counted at.
+type Counted = (Arc<str>, Option<Arc<str>>);
+
+impl Default for OccupancyModel {
+    fn default() -> Self {
+        Self {
+            min_samples: DEFAULT_MIN_SAMPLES,
+            interner: Interner::new(),
+            by_stop: HashMap::new(),
+            by_hour: HashMap::new(),
+            by_route: HashMap::new(),
+            last_counted: HashMap::new(),
+        }
+    }
+}
+
+impl OccupancyModel {
+    /// Creates a model which has not observed a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of samples needed before a forecast is made from them. If there are too
+    /// few samples for the stop and hour, the forecast falls back to less specific history.
+    /// Defaults to 5.
+    ///
+    /// # Parameters
+    ///
+    /// * `min_samples` - The least number of samples.
+    pub fn min_samples(mut self, min_samples: u64) -> Self {
+        self.min_samples = min_samples;
+        self
+    }
+
+    /// Sets the pool the vehicle, trip, route and stop IDs kept by the model are interned in, so
+    /// several trackers can share one copy of each ID. By default the model has a pool of its
+    /// own.
+    ///
+    /// # Parameters
+    ///
+    /// * `interner` - The pool to intern IDs in.
+    pub fn interner(mut self, interner: Interner) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    /// Observes a snapshot, counting the occupancy of each vehicle which reports one. A vehicle
+    /// is counted once for each stop it approaches, however many snapshots it is seen in, and
+    /// vehicles which do not report a stop only count towards the history of their route.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to observe.
+    pub fn observe(&mut self, combined: &CombinedResponse) {
+        let entities = combined.entities.iter().chain(combined.unmatched.iter());
+        for entity in entities {
+            let (vehicle_id, vehicle) = match (vehicle_id(entity), &entity.vehicle) {
+                (Some(id), Some(vehicle)) => (id, vehicle),
+                _ => continue,
+            };
+            if let Some(sample) = Sample::new(vehicle) {
+                let counted = (
+                    self.interner.intern(sample.trip_id),
+                    sample.stop_id.map(|id| self.interner.intern(id)),
+                );
+                if self.last_counted.get(vehicle_id) == Some(&counted) {
+                    continue;
+                }
+                self.last_counted
+                    .insert(self.interner.intern(vehicle_id), counted);
+                self.record(&sample);
+            }
+        }
+        self.interner.shrink();
+    }
+
+    /// Forecasts the occupancy of a departure.
+    ///
+    /// # Parameters
+    ///
+    /// * `route_id` - The ID of the route, with or without the GTFS version.
+    /// * `stop_id` - The ID of the stop, with or without the GTFS version.
+    /// * `timestamp` - The UNIX timestamp of the departure.
+    ///
+    /// # Returns
+    ///
+    /// Returns the forecast from the most specific history with enough samples, or [`None`] if
+    /// there are too few samples of the route.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn forecast(
+        &self,
+        route_id: &str,
+        stop_id: &str,
+        timestamp: i64,
+    ) -> Option<OccupancyForecast> {
+        // IDs which have not been seen are interned, but dropped again by the next snapshot.
+        let route_id = self.interner.intern(truncate(route_id));
+        let stop_id = self.interner.intern(truncate(stop_id));
+        let hour = local_hour(timestamp);
+
+        let by_stop = self
+            .by_stop
+            .get(&(route_id.clone(), stop_id, hour))
+            .and_then(|h| h.forecast(ForecastBasis::StopAndHour, self.min_samples));
+        by_stop
+            .or_else(|| {
+                self.by_hour
+                    .get(&(route_id.clone(), hour))
+                    .and_then(|h| h.forecast(ForecastBasis::RouteAndHour, self.min_samples))
+            })
+            .or_else(|| {
+                self.by_route
+                    .get(&route_id)
+                    .and_then(|h| h.forecast(ForecastBasis::Route, self.min_samples))
+            })
+    }
+
+    fn record(&mut self, sample: &Sample<'_>) {
+        let route_id = self.interner.intern(truncate(sample.route_id));
+
+        if let Some(stop_id) = sample.stop_id {
+            let stop_id = self.interner.intern(truncate(stop_id));
+            self.by_stop
+                .entry((route_id.clone(), stop_id, sample.hour))
+                .or_default()
+                .record(sample.status);
+        }
+        self.by_hour
+            .entry((route_id.clone(), sample.hour))
+            .or_default()
+            .record(sample.status);
+        self.by_route
+            .entry(route_id)
+            .or_default()
+            .record(sample.status);
+    }
+}
+
+/// The occupancy of a vehicle approaching a stop.
+struct Sample<'a> {
+    trip_id: &'a str,
+    route_id: &'a str,
+    stop_id: Option<&'a str>,
+    hour: u8,
+    status: OccupancyStatus,
+}
+
+impl<'a> Sample<'a> {
+    fn new(vehicle: &'a VehiclePosition) -> Option<Self> {
+        let trip = vehicle.trip.as_ref()?;
+        Some(Self {
+            trip_id: trip.trip_id.as_deref()?,
+            route_id: trip.route_id.as_deref()?,
+            stop_id: vehicle.stop_id.as_deref(),
+            hour: local_hour(vehicle.timestamp? as i64),
+            status: vehicle.occupancy_status?,
+        })
+    }
+}
+
+/// Returns an ID with the GTFS version truncated.
+fn truncate(id: &str) -> &str {
+    id.split('-').next().unwrap_or(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::combined::tests::unmatched;
+
+    /// 13:00 in Auckland.
+    const ONE_PM: i64 = 1_705_276_800;
+
+    fn vehicle(vehicle_id: &str, trip_id: &str, stop_id: &str, occupancy: u8) -> Value {
+        json!({
+            "id": vehicle_id,
+            "vehicle": {
+                "trip": {"trip_id": trip_id, "route_id": "r1-v1"},
+                "vehicle": {"id": vehicle_id},
+                "stop_id": stop_id,
+                "occupancy_status": occupancy,
+                "timestamp": ONE_PM
+            }
+        })
+    }
+
+    fn model() -> OccupancyModel {
+        let mut model = OccupancyModel::new().min_samples(2);
+        let snapshot = unmatched(json!([
+            vehicle("v1", "t1", "s1-v1", 1),
+            vehicle("v2", "t2", "s1-v1", 1),
+            vehicle("v3", "t3", "s2-v1", 2),
+        ]));
+        // Vehicles which are still approaching the same stop are not counted again.
+        model.observe(&snapshot);
+        model.observe(&snapshot);
+        model
+    }
+
+    #[test]
+    fn falls_back_to_less_specific_history() {
+        let model = model();
+
+        let forecast = model.forecast("r1-v2", "s1-v2", ONE_PM).unwrap();
+        assert_eq!(forecast.basis, ForecastBasis::StopAndHour);
+        assert_eq!(forecast.status as u8, 1);
+        assert_eq!((forecast.samples, forecast.confidence), (2, 1.0));
+
+        // Only one vehicle approached s2, which is too few samples.
+        let forecast = model.forecast("r1", "s2", ONE_PM + 1800).unwrap();
+        assert_eq!(forecast.basis, ForecastBasis::RouteAndHour);
+        assert_eq!(forecast.status as u8, 1);
+        assert_eq!(forecast.samples, 3);
+        assert!((forecast.confidence - 2.0 / 3.0).abs() < 1e-9);
+
+        let forecast = model.forecast("r1", "s1", ONE_PM + 7 * 3600).unwrap();
+        assert_eq!(forecast.basis, ForecastBasis::Route);
+        assert_eq!(forecast.samples, 3);
+
+        assert!(model.forecast("r2", "s1", ONE_PM).is_none());
+        assert!(model
+            .clone()
+            .min_samples(4)
+            .forecast("r1", "s1", ONE_PM)
+            .is_none());
+    }
+
+    #[test]
+    fn counts_vehicles_again_at_the_next_stop() {
+        let mut model = model();
+        model.observe(&unmatched(json!([vehicle("v1", "t1", "s2-v1", 1)])));
+
+        let forecast = model.forecast("r1", "s2", ONE_PM).unwrap();
+        assert_eq!(forecast.basis, ForecastBasis::StopAndHour);
+        assert_eq!(forecast.samples, 2);
+        // Ties go to the emptier status.
+        assert_eq!(forecast.status as u8, 1);
+        assert_eq!(forecast.confidence, 0.5);
+    }
+}
@@ -0,0 +1,416 @@
+//! Speeds of vehicles between stops.
+//!
+//! A [`SpeedTracker`] compares the position of each vehicle with its previous position and
+//! derives its speed over the time between. The distance travelled is taken from the odometer
+//! where AT reports one, and from the distance between the two positions otherwise.
+//!
+//! GPS fixes are noisy, so speeds are smoothed with an exponential moving average, and a sample
+//! implying an implausible speed is treated as a jump in the fix and discarded. Samples are
+//! gathered into statistics per [`Segment`], the stretch of road between two consecutive stops
+//! of a vehicle. Time spent standing at a stop is left out, see [`DwellTracker`] for that.
+//!
+//! [`DwellTracker`]: crate::analysis::dwell::DwellTracker
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::{
+    analysis::vehicles,
+    geo::distance,
+    intern::Interner,
+    types::gtfs::{CongestionLevel, VehiclePosition, VehicleStopStatus},
+    CombinedResponse,
+};
+
+/// The default weight given to a new sample when smoothing speeds.
+const DEFAULT_SMOOTHING: f64 = 0.5;
+
+/// The default speed above which a sample is discarded, in metres per second (about 110 km/h).
+const DEFAULT_MAX_SPEED: f64 = 30.0;
+
+/// The stretch of road between two consecutive stops of a vehicle.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Segment {
+    /// The full ID of the stop the vehicle left.
+    pub from_stop_id: String,
+    /// The full ID of the stop the vehicle is heading to.
+    pub to_stop_id: String,
+}
+
+/// The speed of a vehicle between two consecutive positions.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SpeedSample {
+    /// The ID of the vehicle.
+    pub vehicle_id: String,
+    /// The full ID of the trip the vehicle is serving, if any.
+    pub trip_id: Option<String>,
+    /// The full ID of the route the vehicle is serving, if any.
+    pub route_id: Option<String>,
+    /// The segment the vehicle is travelling along, or [`None`] until the vehicle has been seen
+    /// heading to two different stops.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub segment: Option<Segment>,
+    /// The UNIX timestamp of the later position.
+    pub timestamp: u64,
+    /// The latitude of the later position.
+    pub latitude: f32,
+    /// The longitude of the later position.
+    pub longitude: f32,
+    /// The congestion AT reported at the later position, if any.
+    pub congestion_level: Option<CongestionLevel>,
+    /// The distance travelled between the two positions, in metres.
+    pub distance: f64,
+    /// The time between the two positions.
+    pub elapsed: Duration,
+    /// The speed between the two positions, in metres per second.
+    pub instantaneous: f64,
+    /// The smoothed speed of the vehicle, in metres per second.
+    pub smoothed: f64,
+}
+
+/// Statistics of the speeds measured along a segment.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SegmentSpeed {
+    /// The number of samples measured.
+    pub samples: u64,
+    /// The total distance travelled, in metres.
+    pub distance: f64,
+    /// The total time spent travelling.
+    pub elapsed: Duration,
+}
+
+impl SegmentSpeed {
+    /// Returns the average speed along the segment in metres per second, weighted by the time
+    /// each sample covers.
+    pub fn average(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed > 0.0 {
+            self.distance / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    pub(crate) fn record(&mut self, sample: &SpeedSample) {
+        self.samples += 1;
+        self.distance += sample.distance;
+        self.elapsed += sample.elapsed;
+    }
+}
+
+/// The last known state of a vehicle.
+#[derive(Debug, Clone)]
+struct Fix {
+    timestamp: u64,
+    point: (f32, f32),
+    odometer: Option<f64>,
+    stopped: bool,
+    /// The stop the vehicle is at or heading to.
+    stop_id: Option<Arc<str>>,
+    /// The stop before `stop_id`.
+    previous_stop_id: Option<Arc<str>>,
+    smoothed: Option<f64>,
+}
+
+/// Tracks vehicle positions across snapshots and measures their speeds.
+///
+/// A vehicle which leaves the feed is forgotten, and its speed is measured afresh if it returns.
+#[derive(Debug, Clone)]
+pub struct SpeedTracker {
+    smoothing: f64,
+    max_speed: f64,
+    interner: Interner,
+    fixes: HashMap<Arc<str>, Fix>,
+    segments: HashMap<Segment, SegmentSpeed>,
+}
+
+impl Default for SpeedTracker {
+    fn default() -> Self {
+        Self {
+            smoothing: DEFAULT_SMOOTHING,
+            max_speed: DEFAULT_MAX_SPEED,
+            interner: Interner::new(),
+            fixes: HashMap::new(),
+            segments: HashMap::new(),
+        }
+    }
+}
+
+impl SpeedTracker {
+    /// Creates a tracker which has not observed a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the weight given to a new sample when smoothing speeds, between 0 and 1. A higher
+    /// weight follows changes in speed more closely but smooths out less noise. Defaults to 0.5.
+    ///
+    /// # Parameters
+    ///
+    /// * `smoothing` - The weight of a new sample.
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the speed above which a sample is treated as a jump in the GPS fix and discarded, in
+    /// metres per second. Defaults to 30 m/s.
+    ///
+    /// # Parameters
+    ///
+    /// * `max_speed` - The highest plausible speed of a vehicle.
+    pub fn max_speed(mut self, max_speed: f64) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Sets the pool the vehicle and stop IDs kept between snapshots are interned in, so several
+    /// trackers can share one copy of each ID. By default the tracker has a pool of its own.
+    ///
+    /// # Parameters
+    ///
+    /// * `interner` - The pool to intern IDs in.
+    pub fn interner(mut self, interner: Interner) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    /// Observes a snapshot, returning a sample for each vehicle which has moved on to a new
+    /// position since the last snapshot. Vehicle positions without a timestamp are ignored, and
+    /// the vehicle is measured from its last known position instead.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to observe.
+    pub fn observe(&mut self, combined: &CombinedResponse) -> Vec<SpeedSample> {
+        let mut samples = vec![];
+        let mut fixes = HashMap::new();
+
+        let positions = vehicles(&combined.entities).chain(vehicles(&combined.unmatched));
+        for (vehicle_id, vehicle) in positions {
+            let key = self.interner.intern(vehicle_id);
+            let previous = self.fixes.remove(vehicle_id);
+            let mut fix = match self.fix(vehicle) {
+                Some(fix) => fix,
+                None => {
+                    // Keep the last known state, so the vehicle is measured from it next time.
+                    if let Some(previous) = previous {
+                        fixes.insert(key, previous);
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(previous) = previous {
+                if fix.timestamp <= previous.timestamp {
+                    fixes.insert(key, previous);
+                    continue;
+                }
+
+                fix.previous_stop_id = if fix.stop_id == previous.stop_id {
+                    previous.previous_stop_id.clone()
+                } else {
+                    previous.stop_id.clone()
+                };
+                fix.smoothed = previous.smoothed;
+
+                if let Some(sample) = self.sample(vehicle_id, vehicle, &previous, &mut fix) {
+                    samples.push(sample);
+                }
+            }
+
+            fixes.insert(key, fix);
+        }
+
+        self.fixes = fixes;
+        self.interner.shrink();
+        samples
+    }
+
+    /// Returns the speed statistics of a segment, or [`None`] if no sample has been measured
+    /// along it.
+    ///
+    /// # Parameters
+    ///
+    /// * `segment` - The segment.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn segment_speed(&self, segment: &Segment) -> Option<&SegmentSpeed> {
+        self.segments.get(segment)
+    }
+
+    /// Returns the speed statistics of every segment a sample has been measured along.
+    pub fn segment_speeds(&self) -> impl Iterator<Item = (&Segment, &SegmentSpeed)> {
+        self.segments.iter()
+    }
+
+    /// Returns the state of a vehicle from its position, without its history.
+    fn fix(&self, vehicle: &VehiclePosition) -> Option<Fix> {
+        let position = vehicle.position.as_ref()?;
+        Some(Fix {
+            timestamp: vehicle.timestamp?,
+            point: (position.latitude, position.longitude),
+            odometer: position.odometer,
+            stopped: matches!(vehicle.current_status, VehicleStopStatus::StoppedAt),
+            stop_id: vehicle
+                .stop_id
+                .as_deref()
+                .map(|id| self.interner.intern(id)),
+            previous_stop_id: None,
+            smoothed: None,
+        })
+    }
+
+    /// Measures the speed of a vehicle between two fixes, updating the smoothed speed of the
+    /// later fix and the statistics of its segment.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] if the sample was discarded.
+    ///
+    /// [`None`]: std::option::Option::None
+    fn sample(
+        &mut self,
+        vehicle_id: &str,
+        vehicle: &VehiclePosition,
+        previous: &Fix,
+        fix: &mut Fix,
+    ) -> Option<SpeedSample> {
+        // A vehicle standing at the same stop is dwelling rather than travelling.
+        if previous.stopped && fix.stopped && previous.stop_id == fix.stop_id {
+            return None;
+        }
+
+        let elapsed = Duration::from_secs(fix.timestamp - previous.timestamp);
+        let distance = match (previous.odometer, fix.odometer) {
+            (Some(from), Some(to)) if to >= from => to - from,
+            _ => distance(previous.point, fix.point),
+        };
+        let instantaneous = distance / elapsed.as_secs_f64();
+        if instantaneous > self.max_speed {
+            return None;
+        }
+
+        let smoothed = match fix.smoothed {
+            Some(smoothed) => self.smoothing * instantaneous + (1.0 - self.smoothing) * smoothed,
+            None => instantaneous,
+        };
+        fix.smoothed = Some(smoothed);
+
+        let segment = match (&fix.previous_stop_id, &fix.stop_id) {
+            (Some(from), Some(to)) => Some(Segment {
+                from_stop_id: from.to_string(),
+                to_stop_id: to.to_string(),
+            }),
+            _ => None,
+        };
+
+        let trip = vehicle.trip.as_ref();
+        let sample = SpeedSample {
+            vehicle_id: vehicle_id.to_string(),
+            trip_id: trip.and_then(|t| t.trip_id.clone()),
+            route_id: trip.and_then(|t| t.route_id.clone()),
+            segment,
+            timestamp: fix.timestamp,
+            latitude: fix.point.0,
+            longitude: fix.point.1,
+            congestion_level: vehicle.congestion_level,
+            distance,
+            elapsed,
+            instantaneous,
+            smoothed,
+        };
+        if let Some(segment) = &sample.segment {
+            self.segments
+                .entry(segment.clone())
+                .or_default()
+                .record(&sample);
+        }
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::combined::tests::unmatched;
+
+    fn position(timestamp: u64, latitude: f32, odometer: f64, stop_id: &str) -> CombinedResponse {
+        unmatched(json!([{
+            "id": "e1",
+            "vehicle": {
+                "trip": {"trip_id": "t1", "route_id": "r1"},
+                "vehicle": {"id": "v1"},
+                "position": {"latitude": latitude, "longitude": 174.76, "odometer": odometer},
+                "stop_id": stop_id,
+                "current_status": 2,
+                "timestamp": timestamp
+            }
+        }]))
+    }
+
+    fn segment(from: &str, to: &str) -> Segment {
+        Segment {
+            from_stop_id: from.into(),
+            to_stop_id: to.into(),
+        }
+    }
+
+    #[test]
+    fn measures_smoothed_speeds_along_segments() {
+        let mut tracker = SpeedTracker::new();
+        assert!(tracker.observe(&position(0, -36.85, 0.0, "s1")).is_empty());
+
+        let samples = tracker.observe(&position(10, -36.85, 100.0, "s1"));
+        let sample = &samples[0];
+        assert_eq!((sample.instantaneous, sample.smoothed), (10.0, 10.0));
+        assert_eq!(sample.segment, None);
+        assert_eq!(sample.route_id.as_deref(), Some("r1"));
+
+        let samples = tracker.observe(&position(20, -36.85, 300.0, "s2"));
+        let sample = &samples[0];
+        assert_eq!((sample.instantaneous, sample.smoothed), (20.0, 15.0));
+        assert_eq!(sample.segment, Some(segment("s1", "s2")));
+
+        // A jump of a kilometre in ten seconds is discarded, keeping the smoothed speed.
+        assert!(tracker
+            .observe(&position(30, -36.85, 1300.0, "s2"))
+            .is_empty());
+
+        // A reset odometer falls back to the distance between the positions.
+        let samples = tracker.observe(&position(40, -36.851, 0.0, "s2"));
+        let sample = &samples[0];
+        let geodesic = distance((-36.85, 174.76), (-36.851, 174.76));
+        assert!((100.0..120.0).contains(&geodesic));
+        assert_eq!(sample.distance, geodesic);
+        assert_eq!(sample.instantaneous, geodesic / 10.0);
+        assert_eq!(sample.smoothed, 0.5 * geodesic / 10.0 + 0.5 * 15.0);
+
+        let speed = tracker.segment_speed(&segment("s1", "s2")).unwrap();
+        assert_eq!(speed.samples, 2);
+        assert_eq!(speed.elapsed, Duration::from_secs(20));
+        assert_eq!(speed.average(), (200.0 + geodesic) / 20.0);
+        assert!(tracker.segment_speed(&segment("s2", "s1")).is_none());
+    }
+
+    #[test]
+    fn ignores_positions_which_are_not_newer() {
+        let mut tracker = SpeedTracker::new().smoothing(1.0).max_speed(50.0);
+        tracker.observe(&position(10, -36.85, 0.0, "s1"));
+        assert!(tracker
+            .observe(&position(10, -36.85, 50.0, "s1"))
+            .is_empty());
+        let samples = tracker.observe(&position(20, -36.85, 400.0, "s1"));
+        assert_eq!(samples[0].instantaneous, 40.0);
+
+        // A position without a timestamp keeps the last known state.
+        let mut untimed = position(25, -36.85, 500.0, "s1");
+        untimed.unmatched[0].vehicle.as_mut().unwrap().timestamp = None;
+        assert!(tracker.observe(&untimed).is_empty());
+        let samples = tracker.observe(&position(30, -36.85, 700.0, "s1"));
+        assert_eq!(samples[0].elapsed, Duration::from_secs(10));
+    }
+}
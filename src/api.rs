@@ -0,0 +1,124 @@
+//! A trait over the realtime fetch methods, for injecting test doubles.
+//!
+//! Services which take a [`RealtimeApi`] trait object, such as `Arc<dyn RealtimeApi>`, can be
+//! given the real [`Realtime`] client in production and a [`Simulator`] or a hand-written fake in
+//! tests, without making every type which holds the client generic.
+//!
+//! [`Realtime`]: crate::Realtime
+//! [`Simulator`]: crate::simulator::Simulator
+
+use std::{future::Future, pin::Pin};
+
+use crate::{
+    error::Result, simulator::Simulator, CombinedResponse, FetchOutcome, Ids, Realtime,
+    RequestOptions,
+};
+
+/// The future returned by the methods of [`RealtimeApi`].
+pub type ApiFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// The fetch methods of the realtime API. The trait is object safe, so it can be used as
+/// `dyn RealtimeApi`.
+pub trait RealtimeApi: Send + Sync {
+    /// Fetches both trip updates and vehicle positions. See [`Realtime::fetch_combined`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+    fn fetch_combined(&self, trip_ids: Ids, vehicle_ids: Ids) -> ApiFuture<'_, CombinedResponse>;
+
+    /// Fetches both trip updates and vehicle positions, overriding the client configuration for
+    /// this call. See [`Realtime::fetch_combined_with_options`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    /// * `options` - The options to override for this call.
+    ///
+    /// [`Realtime::fetch_combined_with_options`]: crate::Realtime::fetch_combined_with_options
+    fn fetch_combined_with_options<'a>(
+        &'a self,
+        trip_ids: Ids,
+        vehicle_ids: Ids,
+        options: &'a RequestOptions,
+    ) -> ApiFuture<'a, CombinedResponse>;
+
+    /// Fetches both trip updates and vehicle positions if they have changed since the previous
+    /// call. See [`Realtime::fetch_combined_if_modified`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// [`Realtime::fetch_combined_if_modified`]: crate::Realtime::fetch_combined_if_modified
+    fn fetch_combined_if_modified(
+        &self,
+        trip_ids: Ids,
+        vehicle_ids: Ids,
+    ) -> ApiFuture<'_, FetchOutcome<CombinedResponse>>;
+}
+
+impl RealtimeApi for Realtime {
+    fn fetch_combined(&self, trip_ids: Ids, vehicle_ids: Ids) -> ApiFuture<'_, CombinedResponse> {
+        Box::pin(Realtime::fetch_combined(self, trip_ids, vehicle_ids))
+    }
+
+    fn fetch_combined_with_options<'a>(
+        &'a self,
+        trip_ids: Ids,
+        vehicle_ids: Ids,
+        options: &'a RequestOptions,
+    ) -> ApiFuture<'a, CombinedResponse> {
+        Box::pin(Realtime::fetch_combined_with_options(
+            self,
+            trip_ids,
+            vehicle_ids,
+            options,
+        ))
+    }
+
+    fn fetch_combined_if_modified(
+        &self,
+        trip_ids: Ids,
+        vehicle_ids: Ids,
+    ) -> ApiFuture<'_, FetchOutcome<CombinedResponse>> {
+        Box::pin(Realtime::fetch_combined_if_modified(
+            self,
+            trip_ids,
+            vehicle_ids,
+        ))
+    }
+}
+
+/// The simulator ignores request options, and every snapshot is reported as updated.
+impl RealtimeApi for Simulator {
+    fn fetch_combined(&self, trip_ids: Ids, vehicle_ids: Ids) -> ApiFuture<'_, CombinedResponse> {
+        Box::pin(Simulator::fetch_combined(self, trip_ids, vehicle_ids))
+    }
+
+    fn fetch_combined_with_options<'a>(
+        &'a self,
+        trip_ids: Ids,
+        vehicle_ids: Ids,
+        _options: &'a RequestOptions,
+    ) -> ApiFuture<'a, CombinedResponse> {
+        Box::pin(Simulator::fetch_combined(self, trip_ids, vehicle_ids))
+    }
+
+    fn fetch_combined_if_modified(
+        &self,
+        trip_ids: Ids,
+        vehicle_ids: Ids,
+    ) -> ApiFuture<'_, FetchOutcome<CombinedResponse>> {
+        Box::pin(async move {
+            Simulator::fetch_combined(self, trip_ids, vehicle_ids)
+                .await
+                .map(FetchOutcome::Updated)
+        })
+    }
+}
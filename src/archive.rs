@@ -0,0 +1,414 @@
+//! Queries over the responses written by a [`Recorder`].
+//!
+//! An [`Archive`] answers historical questions about a recorded feed, such as where the vehicles
+//! on a route were during a morning or how the delay of a trip changed along its journey, with
+//! typed results rather than raw snapshots. Only the recordings in the time range of a query are
+//! read, using the time each recording was made.
+//!
+//! [`Recorder`]: crate::recorder::Recorder
+
+use std::{
+    ops::{Bound, RangeBounds},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    arrivals::{parse_date, service_day_start},
+    error::Result,
+    recorder::{Recording, Replayer},
+    types::gtfs::{Entity, Position, TripDescriptor},
+    CombinedResponse,
+};
+
+/// The longest a service day runs for, since trips after midnight belong to the previous day.
+const SERVICE_DAY_LENGTH: Duration = Duration::from_secs(30 * 60 * 60);
+
+/// A vehicle on a route seen in a recorded snapshot.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct VehicleSighting {
+    /// When the snapshot was recorded.
+    pub recorded_at: SystemTime,
+    /// The ID of the vehicle, or the entity ID if AT did not report one.
+    pub vehicle_id: String,
+    /// The full ID of the trip the vehicle was serving.
+    pub trip_id: Option<String>,
+    /// The position of the vehicle.
+    pub position: Option<Position>,
+    /// The UNIX timestamp of the position.
+    pub timestamp: Option<u64>,
+}
+
+/// The delay of a trip reported in a recorded snapshot.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DelayObservation {
+    /// When the snapshot was recorded.
+    pub recorded_at: SystemTime,
+    /// The delay of the trip, in seconds.
+    pub delay: i32,
+    /// The stop the delay was reported at, if any.
+    pub stop_id: Option<String>,
+    /// The position of that stop in the trip, if any.
+    pub stop_sequence: Option<u32>,
+}
+
+/// A directory of recorded responses which can be queried.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    /// The recordings, with the time each was recorded, in the order they were recorded.
+    recordings: Vec<(SystemTime, Recording)>,
+}
+
+impl Archive {
+    /// Opens the recordings in the given directory.
+    ///
+    /// # Parameters
+    ///
+    /// * `dir` - The directory the recordings were written to.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let replayer = Replayer::open(dir)?;
+        let recordings = replayer
+            .recordings()
+            .iter()
+            .filter_map(|recording| Some((recording.recorded_at?, recording.clone())))
+            .collect();
+
+        Ok(Self { recordings })
+    }
+
+    /// Returns the number of recorded snapshots.
+    pub fn len(&self) -> usize {
+        self.recordings.len()
+    }
+
+    /// Returns true if there are no recorded snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.recordings.is_empty()
+    }
+
+    /// Returns when the first and last snapshots were recorded, or [`None`] if there are none.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn time_range(&self) -> Option<(SystemTime, SystemTime)> {
+        Some((self.recordings.first()?.0, self.recordings.last()?.0))
+    }
+
+    /// Returns the snapshots recorded within a time range, in the order they were recorded.
+    ///
+    /// # Parameters
+    ///
+    /// * `range` - The times the snapshots were recorded between.
+    pub fn snapshots<R: RangeBounds<SystemTime>>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = Result<CombinedResponse>> + '_ {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.recordings.partition_point(|(at, _)| at < start),
+            Bound::Excluded(start) => self.recordings.partition_point(|(at, _)| at <= start),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.recordings.partition_point(|(at, _)| at <= end),
+            Bound::Excluded(end) => self.recordings.partition_point(|(at, _)| at < end),
+            Bound::Unbounded => self.recordings.len(),
+        };
+
+        self.recordings[start..end.max(start)]
+            .iter()
+            .map(|(_, recording)| recording.read())
+    }
+
+    /// Returns every sighting of a vehicle serving a route within a time range.
+    ///
+    /// # Parameters
+    ///
+    /// * `route_id` - The ID of the route, with or without the GTFS version.
+    /// * `range` - The times the snapshots were recorded between.
+    pub fn vehicles_on<R: RangeBounds<SystemTime>>(
+        &self,
+        route_id: &str,
+        range: R,
+    ) -> Result<Vec<VehicleSighting>> {
+        let mut sightings = vec![];
+        for combined in self.snapshots(range) {
+            let combined = combined?;
+            for entity in all_entities(&combined) {
+                let vehicle = match &entity.vehicle {
+                    Some(vehicle) => vehicle,
+                    None => continue,
+                };
+                let trip = vehicle.trip.as_ref().or_else(|| trip_of(entity));
+                if !trip.is_some_and(|trip| matches_id(trip.route_id.as_deref(), route_id)) {
+                    continue;
+                }
+
+                sightings.push(VehicleSighting {
+                    recorded_at: combined.fetched_at,
+                    vehicle_id: vehicle
+                        .vehicle
+                        .as_ref()
+                        .and_then(|v| v.id.clone())
+                        .unwrap_or_else(|| entity.id.clone()),
+                    trip_id: trip.and_then(|trip| trip.trip_id.clone()),
+                    position: vehicle.position.clone(),
+                    timestamp: vehicle.timestamp,
+                });
+            }
+        }
+
+        Ok(sightings)
+    }
+
+    /// Returns the delays reported for a trip on a service day, in the order they were recorded.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_id` - The ID of the trip, with or without the GTFS version.
+    /// * `date` - The service day of the trip, in the form `YYYYMMDD`.
+    pub fn delays(&self, trip_id: &str, date: &str) -> Result<Vec<DelayObservation>> {
+        let start = parse_date(date)
+            .map(service_day_start)
+            .filter(|start| *start >= 0)
+            .map(|start| UNIX_EPOCH + Duration::from_secs(start as u64));
+        let snapshots: Box<dyn Iterator<Item = _>> = match start {
+            Some(start) => Box::new(self.snapshots(start..start + SERVICE_DAY_LENGTH)),
+            None => Box::new(self.snapshots(..)),
+        };
+
+        let mut delays = vec![];
+        for combined in snapshots {
+            let combined = combined?;
+            for entity in all_entities(&combined) {
+                let trip_update = match &entity.trip_update {
+                    Some(trip_update) => trip_update,
+                    None => continue,
+                };
+                let trip = &trip_update.trip;
+                if !matches_id(trip.trip_id.as_deref(), trip_id)
+                    || trip.start_date.as_deref().is_some_and(|d| d != date)
+                {
+                    continue;
+                }
+
+                let stop_time_update = trip_update.stop_time_update.as_ref();
+                let delay = trip_update.delay.or_else(|| {
+                    let update = stop_time_update?;
+                    let event = update.arrival.as_ref().or(update.departure.as_ref())?;
+                    event.delay
+                });
+                if let Some(delay) = delay {
+                    delays.push(DelayObservation {
+                        recorded_at: combined.fetched_at,
+                        delay,
+                        stop_id: stop_time_update.and_then(|u| u.stop_id.clone()),
+                        stop_sequence: stop_time_update.and_then(|u| u.stop_sequence),
+                    });
+                }
+            }
+        }
+
+        Ok(delays)
+    }
+}
+
+fn all_entities(combined: &CombinedResponse) -> impl Iterator<Item = &Entity> {
+    combined.entities.iter().chain(combined.unmatched.iter())
+}
+
+fn trip_of(entity: &Entity) -> Option<&TripDescriptor> {
+    entity
+        .trip_update
+        .as_ref()
+        .map(|trip_update| &trip_update.trip)
+}
+
+/// Returns true if a full ID matches an ID given with or without the GTFS version.
+fn matches_id(full: Option<&str>, id: &str) -> bool {
+    match full {
+        Some(full) => full == id || full.split('-').next() == Some(id),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    /// The start of the service day of 15 January 2024.
+    fn day() -> SystemTime {
+        let start = service_day_start(parse_date("20240115").unwrap());
+        UNIX_EPOCH + Duration::from_secs(start as u64)
+    }
+
+    /// Writes a recording of a v3 response to a directory, as recorded at the given time.
+    fn record(dir: &Path, at: SystemTime, entities: Value) {
+        let millis = at.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let body = json!({
+            "header": {"gtfs_realtime_version": "2.0", "timestamp": 1},
+            "entity": entities
+        });
+        let name = format!("{:020}-000000-v3.json", millis);
+        fs::write(dir.join(name), body.to_string()).unwrap();
+    }
+
+    fn trip_update(trip_id: &str, start_date: Option<&str>, update: Value) -> Value {
+        let mut trip = json!({"trip_id": trip_id, "route_id": "82-v1"});
+        if let Some(start_date) = start_date {
+            trip["start_date"] = start_date.into();
+        }
+        let mut trip_update = update;
+        trip_update["trip"] = trip;
+        let id = format!("{}-{}", trip_id, start_date.unwrap_or("?"));
+        json!({"id": id, "trip_update": trip_update})
+    }
+
+    fn vehicle(vehicle_id: &str, route_id: &str) -> Value {
+        json!({
+            "id": vehicle_id,
+            "vehicle": {
+                "trip": {"trip_id": format!("trip_{}", vehicle_id), "route_id": route_id},
+                "vehicle": {"id": vehicle_id},
+                "position": {"latitude": -36.85, "longitude": 174.76},
+                "timestamp": 1
+            }
+        })
+    }
+
+    fn archive(name: &str) -> (PathBuf, Archive) {
+        let dir = std::env::temp_dir().join(format!("at-api-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let day = day();
+        record(
+            &dir,
+            day - HOUR,
+            json!([
+                trip_update("t1-v1", Some("20240114"), json!({"delay": 10})),
+                vehicle("v1", "82-v1"),
+            ]),
+        );
+        record(
+            &dir,
+            day + HOUR,
+            json!([
+                trip_update("t1-v1", Some("20240115"), json!({"delay": 60})),
+                trip_update("t1-v1", Some("20240116"), json!({"delay": 999})),
+                vehicle("v1", "82-v1"),
+                vehicle("v2", "820-v1"),
+                // A vehicle without a trip is matched by the trip of its trip update.
+                {
+                    "id": "v3",
+                    "trip_update": {"trip": {"trip_id": "t3-v1", "route_id": "82-v1"}},
+                    "vehicle": {"vehicle": {"id": "v3"}}
+                },
+            ]),
+        );
+        record(
+            &dir,
+            day + 2 * HOUR,
+            json!([trip_update(
+                "t1-v1",
+                None,
+                json!({
+                    "stop_time_update": {
+                        "stop_id": "s2",
+                        "stop_sequence": 2,
+                        "arrival": {"delay": 120}
+                    }
+                })
+            )]),
+        );
+        record(
+            &dir,
+            day + 31 * HOUR,
+            json!([trip_update(
+                "t1-v1",
+                Some("20240115"),
+                json!({"delay": 300})
+            )]),
+        );
+
+        let archive = Archive::open(&dir).unwrap();
+        (dir, archive)
+    }
+
+    fn vehicle_ids(sightings: &[VehicleSighting]) -> Vec<&str> {
+        let mut ids: Vec<_> = sightings.iter().map(|s| s.vehicle_id.as_str()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn filters_snapshots_by_time() {
+        let (dir, archive) = archive("archive-times");
+        let day = day();
+        assert_eq!(archive.len(), 4);
+        assert_eq!(archive.time_range(), Some((day - HOUR, day + 31 * HOUR)));
+
+        assert_eq!(archive.snapshots(..).count(), 4);
+        assert_eq!(archive.snapshots(day..).count(), 3);
+        assert_eq!(archive.snapshots(day + HOUR..day + 2 * HOUR).count(), 1);
+        assert_eq!(archive.snapshots(day + HOUR..=day + 2 * HOUR).count(), 2);
+        assert_eq!(archive.snapshots(day + 2 * HOUR..day).count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_vehicles_on_a_route() {
+        let (dir, archive) = archive("archive-vehicles");
+        let day = day();
+
+        let sightings = archive.vehicles_on("82", ..).unwrap();
+        assert_eq!(vehicle_ids(&sightings), ["v1", "v1", "v3"]);
+
+        let sightings = archive.vehicles_on("82-v1", day..).unwrap();
+        assert_eq!(vehicle_ids(&sightings), ["v1", "v3"]);
+        assert!(sightings.iter().all(|s| s.recorded_at == day + HOUR));
+        let v3 = sightings.iter().find(|s| s.vehicle_id == "v3").unwrap();
+        assert_eq!(v3.trip_id.as_deref(), Some("t3-v1"));
+
+        assert_eq!(
+            vehicle_ids(&archive.vehicles_on("820", ..).unwrap()),
+            ["v2"]
+        );
+        assert!(archive.vehicles_on("8", ..).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_delays_on_a_service_day() {
+        let (dir, archive) = archive("archive-delays");
+        let day = day();
+
+        let delays = archive.delays("t1", "20240115").unwrap();
+        let found: Vec<_> = delays
+            .iter()
+            .map(|d| (d.delay, d.stop_id.as_deref(), d.stop_sequence))
+            .collect();
+        assert_eq!(found, [(60, None, None), (120, Some("s2"), Some(2))]);
+        assert_eq!(delays[0].recorded_at, day + HOUR);
+        assert_eq!(archive.delays("t1-v1", "20240115").unwrap().len(), 2);
+
+        // The day before only has the recording made before the service day started.
+        let delays = archive.delays("t1", "20240114").unwrap();
+        let found: Vec<_> = delays.iter().map(|d| d.delay).collect();
+        assert_eq!(found, [10, 120]);
+
+        // An invalid date searches every recording, and only matches trips without a date.
+        let delays = archive.delays("t1", "bad").unwrap();
+        assert_eq!(delays.iter().map(|d| d.delay).collect::<Vec<_>>(), [120]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
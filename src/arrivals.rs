@@ -0,0 +1,351 @@
+//! Arrivals at a stop across all routes, combining the schedule with the realtime feed.
+//!
+//! [`Realtime::arrivals_at`] works like a passenger information display: it looks up the stop from
+//! the code on its signage, finds the trips serving it in the static schedule, and adjusts their
+//! scheduled arrivals with the delays and cancellations in the realtime feed.
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    analysis::occupancy::{OccupancyForecast, OccupancyModel},
+    error::Result,
+    types::{
+        gtfs::{ScheduleRelationship, ScheduleRelationshipTripDescriptor, TripUpdate},
+        schedule::{Stop, StopTime},
+    },
+    Realtime,
+};
+
+/// Seconds in a day.
+const DAY: i64 = 24 * 60 * 60;
+
+/// An arrival of a trip at a stop.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Arrival {
+    /// The full ID of the trip.
+    pub trip_id: String,
+    /// The full ID of the route of the trip.
+    pub route_id: Option<String>,
+    /// The ID of the vehicle serving the trip, if one has been assigned.
+    pub vehicle_id: Option<String>,
+    /// The stop the trip arrives at.
+    pub stop: Stop,
+    /// The position of the stop in the trip.
+    pub stop_sequence: u32,
+    /// The scheduled arrival time, as a UNIX timestamp in seconds.
+    pub scheduled: i64,
+    /// The expected arrival time with the realtime delay applied, as a UNIX timestamp in seconds.
+    pub expected: i64,
+    /// The delay in seconds reported by the realtime feed, if any.
+    pub delay: Option<i32>,
+    /// Whether the trip will arrive at the stop.
+    pub status: ArrivalStatus,
+}
+
+/// Whether a trip will arrive at a stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArrivalStatus {
+    /// The trip will arrive at the stop at the expected time.
+    Expected,
+    /// The trip has been cancelled.
+    Cancelled,
+    /// The trip is running, but will not stop at the stop.
+    Skipped,
+}
+
+impl Arrival {
+    /// Forecasts how full the trip will be when it arrives at the stop, from the history in an
+    /// occupancy model.
+    ///
+    /// # Parameters
+    ///
+    /// * `model` - The model with the recorded history.
+    ///
+    /// # Returns
+    ///
+    /// Returns the forecast, or [`None`] if the arrival has no route or the model has too few
+    /// samples of the route.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn occupancy_forecast(&self, model: &OccupancyModel) -> Option<OccupancyForecast> {
+        model.forecast(self.route_id.as_deref()?, &self.stop.stop_id, self.expected)
+    }
+}
+
+impl Realtime {
+    /// Returns the upcoming arrivals at a stop across all routes, ordered by their expected
+    /// arrival time.
+    ///
+    /// Only trips which are in the realtime feed are included, which is the case for trips that
+    /// are running or about to start. Arrivals which are expected before now, such as those of
+    /// trips which have already passed the stop, are left out. Cancelled and skipped arrivals are
+    /// included, with their [`status`] set, so they can be shown as such.
+    ///
+    /// This sends a request for the stop, one for the scheduled stops of each GTFS stop with the
+    /// code, and one for the realtime feed.
+    ///
+    /// # Parameters
+    ///
+    /// * `stop_code` - The code shown on the stop's signage, such as `7036`.
+    ///
+    /// [`status`]: Arrival::status
+    pub async fn arrivals_at(&self, stop_code: &str) -> Result<Vec<Arrival>> {
+        let stops = self.fetch_stops_by_code(stop_code.trim()).await?;
+        let combined = self.fetch_combined(None, None).await?;
+        let trips: HashMap<&str, &TripUpdate> = combined
+            .entities
+            .iter()
+            .chain(combined.unmatched.iter())
+            .filter_map(|entity| {
+                let trip_update = entity.trip_update.as_ref()?;
+                Some((trip_update.trip.trip_id.as_deref()?, trip_update))
+            })
+            .collect();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as i64);
+        let today = service_day(now);
+
+        let mut arrivals = vec![];
+        for stop in stops.iter() {
+            for stop_time in self.fetch_stop_times_by_stop_id(&stop.stop_id).await? {
+                let arrival = trips
+                    .get(stop_time.trip_id.as_str())
+                    .and_then(|trip_update| arrival(stop, &stop_time, trip_update, today));
+                arrivals.extend(arrival.filter(|arrival| arrival.expected >= now));
+            }
+        }
+
+        arrivals.sort_by_key(|arrival| arrival.expected);
+        Ok(arrivals)
+    }
+}
+
+/// Combines a scheduled stop with the realtime trip update of its trip.
+///
+/// # Parameters
+///
+/// * `stop` - The stop.
+/// * `stop_time` - The scheduled stop of the trip.
+/// * `trip_update` - The realtime trip update of the trip.
+/// * `today` - The current service day, used if the trip update has no start date.
+///
+/// # Returns
+///
+/// Returns the arrival, or [`None`] if the trip has already passed the stop or the scheduled
+/// time is invalid.
+///
+/// [`None`]: std::option::Option::None
+fn arrival(
+    stop: &Stop,
+    stop_time: &StopTime,
+    trip_update: &TripUpdate,
+    today: i64,
+) -> Option<Arrival> {
+    let time = stop_time
+        .arrival_time
+        .as_deref()
+        .or(stop_time.departure_time.as_deref())?;
+    let day = trip_update
+        .trip
+        .start_date
+        .as_deref()
+        .and_then(parse_date)
+        .unwrap_or(today);
+    let scheduled = service_day_start(day) + parse_time(time)?;
+
+    // AT only sends the update for the stop the vehicle is at or approaching.
+    let update = trip_update.stop_time_update.as_ref();
+    if update
+        .and_then(|update| update.stop_sequence)
+        .is_some_and(|sequence| sequence > stop_time.stop_sequence)
+    {
+        return None;
+    }
+    let update_here = update.filter(|update| {
+        update.stop_sequence == Some(stop_time.stop_sequence)
+            || update.stop_id.as_deref() == Some(stop_time.stop_id.as_str())
+    });
+
+    let event = update.and_then(|update| update.arrival.as_ref().or(update.departure.as_ref()));
+    let delay = event.and_then(|event| event.delay).or(trip_update.delay);
+    let expected = match update_here.and(event).and_then(|event| event.time) {
+        Some(time) => time,
+        None => scheduled + i64::from(delay.unwrap_or(0)),
+    };
+
+    let status = if matches!(
+        trip_update.trip.schedule_relationship,
+        Some(ScheduleRelationshipTripDescriptor::Cancelled)
+    ) {
+        ArrivalStatus::Cancelled
+    } else if update_here
+        .is_some_and(|update| matches!(update.schedule_relationship, ScheduleRelationship::Skipped))
+    {
+        ArrivalStatus::Skipped
+    } else {
+        ArrivalStatus::Expected
+    };
+
+    Some(Arrival {
+        trip_id: stop_time.trip_id.clone(),
+        route_id: trip_update.trip.route_id.clone(),
+        vehicle_id: trip_update
+            .vehicle
+            .as_ref()
+            .and_then(|vehicle| vehicle.id.clone()),
+        stop: stop.clone(),
+        stop_sequence: stop_time.stop_sequence,
+        scheduled,
+        expected,
+        delay,
+        status,
+    })
+}
+
+/// Parses a GTFS date in the form `YYYYMMDD` into days since the UNIX epoch.
+pub(crate) fn parse_date(date: &str) -> Option<i64> {
+    if date.len() != 8 {
+        return None;
+    }
+    let year = date.get(..4)?.parse().ok()?;
+    let month = date.get(4..6)?.parse().ok()?;
+    let day = date.get(6..)?.parse().ok()?;
+    Some(days_from_civil(year, month, day))
+}
+
+/// Parses a GTFS time in the form `HH:MM:SS` into seconds since the start of the service day.
+/// Times after midnight are written with hours of 24 or more.
+fn parse_time(time: &str) -> Option<i64> {
+    let mut parts = time.trim().splitn(3, ':');
+    let mut next = || -> Option<i64> { parts.next()?.parse().ok() };
+    Some(next()? * 3600 + next()? * 60 + next()?)
+}
+
+/// Returns the service day in Auckland at a UNIX timestamp, as days since the UNIX epoch.
+fn service_day(now: i64) -> i64 {
+    (now + utc_offset_at(now)).div_euclid(DAY)
+}
+
+/// Returns the hour of the day in Auckland at a UNIX timestamp.
+pub(crate) fn local_hour(now: i64) -> u8 {
+    ((now + utc_offset_at(now)).rem_euclid(DAY) / 3600) as u8
+}
+
+/// Returns the UTC offset of Auckland at a UNIX timestamp, in seconds. The clocks change at
+/// 14:00 UTC on the Saturday before the Sunday the change is named for, which is midnight of that
+/// Sunday in UTC+10.
+fn utc_offset_at(now: i64) -> i64 {
+    utc_offset((now + 10 * 3600).div_euclid(DAY))
+}
+
+/// Returns the UNIX timestamp at which the times of a service day are measured from, which GTFS
+/// defines as 12 hours before noon in local time.
+pub(crate) fn service_day_start(day: i64) -> i64 {
+    day * DAY - utc_offset(day)
+}
+
+/// Returns the UTC offset of Auckland at noon on a day, in seconds. New Zealand daylight time
+/// runs from the last Sunday of September until the first Sunday of April.
+fn utc_offset(day: i64) -> i64 {
+    let (year, _, _) = civil_from_days(day);
+    let sep_30 = days_from_civil(year, 9, 30);
+    let dst_start = sep_30 - weekday(sep_30);
+    let apr_1 = days_from_civil(year, 4, 1);
+    let dst_end = apr_1 + (7 - weekday(apr_1)) % 7;
+
+    if day >= dst_start || day < dst_end {
+        13 * 3600
+    } else {
+        12 * 3600
+    }
+}
+
+/// Returns the day of the week of a day since the UNIX epoch, where Sunday is 0.
+fn weekday(day: i64) -> i64 {
+    (day + 4).rem_euclid(7)
+}
+
+/// Returns the days since the UNIX epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year, month and day of a day since the UNIX epoch. The inverse of
+/// [`days_from_civil`].
+fn civil_from_days(day: i64) -> (i64, i64, i64) {
+    let day = day + 719_468;
+    let era = day.div_euclid(146_097);
+    let day_of_era = day - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day_of_year - (153 * mp + 2) / 5 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("19700101"), Some(0));
+        assert_eq!(parse_date("20240229"), Some(19782));
+        assert_eq!(parse_date("20240407"), Some(19820));
+        assert_eq!(parse_date("2024047"), None);
+        assert_eq!(parse_date("2024ab07"), None);
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn parses_times_after_midnight() {
+        assert_eq!(parse_time("08:05:30"), Some(8 * 3600 + 5 * 60 + 30));
+        assert_eq!(parse_time(" 25:10:00"), Some(25 * 3600 + 10 * 60));
+        assert_eq!(parse_time("08:05"), None);
+    }
+
+    #[test]
+    fn service_days_start_twelve_hours_before_noon() {
+        // Standard time in winter, and daylight time in summer.
+        assert_eq!(service_day_start(19905), 1_719_748_800);
+        assert_eq!(service_day_start(19737), 1_705_230_000);
+        // The offset at noon applies on the days the clocks change, so the service day starts
+        // at 01:00 local time when daylight time ends and 23:00 the day before when it starts.
+        assert_eq!(service_day_start(19820), 19820 * DAY - 12 * 3600);
+        assert_eq!(service_day_start(19995), 19995 * DAY - 13 * 3600);
+    }
+
+    #[test]
+    fn local_time_follows_daylight_saving() {
+        // 2024-01-15T00:00Z is 13:00 NZDT, and 2024-07-01T00:00Z is 12:00 NZST.
+        assert_eq!(local_hour(1_705_276_800), 13);
+        assert_eq!(local_hour(1_719_792_000), 12);
+
+        // Daylight time ended at 2024-04-06T14:00Z, when 03:00 NZDT became 02:00 NZST.
+        assert_eq!(local_hour(1_712_411_999), 2);
+        assert_eq!(local_hour(1_712_412_000), 2);
+        assert_eq!(local_hour(1_712_415_600), 3);
+        // Daylight time started at 2024-09-28T14:00Z, when 02:00 NZST became 03:00 NZDT.
+        assert_eq!(local_hour(1_727_531_999), 1);
+        assert_eq!(local_hour(1_727_532_000), 3);
+
+        // The service day changes at midnight local time, not UTC.
+        assert_eq!(service_day(1_727_523_000), 19994);
+        assert_eq!(service_day(1_727_526_600), 19995);
+        assert_eq!(service_day(1_712_411_999), 19820);
+    }
+}
@@ -0,0 +1,160 @@
+//! A command line client for the AT API, for checking an API key and for scripting.
+//!
+//! The API key is read from the `AT_API_KEY` environment variable. Run `at-cli help` for the list
+//! of commands.
+
+use std::{env, process, time::Duration};
+
+use at_api_rs::{
+    arrivals::ArrivalStatus, error::Result, recorder::Recorder, types::gtfs::Entity, Realtime,
+};
+use futures_util::StreamExt;
+use tokio::runtime::Builder;
+
+const USAGE: &str = "\
+Usage: at-cli <command> [arguments]
+
+Commands:
+    vehicles                    List every vehicle in the realtime feed
+    trip <trip id>              Show the vehicles serving a trip
+    departures <stop code>      List the upcoming arrivals at a stop
+    record <dir> [interval]     Record the realtime feed to a directory, polling every
+                                interval seconds (30 by default)
+    help                        Show this message
+
+The API key is read from the AT_API_KEY environment variable.";
+
+/// A command parsed from the command line arguments.
+enum Command {
+    Vehicles,
+    Trip(String),
+    Departures(String),
+    Record { dir: String, interval: Duration },
+    Help,
+}
+
+impl Command {
+    /// Parses the command line arguments, not including the program name.
+    fn parse(mut args: impl Iterator<Item = String>) -> std::result::Result<Self, String> {
+        let command = args.next().unwrap_or_else(|| "help".to_string());
+        let mut required = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("`{}` requires a {}", command, name))
+        };
+
+        let parsed = match command.as_str() {
+            "vehicles" => Command::Vehicles,
+            "trip" => Command::Trip(required("trip id")?),
+            "departures" => Command::Departures(required("stop code")?),
+            "record" => {
+                let dir = required("directory")?;
+                let interval = match args.next() {
+                    Some(secs) => secs
+                        .parse()
+                        .map_err(|_| format!("invalid interval `{}`", secs))?,
+                    None => 30,
+                };
+                Command::Record {
+                    dir,
+                    interval: Duration::from_secs(interval),
+                }
+            }
+            "help" | "-h" | "--help" => Command::Help,
+            other => return Err(format!("unknown command `{}`", other)),
+        };
+
+        Ok(parsed)
+    }
+}
+
+fn main() {
+    let command = match Command::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let runtime = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start the tokio runtime");
+    if let Err(e) = runtime.block_on(run(command)) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(command: Command) -> Result<()> {
+    if let Command::Help = command {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let realtime = Realtime::from_env()?;
+    match command {
+        Command::Vehicles => {
+            let combined = realtime.fetch_combined(None, None).await?;
+            combined.entities.iter().for_each(print_vehicle);
+        }
+        Command::Trip(trip_id) => {
+            let combined = realtime.fetch_combined(trip_id.as_str(), None).await?;
+            combined.entities.iter().for_each(print_vehicle);
+        }
+        Command::Departures(stop_code) => {
+            for arrival in realtime.arrivals_at(&stop_code).await? {
+                let status = match arrival.status {
+                    ArrivalStatus::Expected => "",
+                    ArrivalStatus::Cancelled => " cancelled",
+                    ArrivalStatus::Skipped => " skipped",
+                    _ => " unknown",
+                };
+                println!(
+                    "{}\t{}\t{}\t{:+}s{}",
+                    arrival.expected,
+                    arrival.route_id.as_deref().unwrap_or("-"),
+                    arrival.trip_id,
+                    arrival.delay.unwrap_or(0),
+                    status
+                );
+            }
+        }
+        Command::Record { dir, interval } => {
+            let realtime = realtime.with_recorder(Recorder::new(dir)?);
+            let mut stream = Box::pin(realtime.stream(interval));
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(combined) => println!("recorded {} vehicles", combined.entities.len()),
+                    Err(e) => eprintln!("error: {}", e),
+                }
+            }
+        }
+        Command::Help => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Prints one line describing a merged vehicle entity.
+fn print_vehicle(entity: &Entity) {
+    let vehicle = entity.vehicle.as_ref();
+    let position = vehicle.and_then(|v| v.position.as_ref());
+    println!(
+        "{}\t{}\t{}\t{}\t{}",
+        vehicle
+            .and_then(|v| v.vehicle.as_ref()?.label.as_deref())
+            .unwrap_or(&entity.id),
+        entity.route_id().as_deref().unwrap_or("-"),
+        entity.trip_id().as_deref().unwrap_or("-"),
+        position.map_or("-".to_string(), |p| format!(
+            "{},{}",
+            p.latitude, p.longitude
+        )),
+        entity
+            .trip_update
+            .as_ref()
+            .and_then(|tu| tu.delay)
+            .map_or("-".to_string(), |delay| format!("{:+}s", delay)),
+    );
+}
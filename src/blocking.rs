@@ -0,0 +1,157 @@
+//! A synchronous client for applications which do not run an async runtime, such as CLIs and
+//! scripts.
+//!
+//! The client wraps an async [`Realtime`] client, and drives each call to completion on its own
+//! single-threaded runtime. Its methods must not be called from within an async runtime.
+//!
+//! [`Realtime`]: crate::Realtime
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    error::Result, health::Health, CombinedResponse, FetchOutcome, Ids, QuotaInfo, RequestOptions,
+};
+
+/// A blocking client for interacting with the Auckland Transport GTFS realtime API.
+pub struct Realtime {
+    inner: crate::Realtime,
+    runtime: Runtime,
+}
+
+impl Realtime {
+    /// Creates a new blocking Auckland Transport GTFS realtime client.
+    ///
+    /// # Parameters
+    ///
+    /// * `api_key` - The API key to use when interacting with the API.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime used to drive requests cannot be created.
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        crate::Realtime::new(api_key).into()
+    }
+
+    /// Creates a new blocking client using the API key in the `AT_API_KEY` environment variable.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::MissingApiKey`] if the environment variable is not set or empty.
+    ///
+    /// [`Error::MissingApiKey`]: crate::error::Error::MissingApiKey
+    pub fn from_env() -> Result<Self> {
+        Ok(crate::Realtime::from_env()?.into())
+    }
+
+    /// Fetches both trip updates and vehicle positions from the AT API, blocking until the
+    /// response has been received. See [`Realtime::fetch_combined`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+    pub fn fetch_combined(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<CombinedResponse> {
+        self.runtime
+            .block_on(self.inner.fetch_combined(trip_ids, vehicle_ids))
+    }
+
+    /// Fetches both trip updates and vehicle positions from the AT API, overriding the client
+    /// configuration for this call. See [`Realtime::fetch_combined_with_options`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    /// * `options` - The options to override for this call.
+    ///
+    /// [`Realtime::fetch_combined_with_options`]: crate::Realtime::fetch_combined_with_options
+    pub fn fetch_combined_with_options(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+        options: &RequestOptions,
+    ) -> Result<CombinedResponse> {
+        self.runtime
+            .block_on(
+                self.inner
+                    .fetch_combined_with_options(trip_ids, vehicle_ids, options),
+            )
+    }
+
+    /// Fetches both trip updates and vehicle positions from the AT API if they have changed since
+    /// the previous call. See [`Realtime::fetch_combined_if_modified`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// [`Realtime::fetch_combined_if_modified`]: crate::Realtime::fetch_combined_if_modified
+    pub fn fetch_combined_if_modified(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<FetchOutcome<CombinedResponse>> {
+        self.runtime
+            .block_on(self.inner.fetch_combined_if_modified(trip_ids, vehicle_ids))
+    }
+
+    /// Sends a minimal authenticated request to the AT API and classifies the result, blocking
+    /// until it has been received. See [`Realtime::health_check`].
+    ///
+    /// [`Realtime::health_check`]: crate::Realtime::health_check
+    pub fn health_check(&self) -> Health {
+        self.runtime.block_on(self.inner.health_check())
+    }
+
+    /// Returns the account quota reported by the most recent response. See [`Realtime::quota`].
+    ///
+    /// [`Realtime::quota`]: crate::Realtime::quota
+    pub fn quota(&self) -> Option<QuotaInfo> {
+        self.inner.quota()
+    }
+
+    /// Polls the AT API for all trip updates and vehicle positions at a fixed interval, blocking
+    /// between fetches. See [`Realtime::stream`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    ///
+    /// # Returns
+    ///
+    /// Returns a never-ending iterator of merged snapshots.
+    ///
+    /// [`Realtime::stream`]: crate::Realtime::stream
+    pub fn poll(&self, interval: Duration) -> impl Iterator<Item = Result<CombinedResponse>> + '_ {
+        let mut stream = Box::pin(self.inner.stream(interval));
+        std::iter::from_fn(move || self.runtime.block_on(stream.next()))
+    }
+}
+
+impl From<crate::Realtime> for Realtime {
+    /// Wraps an async client, such as one configured with [`Realtime::builder`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime used to drive requests cannot be created.
+    ///
+    /// [`Realtime::builder`]: crate::Realtime::builder
+    fn from(inner: crate::Realtime) -> Self {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to construct blocking runtime");
+
+        Self { inner, runtime }
+    }
+}
@@ -0,0 +1,224 @@
+//! A circuit breaker which stops sending requests to AT during outages.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, Result};
+
+/// A circuit breaker which trips after a number of consecutive failed requests.
+///
+/// While the breaker is open, requests fail immediately with [`Error::CircuitOpen`] without
+/// being sent. Once the cool-down period has passed a single request is let through as a probe,
+/// while other requests are still rejected; if the probe succeeds the breaker closes, and if it
+/// fails the breaker opens for another cool-down period.
+///
+/// A request counts as failed if, after any retries, it returned a 5xx status, timed out or could
+/// not be sent. Client errors such as an invalid API key do not trip the breaker.
+///
+/// [`Error::CircuitOpen`]: crate::error::Error::CircuitOpen
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// Whether a request has been let through to probe AT after the cool-down period.
+    probing: bool,
+}
+
+/// Permission from a [`CircuitBreaker`] to send a request, which must be given the outcome of
+/// the request. If the request is abandoned, such as when its future is dropped, the breaker lets
+/// another probe through.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+}
+
+impl CircuitBreaker {
+    /// Creates a new circuit breaker.
+    ///
+    /// # Parameters
+    ///
+    /// * `threshold` - The number of consecutive failures which trips the breaker.
+    /// * `cooldown` - How long the breaker stays open before letting a request through.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Returns whether the breaker is currently open, rejecting requests. This includes while a
+    /// probe is being sent after the cool-down period.
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.probing || state.open_until.is_some_and(|until| until > Instant::now())
+    }
+
+    /// Closes the breaker and clears the count of consecutive failures.
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    /// Returns an error if the breaker is open, or if its cool-down period has passed and another
+    /// request is already probing AT. Requests rejected while a probe is in flight are asked to
+    /// retry after the cool-down period, as that is how long the breaker stays open if the probe
+    /// fails.
+    pub(crate) fn check(&self) -> Result<Permit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let until = match state.open_until {
+            Some(until) => until,
+            None => {
+                return Ok(Permit {
+                    breaker: self,
+                    probe: false,
+                })
+            }
+        };
+
+        match until.checked_duration_since(Instant::now()) {
+            Some(retry_after) if !retry_after.is_zero() => Err(Error::CircuitOpen { retry_after }),
+            _ if state.probing => Err(Error::CircuitOpen {
+                retry_after: self.cooldown,
+            }),
+            _ => {
+                state.probing = true;
+                Ok(Permit {
+                    breaker: self,
+                    probe: true,
+                })
+            }
+        }
+    }
+
+    /// Records the outcome of a request.
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The error the request failed with, if any.
+    fn record(&self, error: Option<&Error>) {
+        let failed = error.is_some_and(|e| e.is_retryable() && !e.is_rate_limited());
+
+        let mut state = self.state.lock().unwrap();
+        state.probing = false;
+        if !failed {
+            *state = State::default();
+            return;
+        }
+
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                failures = state.failures,
+                cooldown_ms = self.cooldown.as_millis() as u64,
+                "circuit breaker opened"
+            );
+        }
+    }
+}
+
+impl Permit<'_> {
+    /// Records the outcome of the request, closing or opening the breaker.
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The error the request failed with, if any.
+    pub(crate) fn record(mut self, error: Option<&Error>) {
+        self.probe = false;
+        self.breaker.record(error);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            if let Ok(mut state) = self.breaker.state.lock() {
+                state.probing = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    fn server_error() -> Error {
+        Error::ServerError {
+            status: StatusCode::BAD_GATEWAY,
+            body: String::new(),
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn lets_one_probe_through() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(5));
+        breaker.check().unwrap().record(Some(&server_error()));
+        assert!(breaker.is_open());
+        assert!(matches!(breaker.check(), Err(Error::CircuitOpen { .. })));
+
+        thread::sleep(Duration::from_millis(10));
+        let probe = breaker.check().unwrap();
+        assert!(matches!(breaker.check(), Err(Error::CircuitOpen { .. })));
+        assert!(breaker.is_open());
+
+        // A failed probe opens the breaker for another cool-down period.
+        probe.record(Some(&server_error()));
+        assert!(matches!(breaker.check(), Err(Error::CircuitOpen { .. })));
+
+        thread::sleep(Duration::from_millis(10));
+        let probe = breaker.check().unwrap();
+        probe.record(None);
+        assert!(!breaker.is_open());
+        let (_first, _second) = (breaker.check().unwrap(), breaker.check().unwrap());
+    }
+
+    #[test]
+    fn abandoned_probes_let_another_through() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(5));
+        breaker.check().unwrap().record(Some(&server_error()));
+        thread::sleep(Duration::from_millis(10));
+
+        drop(breaker.check().unwrap());
+        let probe = breaker.check().unwrap();
+        assert!(matches!(breaker.check(), Err(Error::CircuitOpen { .. })));
+        probe.record(None);
+    }
+
+    #[test]
+    fn client_errors_do_not_trip() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        let not_found = Error::Api {
+            status: StatusCode::NOT_FOUND,
+            body: String::new(),
+            url: String::new(),
+            error: None,
+        };
+        for _ in 0..3 {
+            breaker.check().unwrap().record(Some(&not_found));
+        }
+        assert!(!breaker.is_open());
+
+        breaker.check().unwrap().record(Some(&server_error()));
+        assert!(!breaker.is_open());
+        breaker.check().unwrap().record(Some(&server_error()));
+        assert!(breaker.is_open());
+    }
+}
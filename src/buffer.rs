@@ -0,0 +1,247 @@
+//! A bounded buffer between a background poller and a slow consumer.
+//!
+//! [`Realtime::stream`] only fetches when the consumer asks for the next item, so a slow
+//! consumer slows down polling. [`Realtime::buffered_stream`] instead polls from a separate
+//! future, which can be spawned onto its own task, and hands snapshots over through a buffer of
+//! fixed capacity. The [`OverflowPolicy`] decides what happens when the buffer is full, so a slow
+//! consumer never causes unbounded memory growth.
+//!
+//! [`Realtime::stream`]: crate::Realtime::stream
+//! [`Realtime::buffered_stream`]: crate::Realtime::buffered_stream
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures_util::{
+    stream::{self, Stream},
+    StreamExt,
+};
+use tokio::sync::Notify;
+
+/// What to do with a new snapshot when the buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered snapshot to make room, so the consumer always catches up to the
+    /// most recent data. The number of snapshots dropped is reported by
+    /// [`BufferStats::dropped`].
+    #[default]
+    DropOldest,
+    /// Stop polling until the consumer has made room, so no snapshot is lost but the data may be
+    /// older by the time it is consumed.
+    PausePolling,
+}
+
+/// Counters shared between a buffered stream and its poller.
+#[derive(Debug, Clone)]
+pub struct BufferStats {
+    dropped: Arc<AtomicU64>,
+}
+
+impl BufferStats {
+    /// Returns the number of snapshots dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// The state shared between the poller and the consumer.
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Notified when an item is pushed or the poller stops.
+    items: Notify,
+    /// Notified when an item is taken or the consumer stops.
+    space: Notify,
+    poller_done: AtomicBool,
+    consumer_done: AtomicBool,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Sets a flag and wakes the other side when the poller or consumer is dropped.
+struct DoneGuard<'a> {
+    done: &'a AtomicBool,
+    notify: &'a Notify,
+}
+
+impl Drop for DoneGuard<'_> {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+}
+
+/// The poller side of a bounded buffer.
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a bounded buffer.
+///
+/// # Parameters
+///
+/// * `capacity` - The maximum number of items to buffer, at least 1.
+/// * `policy` - What to do with a new item when the buffer is full.
+///
+/// # Returns
+///
+/// Returns the sender which fills the buffer, the stream which consumes it, and the counters of
+/// the buffer.
+pub(crate) fn channel<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (Sender<T>, impl Stream<Item = T>, BufferStats) {
+    let capacity = capacity.max(1);
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        policy,
+        items: Notify::new(),
+        space: Notify::new(),
+        poller_done: AtomicBool::new(false),
+        consumer_done: AtomicBool::new(false),
+        dropped: Arc::default(),
+    });
+    let stats = BufferStats {
+        dropped: shared.dropped.clone(),
+    };
+
+    let sender = Sender {
+        shared: shared.clone(),
+    };
+    (sender, consume(shared), stats)
+}
+
+impl<T> Sender<T> {
+    /// Polls a source stream into the buffer until the consumer is dropped or the source ends.
+    ///
+    /// # Parameters
+    ///
+    /// * `source` - The stream to poll.
+    pub(crate) async fn forward<S: Stream<Item = T>>(self, source: S) {
+        let shared = &*self.shared;
+        let _guard = DoneGuard {
+            done: &shared.poller_done,
+            notify: &shared.items,
+        };
+        let mut source = Box::pin(source);
+
+        loop {
+            if shared.policy == OverflowPolicy::PausePolling {
+                while shared.queue.lock().unwrap().len() >= shared.capacity {
+                    if shared.consumer_done.load(Ordering::Acquire) {
+                        return;
+                    }
+                    shared.space.notified().await;
+                }
+            }
+            if shared.consumer_done.load(Ordering::Acquire) {
+                return;
+            }
+
+            let item = match source.next().await {
+                Some(item) => item,
+                None => return,
+            };
+
+            {
+                let mut queue = shared.queue.lock().unwrap();
+                if queue.len() >= shared.capacity {
+                    queue.pop_front();
+                    shared.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                queue.push_back(item);
+            }
+            shared.items.notify_one();
+        }
+    }
+}
+
+/// Yields the items in the buffer until the poller stops and the buffer is empty.
+fn consume<T>(shared: Arc<Shared<T>>) -> impl Stream<Item = T> {
+    /// Marks the consumer as done when the stream is dropped.
+    struct Consumer<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    impl<T> Drop for Consumer<T> {
+        fn drop(&mut self) {
+            drop(DoneGuard {
+                done: &self.shared.consumer_done,
+                notify: &self.shared.space,
+            });
+        }
+    }
+
+    stream::unfold(Consumer { shared }, |consumer| async move {
+        loop {
+            let item = consumer.shared.queue.lock().unwrap().pop_front();
+            if let Some(item) = item {
+                consumer.shared.space.notify_one();
+                return Some((item, consumer));
+            }
+            if consumer.shared.poller_done.load(Ordering::Acquire) {
+                return None;
+            }
+            consumer.shared.items.notified().await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future;
+
+    use super::*;
+    use crate::transport::tests::block_on;
+
+    #[test]
+    fn drops_the_oldest_items_when_full() {
+        let (sender, items, stats) = channel(2, OverflowPolicy::DropOldest);
+        block_on(sender.forward(stream::iter(1..=5)));
+
+        let items: Vec<u32> = block_on(items.collect());
+        assert_eq!(items, [4, 5]);
+        assert_eq!(stats.dropped(), 3);
+    }
+
+    #[test]
+    fn pauses_polling_until_there_is_room() {
+        let (sender, items, stats) = channel(2, OverflowPolicy::PausePolling);
+        let consumer = items.then(|item| async move {
+            tokio::task::yield_now().await;
+            item
+        });
+
+        let ((), items) = block_on(future::join(
+            sender.forward(stream::iter(1..=5)),
+            consumer.collect::<Vec<u32>>(),
+        ));
+        assert_eq!(items, [1, 2, 3, 4, 5]);
+        assert_eq!(stats.dropped(), 0);
+    }
+
+    #[test]
+    fn stops_polling_once_the_consumer_is_dropped() {
+        for policy in [OverflowPolicy::DropOldest, OverflowPolicy::PausePolling].iter() {
+            let (sender, items, _) = channel::<u32>(1, *policy);
+            drop(items);
+            // The source never ends, so this only returns because the consumer is gone.
+            block_on(sender.forward(stream::repeat(1)));
+        }
+
+        let (sender, items, _) = channel(1, OverflowPolicy::PausePolling);
+        let mut items = Box::pin(items);
+        let polled = async {
+            assert_eq!(items.next().await, Some(1));
+            drop(items);
+        };
+        block_on(future::join(sender.forward(stream::repeat(1)), polled));
+    }
+}
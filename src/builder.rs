@@ -0,0 +1,449 @@
+//! Configuration of clients before they are constructed.
+
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT},
+    Client, ClientBuilder,
+};
+
+use crate::{
+    breaker::CircuitBreaker, cache::ResponseCache, limiter::RateLimiter, timer::Timer,
+    transport::Transport, ApiVersion, FetchStrategy, Realtime, RetryPolicy, BASE_API_URL,
+};
+
+/// The default maximum time to wait for a connection to be established.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The default maximum time to wait for the response body once the response headers arrive.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// The default maximum time a request may take in total.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The default minimum interval between fetches made by polling streams.
+pub const DEFAULT_MIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The `User-Agent` header sent with requests unless overridden.
+pub const DEFAULT_USER_AGENT: &str = concat!("at-api-rs/", env!("CARGO_PKG_VERSION"));
+
+/// Environment variable which overrides the default base URL.
+pub const BASE_URL_ENV: &str = "AT_API_BASE_URL";
+
+/// A builder for a [`Realtime`] client, created with [`Realtime::builder`].
+///
+/// Options which are not set keep the defaults used by [`Realtime::new`].
+pub struct RealtimeBuilder {
+    api_key: Arc<str>,
+    transport: Option<Arc<dyn Transport>>,
+    base_url: Option<String>,
+    api_version: ApiVersion,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    max_response_size: Option<usize>,
+    timeout: Duration,
+    retry: RetryPolicy,
+    breaker: Option<CircuitBreaker>,
+    limiter: Option<RateLimiter>,
+    coalesce: bool,
+    cache: Option<Arc<ResponseCache>>,
+    min_poll_interval: Duration,
+    fetch_strategy: FetchStrategy,
+    timer: Option<Arc<dyn Timer>>,
+    default_headers: HeaderMap,
+    pool: PoolOptions,
+}
+
+/// Connection pool and keepalive settings applied to the reqwest client built by
+/// [`RealtimeBuilder`]. Settings which are not set keep reqwest's defaults.
+#[derive(Debug, Default)]
+struct PoolOptions {
+    idle_timeout: Option<Duration>,
+    max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    http2_keep_alive_interval: Option<Duration>,
+    http2_keep_alive_timeout: Option<Duration>,
+    http2_keep_alive_while_idle: Option<bool>,
+}
+
+impl RealtimeBuilder {
+    pub(crate) fn new(api_key: Arc<str>) -> Self {
+        Self {
+            api_key,
+            transport: None,
+            base_url: None,
+            api_version: ApiVersion::default(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            max_response_size: None,
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::none(),
+            breaker: None,
+            limiter: None,
+            coalesce: true,
+            cache: None,
+            min_poll_interval: DEFAULT_MIN_POLL_INTERVAL,
+            fetch_strategy: FetchStrategy::default(),
+            timer: None,
+            default_headers: crate::realtime::default_headers(),
+            pool: PoolOptions::default(),
+        }
+    }
+
+    /// Sets the transport used to execute HTTP requests, instead of a default reqwest client.
+    ///
+    /// # Parameters
+    ///
+    /// * `transport` - The transport to execute requests with.
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Sets the base URL requests are sent to, such as a mock server or a proxy.
+    ///
+    /// If not set, the base URL is read from the `AT_API_BASE_URL` environment variable, falling
+    /// back to `https://api.at.govt.nz`.
+    ///
+    /// # Parameters
+    ///
+    /// * `base_url` - The base URL, excluding the API version path.
+    pub fn base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Sets the version of the AT API to use. Defaults to [`ApiVersion::V2`].
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The API version.
+    pub fn api_version(mut self, version: ApiVersion) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Sets the endpoints used to fetch the combined feed. Defaults to
+    /// [`FetchStrategy::Combined`].
+    ///
+    /// # Parameters
+    ///
+    /// * `strategy` - The fetch strategy.
+    pub fn fetch_strategy(mut self, strategy: FetchStrategy) -> Self {
+        self.fetch_strategy = strategy;
+        self
+    }
+
+    /// Sets the timer used for backoff, rate limiting, read timeouts and polling, instead of
+    /// tokio.
+    ///
+    /// The default transport is built on reqwest, which requires a tokio runtime, so a custom
+    /// transport is also needed to run the client without tokio.
+    ///
+    /// # Parameters
+    ///
+    /// * `timer` - The timer to wait with.
+    pub fn timer<T: Timer + 'static>(mut self, timer: T) -> Self {
+        self.timer = Some(Arc::new(timer));
+        self
+    }
+
+    /// Sets the `User-Agent` header sent with every request. Defaults to `at-api-rs/<version>`.
+    ///
+    /// # Parameters
+    ///
+    /// * `user_agent` - The user agent.
+    pub fn user_agent(self, user_agent: HeaderValue) -> Self {
+        self.default_header(USER_AGENT, user_agent)
+    }
+
+    /// Adds a header sent with every request, replacing any default header of the same name.
+    ///
+    /// Headers set for a single call with [`RequestOptions::header`] take precedence.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the header.
+    /// * `value` - The value of the header.
+    ///
+    /// [`RequestOptions::header`]: crate::RequestOptions::header
+    pub fn default_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.default_headers.insert(name, value);
+        self
+    }
+
+    /// Sets the reqwest client used to execute HTTP requests, instead of constructing a new one.
+    ///
+    /// `reqwest::Client` keeps its connection pool behind a reference count, so passing a clone
+    /// of an existing client shares its pool, proxies and TLS configuration with the rest of the
+    /// application.
+    ///
+    /// A provided client keeps its own decompression settings, which reqwest enables by default
+    /// when the `gzip` or `brotli` features of this crate are enabled.
+    ///
+    /// # Parameters
+    ///
+    /// * `client` - The client to execute requests with.
+    pub fn client(self, client: Client) -> Self {
+        self.transport(client)
+    }
+
+    /// Sets the maximum time to wait for a connection to AT to be established. Defaults to 10
+    /// seconds.
+    ///
+    /// This only applies when the builder constructs the reqwest client, and is ignored if a
+    /// client or transport is provided.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The connect timeout.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets how long idle connections are kept open in the pool for reuse. Defaults to reqwest's
+    /// default of 90 seconds.
+    ///
+    /// Pollers should keep this above their poll interval, so each poll reuses the previous
+    /// connection instead of performing a new TLS handshake. Like the connect timeout, this only
+    /// applies when the builder constructs the reqwest client.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The idle timeout.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of idle connections kept open to each host. Defaults to no limit.
+    ///
+    /// # Parameters
+    ///
+    /// * `max` - The maximum number of idle connections.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool.max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Sets the interval of TCP keepalive probes sent on open connections. Disabled by default.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The keepalive interval.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.pool.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Sets the interval of HTTP/2 keepalive pings sent on open connections. Disabled by
+    /// default.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The ping interval.
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.pool.http2_keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Sets how long to wait for an HTTP/2 keepalive ping to be acknowledged before closing the
+    /// connection.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The ping timeout.
+    pub fn http2_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.pool.http2_keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets whether HTTP/2 keepalive pings are sent while no requests are in flight, which keeps
+    /// the connection open between polls.
+    ///
+    /// # Parameters
+    ///
+    /// * `enabled` - Whether to ping idle connections.
+    pub fn http2_keep_alive_while_idle(mut self, enabled: bool) -> Self {
+        self.pool.http2_keep_alive_while_idle = Some(enabled);
+        self
+    }
+
+    /// Sets the maximum time to wait for the response body once the response headers have been
+    /// received. Defaults to 30 seconds.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The read timeout.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum size of a response body. Responses larger than this fail with
+    /// [`Error::ResponseTooLarge`] as soon as the limit is exceeded, without reading the rest of
+    /// the body. There is no limit by default.
+    ///
+    /// # Parameters
+    ///
+    /// * `bytes` - The maximum response size in bytes.
+    ///
+    /// [`Error::ResponseTooLarge`]: crate::error::Error::ResponseTooLarge
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// Sets the maximum time a request may take, from sending the request until the response
+    /// body has been received. Defaults to 60 seconds.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The request timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the policy used to retry failed requests. By default requests are not retried.
+    ///
+    /// # Parameters
+    ///
+    /// * `policy` - The retry policy.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Sets a circuit breaker which short-circuits requests after repeated failures. By default
+    /// no circuit breaker is used.
+    ///
+    /// # Parameters
+    ///
+    /// * `breaker` - The circuit breaker.
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Sets a rate limiter which delays requests so the account quota is not exceeded. By
+    /// default requests are not rate limited.
+    ///
+    /// # Parameters
+    ///
+    /// * `limiter` - The rate limiter.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Sets whether concurrent identical requests are coalesced into a single upstream request,
+    /// whose response is shared between the callers. Enabled by default.
+    ///
+    /// # Parameters
+    ///
+    /// * `coalesce` - Whether to coalesce requests.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    /// Sets an in-memory cache which serves repeated identical calls within its TTL without
+    /// sending a request. By default responses are not cached.
+    ///
+    /// The cache may be shared between clients, and cleared by the application at any time.
+    /// Conditional fetches such as [`Realtime::fetch_combined_if_modified`] are not served from
+    /// the cache, as they report whether the feed changed since the previous call, but the
+    /// responses they receive are cached for other calls.
+    ///
+    /// [`Realtime::fetch_combined_if_modified`]: crate::Realtime::fetch_combined_if_modified
+    ///
+    /// # Parameters
+    ///
+    /// * `cache` - The response cache.
+    pub fn response_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sets the minimum interval between fetches made by polling streams and the embedded server.
+    /// Shorter intervals are clamped to this value. Defaults to 1 second.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The minimum poll interval.
+    pub fn min_poll_interval(mut self, interval: Duration) -> Self {
+        self.min_poll_interval = interval;
+        self
+    }
+
+    /// Builds the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no client or transport was provided and the default reqwest client cannot be
+    /// constructed, in the same way as `reqwest::Client::new`.
+    pub fn build(self) -> Realtime {
+        let (connect_timeout, pool) = (self.connect_timeout, self.pool);
+        let transport = self.transport.unwrap_or_else(|| {
+            let client = pool
+                .apply(Client::builder().connect_timeout(connect_timeout))
+                .build()
+                .expect("failed to construct reqwest client");
+            Arc::new(client)
+        });
+
+        let mut realtime = Realtime::from_parts(self.api_key, transport);
+        realtime.timeout = Some(self.timeout);
+        realtime.read_timeout = Some(self.read_timeout);
+        realtime.max_response_size = self.max_response_size;
+        realtime.retry = self.retry;
+        realtime.breaker = self.breaker.map(Arc::new);
+        realtime.limiter = self.limiter.map(Arc::new);
+        realtime.coalesce = self.coalesce;
+        realtime.cache = self.cache;
+        realtime.min_poll_interval = self.min_poll_interval;
+        realtime.api_version = self.api_version;
+        realtime.fetch_strategy = self.fetch_strategy;
+        realtime.default_headers = self.default_headers;
+        if let Some(timer) = self.timer {
+            realtime.timer = timer;
+        }
+
+        let base_url = self
+            .base_url
+            .or_else(|| std::env::var(BASE_URL_ENV).ok())
+            .unwrap_or_else(|| BASE_API_URL.to_string());
+        realtime.base_url = base_url.trim_end_matches('/').into();
+
+        realtime
+    }
+}
+
+impl PoolOptions {
+    /// Applies the settings which were set to a reqwest client builder.
+    ///
+    /// # Parameters
+    ///
+    /// * `builder` - The client builder.
+    fn apply(self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(timeout) = self.idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            builder = builder.http2_keep_alive_interval(interval);
+        }
+        if let Some(timeout) = self.http2_keep_alive_timeout {
+            builder = builder.http2_keep_alive_timeout(timeout);
+        }
+        if let Some(enabled) = self.http2_keep_alive_while_idle {
+            builder = builder.http2_keep_alive_while_idle(enabled);
+        }
+        builder
+    }
+}
@@ -0,0 +1,284 @@
+//! Caches which keep recently fetched data around between requests and restarts.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    types::{gtfs::Entity, Header},
+    CombinedResponse,
+};
+
+/// Key used to store the last good realtime snapshot.
+const SNAPSHOT_KEY: &str = "snapshot";
+
+/// A persistent cache stored as JSON files inside a directory.
+///
+/// The cache is intended to keep the last good realtime snapshot (and any other lookups the
+/// application wants to keep) on disk, so an application can start and show recent data even
+/// when the AT API is temporarily unavailable.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+/// An in-memory cache of fetched realtime data, keyed by the request URL including its query
+/// parameters.
+///
+/// Calls made within the TTL of a previous identical call return the cached data instead of
+/// sending a request. AT refreshes the realtime feed roughly every 30 seconds, so a TTL of up to
+/// 30 seconds avoids redundant requests without serving noticeably stale data.
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+/// Data stored in a [`ResponseCache`].
+#[derive(Debug)]
+struct CacheEntry {
+    stored_at: Instant,
+    combined: CombinedResponse,
+}
+
+/// A realtime snapshot loaded from the disk cache.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedSnapshot {
+    /// The response header received from AT.
+    pub header: Header,
+    /// The merged entities returned with the header.
+    pub entities: Vec<Entity>,
+    /// The entities which were not merged.
+    #[serde(default)]
+    pub unmatched: Vec<Entity>,
+    /// When the snapshot was received from AT.
+    pub fetched_at: SystemTime,
+    /// The UNIX timestamp (in seconds) at which the snapshot was stored.
+    pub stored_at: u64,
+}
+
+impl CachedSnapshot {
+    /// Turns the snapshot back into the response it was stored from.
+    pub fn into_combined(self) -> CombinedResponse {
+        CombinedResponse {
+            header: self.header,
+            entities: self.entities,
+            unmatched: self.unmatched,
+            fetched_at: self.fetched_at,
+        }
+    }
+}
+
+impl ResponseCache {
+    /// Creates a new, empty response cache.
+    ///
+    /// # Parameters
+    ///
+    /// * `ttl` - How long fetched data is served from the cache.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Removes all cached data.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Returns the cached data for the given URL, if it has not expired.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The request URL.
+    pub(crate) fn get(&self, url: &str) -> Option<CombinedResponse> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(url) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => Some(entry.combined.clone()),
+            _ => None,
+        }
+    }
+
+    /// Stores fetched data for the given URL, removing any expired data.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The request URL.
+    /// * `combined` - The merged response.
+    pub(crate) fn put(&self, url: &str, combined: &CombinedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.stored_at.elapsed() < self.ttl);
+        entries.insert(
+            url.to_string(),
+            CacheEntry {
+                stored_at: Instant::now(),
+                combined: combined.clone(),
+            },
+        );
+    }
+}
+
+impl DiskCache {
+    /// Opens a disk cache in the given directory, creating the directory if it does not exist.
+    ///
+    /// # Parameters
+    ///
+    /// * `dir` - The directory to store cache entries in.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Stores a realtime snapshot, replacing the previously stored snapshot.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to store.
+    pub fn store_snapshot(&self, combined: &CombinedResponse) -> Result<()> {
+        #[derive(Serialize)]
+        struct SnapshotRef<'a> {
+            header: &'a Header,
+            entities: &'a [Entity],
+            unmatched: &'a [Entity],
+            fetched_at: SystemTime,
+            stored_at: u64,
+        }
+
+        let stored_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        self.put(
+            SNAPSHOT_KEY,
+            &SnapshotRef {
+                header: &combined.header,
+                entities: &combined.entities,
+                unmatched: &combined.unmatched,
+                fetched_at: combined.fetched_at,
+                stored_at,
+            },
+        )
+    }
+
+    /// Loads the last stored realtime snapshot. Use [`CachedSnapshot::into_combined`] to turn it
+    /// back into the response it was stored from.
+    ///
+    /// # Returns
+    ///
+    /// Returns the stored snapshot, or [`None`] if no snapshot has been stored.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn load_snapshot(&self) -> Result<Option<CachedSnapshot>> {
+        self.get(SNAPSHOT_KEY)
+    }
+
+    /// Stores an arbitrary value under the given key.
+    ///
+    /// The value is written to a temporary file and moved into place, so a crash while writing
+    /// never leaves a partially written entry behind.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key to store the value under. Must be a valid file name.
+    /// * `value` - The value to store.
+    pub fn put<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        let path = self.path(key);
+        let tmp = path.with_extension("json.tmp");
+
+        fs::write(&tmp, serde_json::to_vec(value)?)?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /// Loads the value stored under the given key.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key the value was stored under.
+    ///
+    /// # Returns
+    ///
+    /// Returns the stored value, or [`None`] if nothing is stored under the key.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match fs::read(self.path(key)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Removes the value stored under the given key, if any.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key to remove.
+    pub fn remove(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn round_trips_snapshots() {
+        let dir = std::env::temp_dir().join(format!("at-api-rs-cache-{}", process::id()));
+        let cache = DiskCache::open(&dir).unwrap();
+        assert!(cache.load_snapshot().unwrap().is_none());
+
+        let header: Header =
+            serde_json::from_value(json!({"gtfs_realtime_version": "2.0", "timestamp": 1}))
+                .unwrap();
+        let mut combined = CombinedResponse::new(
+            header,
+            serde_json::from_value(json!([{"id": "a", "vehicle": {"stop_id": "s1"}}])).unwrap(),
+        );
+        combined.unmatched =
+            serde_json::from_value(json!([{"id": "b", "trip_update": {"trip": {}}}])).unwrap();
+        combined.fetched_at = UNIX_EPOCH + Duration::from_millis(1_600_000_000_123);
+        cache.store_snapshot(&combined).unwrap();
+
+        let loaded = cache.load_snapshot().unwrap().unwrap().into_combined();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.fetched_at, combined.fetched_at);
+        assert_eq!(
+            serde_json::to_value(&loaded.header).unwrap(),
+            serde_json::to_value(&combined.header).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&loaded.entities).unwrap(),
+            serde_json::to_value(&combined.entities).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&loaded.unmatched).unwrap(),
+            serde_json::to_value(&combined.unmatched).unwrap()
+        );
+    }
+}
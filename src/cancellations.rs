@@ -0,0 +1,246 @@
+//! Events for trips being cancelled and reinstated.
+//!
+//! AT marks a cancelled trip by setting the schedule relationship of its trip descriptor to
+//! [`Cancelled`] in every snapshot until the trip is over. A [`CancellationTracker`] compares
+//! snapshots and reports each cancellation once, so alerting systems do not have to diff
+//! snapshots themselves.
+//!
+//! [`Cancelled`]: crate::types::gtfs::ScheduleRelationshipTripDescriptor::Cancelled
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
+
+use futures_util::{
+    stream::{self, Stream},
+    StreamExt,
+};
+
+use crate::{
+    error::Result,
+    intern::Interner,
+    types::gtfs::{Entity, ScheduleRelationshipTripDescriptor, TripDescriptor},
+    CombinedResponse,
+};
+
+/// A change to the cancellation of a trip.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum CancellationEvent {
+    /// The trip was cancelled, or appeared cancelled for the first time.
+    Cancelled(TripDescriptor),
+    /// The trip was previously cancelled and is now running again.
+    Reinstated(TripDescriptor),
+}
+
+impl CancellationEvent {
+    /// Returns the trip descriptor of the trip, as it appeared in the snapshot which caused the
+    /// event.
+    pub fn trip(&self) -> &TripDescriptor {
+        match self {
+            CancellationEvent::Cancelled(trip) | CancellationEvent::Reinstated(trip) => trip,
+        }
+    }
+}
+
+/// Tracks which trips are cancelled across snapshots.
+///
+/// Trips are identified by their full trip ID. A cancelled trip which leaves the feed is
+/// forgotten without an event, as AT removes trips once they are over.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationTracker {
+    interner: Interner,
+    cancelled: HashSet<Arc<str>>,
+}
+
+impl CancellationTracker {
+    /// Creates a tracker which has not observed a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the pool the IDs of cancelled trips are interned in, so several trackers can share
+    /// one copy of each ID. By default the tracker has a pool of its own.
+    ///
+    /// # Parameters
+    ///
+    /// * `interner` - The pool to intern IDs in.
+    pub fn interner(mut self, interner: Interner) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    /// Returns the IDs of the trips which were cancelled in the last snapshot observed.
+    pub fn cancelled(&self) -> impl Iterator<Item = &str> {
+        self.cancelled.iter().map(|trip_id| &**trip_id)
+    }
+
+    /// Observes a snapshot and returns the changes since the last snapshot. Every cancelled trip
+    /// in the first snapshot observed is reported as cancelled.
+    ///
+    /// Cancelled trips usually have no vehicle serving them, so both the merged and the unmatched
+    /// entities of the snapshot are checked.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to observe.
+    pub fn observe(&mut self, combined: &CombinedResponse) -> Vec<CancellationEvent> {
+        let trips = combined
+            .entities
+            .iter()
+            .chain(combined.unmatched.iter())
+            .filter_map(trip);
+
+        let mut events = vec![];
+        let mut cancelled = HashSet::new();
+        for (trip_id, trip) in trips {
+            let is_cancelled = matches!(
+                trip.schedule_relationship,
+                Some(ScheduleRelationshipTripDescriptor::Cancelled)
+            );
+            if is_cancelled {
+                if !self.cancelled.contains(trip_id) && !cancelled.contains(trip_id) {
+                    events.push(CancellationEvent::Cancelled(trip.clone()));
+                }
+                cancelled.insert(self.interner.intern(trip_id));
+            } else if self.cancelled.remove(trip_id) {
+                events.push(CancellationEvent::Reinstated(trip.clone()));
+            }
+        }
+
+        self.cancelled = cancelled;
+        self.interner.shrink();
+        events
+    }
+}
+
+/// Turns a stream of snapshots, such as [`Realtime::stream`], into a stream of cancellation
+/// events. Errors from the snapshot stream are passed through.
+///
+/// # Parameters
+///
+/// * `snapshots` - The stream of snapshots to observe.
+///
+/// [`Realtime::stream`]: crate::Realtime::stream
+pub fn cancellation_events<S>(snapshots: S) -> impl Stream<Item = Result<CancellationEvent>>
+where
+    S: Stream<Item = Result<CombinedResponse>>,
+{
+    struct State<S> {
+        snapshots: S,
+        tracker: CancellationTracker,
+        pending: VecDeque<CancellationEvent>,
+    }
+
+    let state = State {
+        snapshots: Box::pin(snapshots),
+        tracker: CancellationTracker::new(),
+        pending: VecDeque::new(),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+
+            match state.snapshots.next().await? {
+                Ok(combined) => state.pending.extend(state.tracker.observe(&combined)),
+                Err(e) => return Some((Err(e), state)),
+            }
+        }
+    })
+}
+
+/// Returns the trip ID and trip descriptor of the trip update of an entity.
+fn trip(entity: &Entity) -> Option<(&str, &TripDescriptor)> {
+    let trip = &entity.trip_update.as_ref()?.trip;
+    Some((trip.trip_id.as_deref()?, trip))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        combined::tests::{snapshot, unmatched},
+        error::Error,
+        transport::tests::block_on,
+    };
+
+    fn trip_update(trip_id: &str, cancelled: bool) -> Value {
+        let schedule_relationship = if cancelled { 3 } else { 0 };
+        json!({
+            "id": trip_id,
+            "trip_update": {
+                "trip": {"trip_id": trip_id, "schedule_relationship": schedule_relationship}
+            }
+        })
+    }
+
+    /// Returns each event as whether it is a cancellation, and the trip ID.
+    fn summary(events: &[CancellationEvent]) -> Vec<(bool, &str)> {
+        events
+            .iter()
+            .map(|event| {
+                let cancelled = matches!(event, CancellationEvent::Cancelled(_));
+                (cancelled, event.trip().trip_id.as_deref().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_cancellations_and_reinstatements_once() {
+        let mut tracker = CancellationTracker::new();
+
+        let events = tracker.observe(&unmatched(json!([
+            trip_update("t1", true),
+            trip_update("t2", false),
+        ])));
+        assert_eq!(summary(&events), [(true, "t1")]);
+
+        let events = tracker.observe(&snapshot(json!([
+            trip_update("t1", true),
+            trip_update("t2", true),
+        ])));
+        assert_eq!(summary(&events), [(true, "t2")]);
+        let mut cancelled: Vec<_> = tracker.cancelled().collect();
+        cancelled.sort_unstable();
+        assert_eq!(cancelled, ["t1", "t2"]);
+
+        // t2 is reinstated and t1 leaves the feed without an event.
+        let events = tracker.observe(&snapshot(json!([trip_update("t2", false)])));
+        assert_eq!(summary(&events), [(false, "t2")]);
+        assert_eq!(tracker.cancelled().count(), 0);
+
+        // A trip which was forgotten is reported again if it reappears cancelled.
+        let events = tracker.observe(&snapshot(json!([trip_update("t1", true)])));
+        assert_eq!(summary(&events), [(true, "t1")]);
+    }
+
+    #[test]
+    fn streams_events_and_errors() {
+        let snapshots = stream::iter(vec![
+            Ok(snapshot(json!([
+                trip_update("t1", true),
+                trip_update("t2", true)
+            ]))),
+            Err(Error::MissingApiKey),
+            Ok(snapshot(json!([trip_update("t1", false)]))),
+        ]);
+        let events: Vec<_> = block_on(cancellation_events(snapshots).collect());
+
+        assert_eq!(events.len(), 4);
+        let trip_ids: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                Ok(event) => event.trip().trip_id.as_deref().unwrap(),
+                Err(_) => "error",
+            })
+            .collect();
+        assert_eq!(trip_ids, ["t1", "t2", "error", "t1"]);
+        assert!(matches!(events[3], Ok(CancellationEvent::Reinstated(_))));
+    }
+}
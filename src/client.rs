@@ -0,0 +1,145 @@
+//! A single entry point to the realtime and static GTFS APIs.
+
+use crate::{
+    error::Result,
+    types::schedule::{GtfsVersion, Route, ShapePoint, Stop, StopTime, Trip},
+    Realtime, RealtimeBuilder,
+};
+
+/// A client for every part of the AT API supported by the library.
+///
+/// The client owns one HTTP client and API key, so authentication, retries, rate limiting and
+/// the other options of [`RealtimeBuilder`] are configured once and apply to every API. Each API
+/// is reached through an accessor, such as [`realtime`] and [`gtfs`].
+///
+/// [`realtime`]: AtClient::realtime
+/// [`gtfs`]: AtClient::gtfs
+#[derive(Clone)]
+pub struct AtClient {
+    inner: Realtime,
+}
+
+/// The static GTFS API, which serves the published schedule. Returned by [`AtClient::gtfs`].
+#[derive(Clone, Copy)]
+pub struct Gtfs<'a> {
+    client: &'a Realtime,
+}
+
+impl AtClient {
+    /// Creates a new client with the default configuration.
+    ///
+    /// # Parameters
+    ///
+    /// * `api_key` - The API key to use when interacting with the API.
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Realtime::new(api_key).into()
+    }
+
+    /// Creates a new client using the API key in the `AT_API_KEY` environment variable.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::MissingApiKey`] if the environment variable is not set or empty.
+    ///
+    /// [`Error::MissingApiKey`]: crate::error::Error::MissingApiKey
+    pub fn from_env() -> Result<Self> {
+        Ok(Realtime::from_env()?.into())
+    }
+
+    /// Creates a builder to configure the client with. The built [`Realtime`] client converts
+    /// into an `AtClient` with [`From`].
+    ///
+    /// # Parameters
+    ///
+    /// * `api_key` - The API key to use when interacting with the API.
+    pub fn builder<S: Into<String>>(api_key: S) -> RealtimeBuilder {
+        Realtime::builder(api_key)
+    }
+
+    /// Returns the realtime API.
+    pub fn realtime(&self) -> &Realtime {
+        &self.inner
+    }
+
+    /// Returns the static GTFS API.
+    pub fn gtfs(&self) -> Gtfs<'_> {
+        Gtfs {
+            client: &self.inner,
+        }
+    }
+
+    /// Returns the underlying realtime client.
+    pub fn into_realtime(self) -> Realtime {
+        self.inner
+    }
+}
+
+impl From<Realtime> for AtClient {
+    fn from(inner: Realtime) -> Self {
+        Self { inner }
+    }
+}
+
+impl Gtfs<'_> {
+    /// Fetches the routes with the given route number. See
+    /// [`Realtime::fetch_routes_by_short_name`].
+    ///
+    /// # Parameters
+    ///
+    /// * `short_name` - The route number shown to passengers, such as `82` or `NX2`.
+    pub async fn routes_by_short_name(&self, short_name: &str) -> Result<Vec<Route>> {
+        self.client.fetch_routes_by_short_name(short_name).await
+    }
+
+    /// Fetches the stops with the given stop code. See [`Realtime::fetch_stops_by_code`].
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The code shown on the stop's signage, such as `7036`.
+    pub async fn stops_by_code(&self, code: &str) -> Result<Vec<Stop>> {
+        self.client.fetch_stops_by_code(code).await
+    }
+
+    /// Fetches a trip. See [`Realtime::fetch_trip`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_id` - The full ID of the trip, including the GTFS version.
+    pub async fn trip(&self, trip_id: &str) -> Result<Vec<Trip>> {
+        self.client.fetch_trip(trip_id).await
+    }
+
+    /// Fetches the scheduled stops of a trip. See [`Realtime::fetch_stop_times_by_trip_id`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_id` - The full ID of the trip, including the GTFS version.
+    pub async fn stop_times_by_trip_id(&self, trip_id: &str) -> Result<Vec<StopTime>> {
+        self.client.fetch_stop_times_by_trip_id(trip_id).await
+    }
+
+    /// Fetches the scheduled stops of every trip which serves a stop. See
+    /// [`Realtime::fetch_stop_times_by_stop_id`].
+    ///
+    /// # Parameters
+    ///
+    /// * `stop_id` - The full ID of the stop, including the GTFS version.
+    pub async fn stop_times_by_stop_id(&self, stop_id: &str) -> Result<Vec<StopTime>> {
+        self.client.fetch_stop_times_by_stop_id(stop_id).await
+    }
+
+    /// Fetches the points of a shape. See [`Realtime::fetch_shape`].
+    ///
+    /// # Parameters
+    ///
+    /// * `shape_id` - The full ID of the shape, including the GTFS version.
+    pub async fn shape(&self, shape_id: &str) -> Result<Vec<ShapePoint>> {
+        self.client.fetch_shape(shape_id).await
+    }
+
+    /// Fetches the GTFS versions which are currently published. See
+    /// [`Realtime::fetch_versions`].
+    pub async fn versions(&self) -> Result<Vec<GtfsVersion>> {
+        self.client.fetch_versions().await
+    }
+}
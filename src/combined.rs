@@ -0,0 +1,154 @@
+//! The merged result of fetching trip updates and vehicle positions together.
+
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::types::{
+    gtfs::{Entity, VehicleService},
+    Header,
+};
+
+/// A merged snapshot of trip updates and vehicle positions, as returned by
+/// [`Realtime::fetch_combined`].
+///
+/// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CombinedResponse {
+    /// The header of the response. When the feed was fetched from separate trip update and
+    /// vehicle position endpoints, this is the header of the trip updates.
+    pub header: Header,
+    /// The vehicle positions on a trip, each with the trip update of its trip attached.
+    pub entities: Vec<Entity>,
+    /// The entities which were not merged: vehicle positions without a matching trip update
+    /// entity, such as vehicles not serving a trip, and trip updates which no vehicle is serving,
+    /// such as cancelled trips.
+    pub unmatched: Vec<Entity>,
+    /// When the response was received from AT.
+    pub fetched_at: SystemTime,
+}
+
+/// The JSON document of a snapshot, with its header and merged entities sorted by ID. This is the
+/// form served by the embedded server and patched by [`json_patch`]. The entities of a snapshot
+/// are in no particular order, so they are sorted to keep the same entity at the same position
+/// between snapshots.
+///
+/// [`json_patch`]: crate::compare::json_patch
+#[derive(Serialize)]
+pub(crate) struct SnapshotDocument<'a> {
+    pub(crate) header: &'a Header,
+    pub(crate) entities: Vec<&'a Entity>,
+}
+
+impl CombinedResponse {
+    /// Creates a response received now, with no unmatched entities.
+    ///
+    /// # Parameters
+    ///
+    /// * `header` - The header of the response.
+    /// * `entities` - The merged entities.
+    pub fn new(header: Header, entities: Vec<Entity>) -> Self {
+        Self {
+            header,
+            entities,
+            unmatched: vec![],
+            fetched_at: SystemTime::now(),
+        }
+    }
+
+    /// Creates a response received now with no entities, as AT returns when nothing is running,
+    /// such as overnight.
+    ///
+    /// # Parameters
+    ///
+    /// * `header` - The header of the response.
+    pub fn empty(header: Header) -> Self {
+        Self::new(header, vec![])
+    }
+
+    /// Returns true if the response has no entities, merged or unmatched.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty() && self.unmatched.is_empty()
+    }
+
+    /// Returns the entities for trips which are not in the static schedule, such as extra
+    /// services added on the day. These are included in [`entities`] or [`unmatched`] as well,
+    /// but cannot be looked up in the static schedule and usually need special handling, such as
+    /// on departure boards. See [`Entity::is_extra_service`].
+    ///
+    /// [`entities`]: CombinedResponse::entities
+    /// [`unmatched`]: CombinedResponse::unmatched
+    /// [`Entity::is_extra_service`]: crate::types::gtfs::Entity::is_extra_service
+    pub fn extra_services(&self) -> impl Iterator<Item = &Entity> {
+        self.entities
+            .iter()
+            .chain(self.unmatched.iter())
+            .filter(|entity| entity.is_extra_service())
+    }
+
+    /// Returns the vehicles which are reporting their position without serving a trip, such as
+    /// vehicles running to or from the depot, so they can be shown differently from vehicles in
+    /// service. See [`Entity::is_deadhead`].
+    ///
+    /// [`Entity::is_deadhead`]: crate::types::gtfs::Entity::is_deadhead
+    pub fn deadheads(&self) -> impl Iterator<Item = &Entity> {
+        self.entities
+            .iter()
+            .chain(self.unmatched.iter())
+            .filter(|entity| entity.is_deadhead())
+    }
+
+    /// Returns the vehicles which are serving a trip, the counterpart of
+    /// [`CombinedResponse::deadheads`].
+    pub fn in_service(&self) -> impl Iterator<Item = &Entity> {
+        self.entities
+            .iter()
+            .chain(self.unmatched.iter())
+            .filter(|entity| entity.vehicle_service() == Some(VehicleService::InService))
+    }
+
+    /// Returns the JSON document of the snapshot.
+    pub(crate) fn document(&self) -> SnapshotDocument<'_> {
+        let mut entities: Vec<&Entity> = self.entities.iter().collect();
+        entities.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        SnapshotDocument {
+            header: &self.header,
+            entities,
+        }
+    }
+
+    /// Splits the response into its header and merged entities, which is the form
+    /// [`Realtime::fetch_combined`] returned before this type was added.
+    ///
+    /// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+    pub fn into_parts(self) -> (Header, Vec<Entity>) {
+        (self.header, self.entities)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Returns a header with the given timestamp.
+    pub(crate) fn header(timestamp: u64) -> Header {
+        serde_json::from_value(json!({"gtfs_realtime_version": "2.0", "timestamp": timestamp}))
+            .unwrap()
+    }
+
+    /// Returns a snapshot with the given merged entities, in their JSON form.
+    pub(crate) fn snapshot(entities: Value) -> CombinedResponse {
+        CombinedResponse::new(header(1), serde_json::from_value(entities).unwrap())
+    }
+
+    /// Returns a snapshot with the given unmatched entities, in their JSON form.
+    pub(crate) fn unmatched(entities: Value) -> CombinedResponse {
+        CombinedResponse {
+            unmatched: serde_json::from_value(entities).unwrap(),
+            ..CombinedResponse::empty(header(1))
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! Comparison of two snapshots, for before and after analysis of disruptions.
+//!
+//! [`compare`] reports what changed between two snapshots, such as one taken before an incident
+//! and one taken during it: the vehicles which entered and left the feed, how the average delay
+//! of each route changed, and the trips which were cancelled or reinstated in between.
+//!
+//! [`json_patch`] instead reports the changes as an RFC 6902 JSON Patch against the JSON
+//! document of the earlier snapshot, as served from `/realtime.json` by the embedded server, so
+//! web clients can apply small incremental updates rather than downloading every snapshot.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    analysis::vehicle_id,
+    cancellations::{CancellationEvent, CancellationTracker},
+    error::Result,
+    types::gtfs::{Entity, TripDescriptor},
+    CombinedResponse,
+};
+
+/// The average delay of a route in each of two snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub struct RouteDelayChange {
+    /// The mean delay of the trips on the route in the first snapshot, in seconds.
+    pub before: Option<f64>,
+    /// The mean delay of the trips on the route in the second snapshot, in seconds.
+    pub after: Option<f64>,
+}
+
+impl RouteDelayChange {
+    /// Returns how much the mean delay increased from the first snapshot to the second, in
+    /// seconds, or [`None`] if the route has no delays in one of the snapshots.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn change(&self) -> Option<f64> {
+        Some(self.after? - self.before?)
+    }
+}
+
+/// The changes between two snapshots, returned by [`compare`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct SnapshotComparison {
+    /// The IDs of the vehicles in the second snapshot but not the first.
+    pub vehicles_added: Vec<String>,
+    /// The IDs of the vehicles in the first snapshot but not the second.
+    pub vehicles_removed: Vec<String>,
+    /// The mean delay of each route with a delay in either snapshot, by full route ID.
+    pub delays: BTreeMap<String, RouteDelayChange>,
+    /// The trips which are cancelled in the second snapshot but were not in the first.
+    pub cancelled: Vec<TripDescriptor>,
+    /// The trips which were cancelled in the first snapshot and are running in the second.
+    pub reinstated: Vec<TripDescriptor>,
+}
+
+impl SnapshotComparison {
+    /// Returns true if nothing changed between the snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.vehicles_added.is_empty()
+            && self.vehicles_removed.is_empty()
+            && self.cancelled.is_empty()
+            && self.reinstated.is_empty()
+            && self
+                .delays
+                .values()
+                .all(|delay| delay.change() == Some(0.0))
+    }
+}
+
+/// Compares two snapshots.
+///
+/// # Parameters
+///
+/// * `before` - The earlier snapshot.
+/// * `after` - The later snapshot.
+pub fn compare(before: &CombinedResponse, after: &CombinedResponse) -> SnapshotComparison {
+    let vehicles_before = vehicle_ids(before);
+    let vehicles_after = vehicle_ids(after);
+
+    let mut delays: BTreeMap<String, RouteDelayChange> = BTreeMap::new();
+    for (route_id, mean) in mean_delays(before) {
+        delays.entry(route_id).or_default().before = Some(mean);
+    }
+    for (route_id, mean) in mean_delays(after) {
+        delays.entry(route_id).or_default().after = Some(mean);
+    }
+
+    let mut tracker = CancellationTracker::new();
+    tracker.observe(before);
+    let (mut cancelled, mut reinstated) = (vec![], vec![]);
+    for event in tracker.observe(after) {
+        match event {
+            CancellationEvent::Cancelled(trip) => cancelled.push(trip),
+            CancellationEvent::Reinstated(trip) => reinstated.push(trip),
+        }
+    }
+
+    SnapshotComparison {
+        vehicles_added: vehicles_after
+            .difference(&vehicles_before)
+            .map(|id| id.to_string())
+            .collect(),
+        vehicles_removed: vehicles_before
+            .difference(&vehicles_after)
+            .map(|id| id.to_string())
+            .collect(),
+        delays,
+        cancelled,
+        reinstated,
+    }
+}
+
+fn all_entities(combined: &CombinedResponse) -> impl Iterator<Item = &Entity> {
+    combined.entities.iter().chain(combined.unmatched.iter())
+}
+
+fn vehicle_ids(combined: &CombinedResponse) -> BTreeSet<&str> {
+    all_entities(combined).filter_map(vehicle_id).collect()
+}
+
+/// Returns the mean delay of the trips on each route with a delay, in seconds.
+fn mean_delays(combined: &CombinedResponse) -> BTreeMap<String, f64> {
+    let mut totals: BTreeMap<&str, (i64, u32)> = BTreeMap::new();
+    for trip_update in all_entities(combined).filter_map(|e| e.trip_update.as_ref()) {
+        if let (Some(route_id), Some(delay)) =
+            (trip_update.trip.route_id.as_deref(), trip_update.delay)
+        {
+            let total = totals.entry(route_id).or_default();
+            total.0 += i64::from(delay);
+            total.1 += 1;
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|(route_id, (sum, count))| (route_id.to_string(), sum as f64 / f64::from(count)))
+        .collect()
+}
+
+/// An operation of an RFC 6902 JSON Patch. Serializing a list of operations with `serde_json`
+/// gives the patch document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum PatchOperation {
+    /// Adds a value, or appends it to an array if the path ends in `-`.
+    Add {
+        /// The JSON Pointer to add the value at.
+        path: String,
+        /// The value to add.
+        value: Value,
+    },
+    /// Removes the value at a path.
+    Remove {
+        /// The JSON Pointer of the value to remove.
+        path: String,
+    },
+    /// Replaces the value at a path.
+    Replace {
+        /// The JSON Pointer of the value to replace.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+}
+
+/// Returns the JSON Patch which turns the JSON document of one snapshot into that of another.
+///
+/// # Parameters
+///
+/// * `before` - The snapshot the client has.
+/// * `after` - The snapshot to update the client to.
+pub fn json_patch(
+    before: &CombinedResponse,
+    after: &CombinedResponse,
+) -> Result<Vec<PatchOperation>> {
+    let before = serde_json::to_value(before.document())?;
+    let after = serde_json::to_value(after.document())?;
+    Ok(diff_json(&before, &after))
+}
+
+/// Returns the JSON Patch which turns one JSON value into another.
+///
+/// Objects are compared key by key and arrays element by element, so a change deep inside an
+/// entity only replaces the changed field. Arrays of objects with unique `id` fields, such as the
+/// entities of a snapshot, are matched by ID when both are in the same order of IDs, so adding or
+/// removing an entity only adds or removes that entity. Otherwise elements are matched by
+/// position, and elements added to or removed from the end of an array are added or removed
+/// individually.
+///
+/// # Parameters
+///
+/// * `before` - The value to patch.
+/// * `after` - The value the patch produces.
+pub fn diff_json(before: &Value, after: &Value) -> Vec<PatchOperation> {
+    let mut operations = vec![];
+    diff_at(&mut String::new(), before, after, &mut operations);
+    operations
+}
+
+fn diff_at(path: &mut String, before: &Value, after: &Value, operations: &mut Vec<PatchOperation>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            for (key, value) in before {
+                let len = push_token(path, key);
+                match after.get(key) {
+                    Some(new) => diff_at(path, value, new, operations),
+                    None => operations.push(PatchOperation::Remove { path: path.clone() }),
+                }
+                path.truncate(len);
+            }
+            for (key, value) in after {
+                if !before.contains_key(key) {
+                    let len = push_token(path, key);
+                    operations.push(PatchOperation::Add {
+                        path: path.clone(),
+                        value: value.clone(),
+                    });
+                    path.truncate(len);
+                }
+            }
+        }
+        (Value::Array(before), Value::Array(after))
+            if diff_by_id(path, before, after, operations) => {}
+        (Value::Array(before), Value::Array(after)) => {
+            for (index, (old, new)) in before.iter().zip(after).enumerate() {
+                let len = push_token(path, &index.to_string());
+                diff_at(path, old, new, operations);
+                path.truncate(len);
+            }
+            // Removing from the end first keeps the indexes of the remaining elements valid.
+            for index in (after.len()..before.len()).rev() {
+                let len = push_token(path, &index.to_string());
+                operations.push(PatchOperation::Remove { path: path.clone() });
+                path.truncate(len);
+            }
+            for value in after.iter().skip(before.len()) {
+                let len = push_token(path, "-");
+                operations.push(PatchOperation::Add {
+                    path: path.clone(),
+                    value: value.clone(),
+                });
+                path.truncate(len);
+            }
+        }
+        _ if before == after => {}
+        _ => operations.push(PatchOperation::Replace {
+            path: path.clone(),
+            value: after.clone(),
+        }),
+    }
+}
+
+/// Diffs two arrays of objects by their `id` fields, removing the elements whose ID is only in the
+/// first, adding those whose ID is only in the second, and diffing the others in place.
+///
+/// # Returns
+///
+/// Returns false without adding any operations if the elements do not all have unique IDs, or
+/// the IDs in both arrays are not in the same order.
+fn diff_by_id(
+    path: &mut String,
+    before: &[Value],
+    after: &[Value],
+    operations: &mut Vec<PatchOperation>,
+) -> bool {
+    let (before_ids, after_ids) = match (element_ids(before), element_ids(after)) {
+        (Some(before_ids), Some(after_ids)) => (before_ids, after_ids),
+        _ => return false,
+    };
+    let before_set: HashSet<&str> = before_ids.iter().copied().collect();
+    let after_set: HashSet<&str> = after_ids.iter().copied().collect();
+    let kept_before = before_ids.iter().filter(|id| after_set.contains(*id));
+    let kept_after = after_ids.iter().filter(|id| before_set.contains(*id));
+    if !kept_before.eq(kept_after) {
+        return false;
+    }
+
+    // Removing from the end first keeps the indexes of the remaining elements valid.
+    for (index, id) in before_ids.iter().enumerate().rev() {
+        if !after_set.contains(id) {
+            let len = push_token(path, &index.to_string());
+            operations.push(PatchOperation::Remove { path: path.clone() });
+            path.truncate(len);
+        }
+    }
+
+    // The kept elements are now in order, so each new element is inserted at its final index.
+    let mut kept = before
+        .iter()
+        .zip(&before_ids)
+        .filter(|(_, id)| after_set.contains(*id))
+        .map(|(value, _)| value);
+    for (index, (new, id)) in after.iter().zip(&after_ids).enumerate() {
+        let len = push_token(path, &index.to_string());
+        match before_set.contains(id).then(|| kept.next()).flatten() {
+            Some(old) => diff_at(path, old, new, operations),
+            None => operations.push(PatchOperation::Add {
+                path: path.clone(),
+                value: new.clone(),
+            }),
+        }
+        path.truncate(len);
+    }
+    true
+}
+
+/// Returns the `id` field of each element of an array in order, or [`None`] if any element does
+/// not have a string ID or two elements have the same ID.
+///
+/// [`None`]: std::option::Option::None
+fn element_ids(values: &[Value]) -> Option<Vec<&str>> {
+    let mut seen = HashSet::new();
+    let mut ids = Vec::with_capacity(values.len());
+    for value in values {
+        let id = value.get("id")?.as_str()?;
+        if !seen.insert(id) {
+            return None;
+        }
+        ids.push(id);
+    }
+    Some(ids)
+}
+
+/// Appends a reference token to a JSON Pointer, escaping it as RFC 6901 requires.
+///
+/// # Returns
+///
+/// Returns the length of the pointer before the token, to truncate it back to.
+fn push_token(path: &mut String, token: &str) -> usize {
+    let len = path.len();
+    path.push('/');
+    for c in token.chars() {
+        match c {
+            '~' => path.push_str("~0"),
+            '/' => path.push_str("~1"),
+            c => path.push(c),
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::Header;
+
+    fn snapshot(entities: Value) -> CombinedResponse {
+        let header: Header =
+            serde_json::from_value(json!({"gtfs_realtime_version": "2.0", "timestamp": 1}))
+                .unwrap();
+        CombinedResponse::new(header, serde_json::from_value(entities).unwrap())
+    }
+
+    fn vehicle(id: &str, route_id: &str, delay: i32) -> Value {
+        json!({
+            "id": id,
+            "vehicle": {
+                "trip": {"trip_id": format!("trip-{}", id), "route_id": route_id},
+                "vehicle": {"id": id},
+                "position": {"latitude": -36.8, "longitude": 174.7}
+            },
+            "trip_update": {
+                "trip": {"trip_id": format!("trip-{}", id), "route_id": route_id},
+                "delay": delay
+            }
+        })
+    }
+
+    /// Applies a JSON Patch, for checking that a patch turns one document into another.
+    fn apply(mut document: Value, operations: &[PatchOperation]) -> Value {
+        fn parent<'a>(document: &'a mut Value, path: &str) -> (&'a mut Value, String) {
+            let mut tokens: Vec<String> = path
+                .split('/')
+                .skip(1)
+                .map(|token| token.replace("~1", "/").replace("~0", "~"))
+                .collect();
+            let last = tokens.pop().unwrap();
+            let mut value = document;
+            for token in tokens {
+                value = match value {
+                    Value::Array(array) => &mut array[token.parse::<usize>().unwrap()],
+                    value => &mut value[token.as_str()],
+                };
+            }
+            (value, last)
+        }
+
+        for operation in operations {
+            match operation {
+                PatchOperation::Add { path, value } => match parent(&mut document, path) {
+                    (Value::Array(array), last) if last == "-" => array.push(value.clone()),
+                    (Value::Array(array), last) => {
+                        array.insert(last.parse().unwrap(), value.clone())
+                    }
+                    (object, last) => object[last.as_str()] = value.clone(),
+                },
+                PatchOperation::Remove { path } => match parent(&mut document, path) {
+                    (Value::Array(array), last) => {
+                        array.remove(last.parse().unwrap());
+                    }
+                    (Value::Object(object), last) => {
+                        object.remove(&last);
+                    }
+                    _ => panic!("cannot remove {}", path),
+                },
+                PatchOperation::Replace { path, value } => match parent(&mut document, path) {
+                    (Value::Array(array), last) => {
+                        array[last.parse::<usize>().unwrap()] = value.clone()
+                    }
+                    (object, last) => object[last.as_str()] = value.clone(),
+                },
+            }
+        }
+        document
+    }
+
+    #[test]
+    fn one_field_change_is_one_operation() {
+        let before = snapshot(json!([
+            vehicle("a", "r1", 10),
+            vehicle("b", "r1", 20),
+            vehicle("c", "r2", 30)
+        ]));
+        // The same entities in a different order, as merging can return them.
+        let after = snapshot(json!([
+            vehicle("c", "r2", 30),
+            vehicle("b", "r1", 25),
+            vehicle("a", "r1", 10)
+        ]));
+
+        let patch = json_patch(&before, &after).unwrap();
+        assert_eq!(
+            patch,
+            vec![PatchOperation::Replace {
+                path: "/entities/1/trip_update/delay".into(),
+                value: json!(25),
+            }]
+        );
+    }
+
+    #[test]
+    fn entities_are_added_and_removed_by_id() {
+        let before = snapshot(json!([
+            vehicle("a", "r1", 10),
+            vehicle("b", "r1", 20),
+            vehicle("d", "r2", 30)
+        ]));
+        let after = snapshot(json!([
+            vehicle("a", "r1", 10),
+            vehicle("c", "r1", 20),
+            vehicle("d", "r2", 30),
+            vehicle("e", "r2", 40)
+        ]));
+
+        let patch = json_patch(&before, &after).unwrap();
+        assert_eq!(patch.len(), 3);
+        assert_eq!(
+            patch[0],
+            PatchOperation::Remove {
+                path: "/entities/1".into()
+            }
+        );
+
+        let before = serde_json::to_value(before.document()).unwrap();
+        let after = serde_json::to_value(after.document()).unwrap();
+        assert_eq!(apply(before, &patch), after);
+    }
+
+    #[test]
+    fn escapes_pointer_tokens() {
+        let patch = diff_json(&json!({"a/b": 1, "m~n": 1}), &json!({"a/b": 2}));
+        assert_eq!(
+            patch,
+            vec![
+                PatchOperation::Replace {
+                    path: "/a~1b".into(),
+                    value: json!(2),
+                },
+                PatchOperation::Remove {
+                    path: "/m~0n".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_array_tails() {
+        let before = json!({"list": [1, 2, 3, 4]});
+        let shorter = json!({"list": [1, 5]});
+        let longer = json!({"list": [1, 2, 3, 4, 5, 6]});
+
+        let patch = diff_json(&before, &shorter);
+        assert_eq!(
+            patch,
+            vec![
+                PatchOperation::Replace {
+                    path: "/list/1".into(),
+                    value: json!(5),
+                },
+                PatchOperation::Remove {
+                    path: "/list/3".into()
+                },
+                PatchOperation::Remove {
+                    path: "/list/2".into()
+                },
+            ]
+        );
+        assert_eq!(apply(before.clone(), &patch), shorter);
+
+        let patch = diff_json(&before, &longer);
+        assert_eq!(patch.len(), 2);
+        assert!(patch
+            .iter()
+            .all(|op| matches!(op, PatchOperation::Add { path, .. } if path == "/list/-")));
+        assert_eq!(apply(before.clone(), &patch), longer);
+
+        assert!(diff_json(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn compares_vehicles_and_delays() {
+        let before = snapshot(json!([vehicle("a", "r1", 10), vehicle("b", "r1", 20)]));
+        let after = snapshot(json!([vehicle("b", "r1", 40), vehicle("c", "r2", 30)]));
+
+        let comparison = compare(&before, &after);
+        assert_eq!(comparison.vehicles_added, vec!["c"]);
+        assert_eq!(comparison.vehicles_removed, vec!["a"]);
+        assert_eq!(comparison.delays["r1"].change(), Some(25.0));
+        assert_eq!(comparison.delays["r2"].change(), None);
+        assert!(!comparison.is_empty());
+        assert!(compare(&after, &after).is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! Loading client configuration from the environment and from files.
+
+use std::{fs, path::Path, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    ApiVersion, Realtime, RealtimeBuilder,
+};
+
+/// Environment variable which holds the AT API key.
+pub const API_KEY_ENV: &str = "AT_API_KEY";
+
+/// Client configuration, typically loaded from a JSON file with [`Config::from_file`].
+///
+/// ```json
+/// {
+///     "api_key": "...",
+///     "base_url": "https://api.at.govt.nz",
+///     "api_version": "v2",
+///     "timeout_secs": 30
+/// }
+/// ```
+///
+/// Every field is optional. If `api_key` is not set, it is read from the `AT_API_KEY`
+/// environment variable.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[non_exhaustive]
+pub struct Config {
+    /// The API key to use when interacting with the API.
+    pub api_key: Option<String>,
+    /// The base URL requests are sent to.
+    pub base_url: Option<String>,
+    /// The version of the AT API to use.
+    pub api_version: Option<ApiVersion>,
+    /// The request timeout, in seconds.
+    pub timeout_secs: Option<u64>,
+}
+
+impl Config {
+    /// Loads configuration from a JSON file.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the configuration file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Creates a client builder from the configuration.
+    pub fn builder(&self) -> Result<RealtimeBuilder> {
+        let api_key = match self.api_key.clone() {
+            Some(api_key) => api_key,
+            None => api_key_from_env()?,
+        };
+
+        let mut builder = Realtime::builder(api_key);
+        if let Some(base_url) = self.base_url.as_ref() {
+            builder = builder.base_url(base_url.as_str());
+        }
+        if let Some(api_version) = self.api_version {
+            builder = builder.api_version(api_version);
+        }
+        if let Some(timeout) = self.timeout_secs {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Reads the API key from the `AT_API_KEY` environment variable.
+pub(crate) fn api_key_from_env() -> Result<String> {
+    match std::env::var(API_KEY_ENV) {
+        Ok(key) if !key.trim().is_empty() => Ok(key),
+        _ => Err(Error::MissingApiKey),
+    }
+}
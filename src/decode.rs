@@ -0,0 +1,465 @@
+//! Incremental decoding of realtime responses.
+//!
+//! A full realtime response can be several megabytes. Rather than deserializing every entity into
+//! one large `Vec` before merging, [`decode_entities`] passes each entity to a callback as soon as
+//! it has been parsed, and [`Merger`] merges entities as they arrive.
+
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    error::{Error, Result},
+    types::{borrowed::EntityRef, gtfs::Entity, Header},
+    ApiVersion, CombinedResponse,
+};
+
+/// Decodes a raw realtime response, passing each entity to a callback as it is parsed.
+///
+/// The body is accepted in the same forms as the client accepts for the given API version: the
+/// wrapped `{"status", "response"}` form for v2, and either the wrapped form or a bare feed
+/// message for v3. A feed whose entity list is `null` or missing has no entities, rather than
+/// failing to decode.
+///
+/// # Parameters
+///
+/// * `version` - The API version the response was received from.
+/// * `body` - The raw response body.
+/// * `on_entity` - Called with each entity in the order they appear in the response.
+///
+/// # Returns
+///
+/// Returns the header of the response, or [`Error::Decode`] if the body could not be decoded.
+/// Entities parsed before the error was found have already been passed to `on_entity`.
+///
+/// [`Error::Decode`]: crate::error::Error::Decode
+pub fn decode_entities<F>(version: ApiVersion, body: &[u8], on_entity: F) -> Result<Header>
+where
+    F: FnMut(Entity),
+{
+    decode_with(version, body, on_entity)
+}
+
+/// Decodes a raw realtime response into [borrowed entities], which reference strings in the body
+/// instead of allocating them. The body is accepted in the same forms as [`decode_entities`].
+///
+/// # Parameters
+///
+/// * `version` - The API version the response was received from.
+/// * `body` - The raw response body.
+///
+/// # Returns
+///
+/// Returns the header and the unmerged entities of the response.
+///
+/// [borrowed entities]: crate::types::borrowed::EntityRef
+pub fn decode_borrowed(version: ApiVersion, body: &[u8]) -> Result<(Header, Vec<EntityRef<'_>>)> {
+    let mut entities = vec![];
+    let header = decode_with(version, body, |entity| entities.push(entity))?;
+    Ok((header, entities))
+}
+
+/// Decodes a raw realtime response, passing each entity to a callback as it is parsed.
+///
+/// # Parameters
+///
+/// * `version` - The API version the response was received from.
+/// * `body` - The raw response body.
+/// * `on_entity` - Called with each entity in the order they appear in the response.
+fn decode_with<'de, E, F>(version: ApiVersion, body: &'de [u8], mut on_entity: F) -> Result<Header>
+where
+    E: Deserialize<'de>,
+    F: FnMut(E),
+{
+    let shape = match version {
+        ApiVersion::V2 => Shape::Wrapped,
+        ApiVersion::V3 => Shape::Either,
+    };
+
+    let mut de = serde_json::Deserializer::from_slice(body);
+    let header = FeedSeed {
+        on_entity: &mut on_entity,
+        shape,
+        entity: PhantomData,
+    }
+    .deserialize(&mut de)
+    .and_then(|header| de.end().map(|_| header))
+    .map_err(|e| Error::decode(e, body))?;
+    Ok(header)
+}
+
+/// Decodes and merges a raw realtime response in the same way as
+/// [`Realtime::fetch_combined`], without collecting the unmerged entities first.
+///
+/// # Parameters
+///
+/// * `version` - The API version the response was received from.
+/// * `body` - The raw response body.
+///
+/// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+pub fn decode_merged(version: ApiVersion, body: &[u8]) -> Result<CombinedResponse> {
+    let mut merger = Merger::new();
+    let header = decode_entities(version, body, |entity| merger.push(entity))?;
+    Ok(merger.finish(header))
+}
+
+/// Merges trip updates into vehicle positions as entities arrive.
+///
+/// Each entity with a vehicle position on a trip is matched with the trip update entity whose ID
+/// is the ID of that trip, and the trip update is attached to the vehicle. Entities which do not
+/// match are kept separately as [`CombinedResponse::unmatched`]. If several entities share an ID,
+/// the last one wins.
+///
+/// Entities are moved into the merged output rather than cloned. A trip update is only cloned if
+/// more than one vehicle is serving its trip.
+#[derive(Debug, Default)]
+pub struct Merger {
+    /// Entities with a vehicle position, keyed by entity ID. The ID is moved out of the entity
+    /// into the key, and moved back when merging.
+    vehicles: HashMap<String, Entity>,
+    /// The trip updates of entities without a vehicle position, keyed by entity ID (which is the
+    /// trip ID).
+    trip_updates: HashMap<String, TripSlot>,
+}
+
+/// A trip update entity waiting for its trip update to be attached to the vehicles serving its
+/// trip.
+#[derive(Debug)]
+struct TripSlot {
+    entity: Entity,
+    vehicles: usize,
+}
+
+impl Merger {
+    /// Creates an empty merger.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entity to be merged.
+    ///
+    /// # Parameters
+    ///
+    /// * `entity` - The entity to add.
+    pub fn push(&mut self, mut entity: Entity) {
+        let id = std::mem::take(&mut entity.id);
+        if entity.vehicle.is_some() {
+            self.trip_updates.remove(&id);
+            self.vehicles.insert(id, entity);
+        } else {
+            self.vehicles.remove(&id);
+            let slot = TripSlot {
+                entity,
+                vehicles: 0,
+            };
+            self.trip_updates.insert(id, slot);
+        }
+    }
+
+    /// Returns the number of distinct entities added so far.
+    pub fn len(&self) -> usize {
+        self.vehicles.len() + self.trip_updates.len()
+    }
+
+    /// Returns true if no entities have been added.
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty() && self.trip_updates.is_empty()
+    }
+
+    /// Merges the added entities.
+    ///
+    /// # Parameters
+    ///
+    /// * `header` - The header of the response the entities were decoded from.
+    ///
+    /// # Returns
+    ///
+    /// Returns the vehicle positions which are on a trip, with the trip update of that trip
+    /// attached if one was added, along with the entities which could not be merged.
+    pub fn finish(self, header: Header) -> CombinedResponse {
+        if self.is_empty() {
+            return CombinedResponse::empty(header);
+        }
+
+        fn trip_id(entity: &Entity) -> Option<&str> {
+            entity.vehicle.as_ref()?.trip.as_ref()?.trip_id.as_deref()
+        }
+
+        let mut trip_updates = self.trip_updates;
+
+        // Count the vehicles serving each trip, so the last of them can take the trip update
+        // instead of cloning it.
+        for entity in self.vehicles.values() {
+            if let Some(slot) = trip_id(entity).and_then(|id| trip_updates.get_mut(id)) {
+                slot.vehicles += 1;
+            }
+        }
+
+        let mut merged = Vec::with_capacity(self.vehicles.len());
+        let mut unmatched = vec![];
+        for (id, mut entity) in self.vehicles {
+            entity.id = id;
+            let slot = match trip_id(&entity).and_then(|id| trip_updates.get_mut(id)) {
+                Some(slot) => slot,
+                None => {
+                    unmatched.push(entity);
+                    continue;
+                }
+            };
+
+            slot.vehicles -= 1;
+            let trip_update = match slot.vehicles {
+                0 => slot.entity.trip_update.take(),
+                _ => slot.entity.trip_update.clone(),
+            };
+            if trip_update.is_some() {
+                entity.trip_update = trip_update;
+            }
+            merged.push(entity);
+        }
+
+        // Trip updates which were attached to a vehicle have been taken out of their slot.
+        unmatched.extend(trip_updates.into_iter().filter_map(|(id, mut slot)| {
+            slot.entity.trip_update.as_ref()?;
+            slot.entity.id = id;
+            Some(slot.entity)
+        }));
+
+        CombinedResponse {
+            unmatched,
+            ..CombinedResponse::new(header, merged)
+        }
+    }
+}
+
+/// The forms a realtime response body may take.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    /// The `{"status", "response", "error"}` wrapper around a feed message.
+    Wrapped,
+    /// A bare `{"header", "entity"}` feed message.
+    Feed,
+    /// Either of the above.
+    Either,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    Response,
+    Header,
+    Entity,
+    #[serde(other)]
+    Other,
+}
+
+/// Deserializes a response body into its header, passing entities to a callback.
+struct FeedSeed<'f, F, E> {
+    on_entity: &'f mut F,
+    shape: Shape,
+    entity: PhantomData<fn(E)>,
+}
+
+impl<'de, E: Deserialize<'de>, F: FnMut(E)> DeserializeSeed<'de> for FeedSeed<'_, F, E> {
+    type Value = Header;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Header, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, E: Deserialize<'de>, F: FnMut(E)> Visitor<'de> for FeedSeed<'_, F, E> {
+    type Value = Header;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a realtime response")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Header, A::Error> {
+        let mut header = None;
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Response if self.shape != Shape::Feed => {
+                    header = Some(map.next_value_seed(FeedSeed {
+                        on_entity: &mut *self.on_entity,
+                        shape: Shape::Feed,
+                        entity: PhantomData,
+                    })?);
+                }
+                Field::Header if self.shape != Shape::Wrapped => {
+                    header = Some(map.next_value()?);
+                }
+                Field::Entity if self.shape != Shape::Wrapped => {
+                    map.next_value_seed(EntitySeed(&mut *self.on_entity, PhantomData))?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        // AT sometimes leaves out the entities of a feed with none, rather than sending an empty
+        // list, so only the header is required.
+        match header {
+            Some(header) => Ok(header),
+            None if self.shape == Shape::Wrapped => Err(de::Error::missing_field("response")),
+            None => Err(de::Error::missing_field("header")),
+        }
+    }
+}
+
+/// Deserializes a sequence of entities, passing each to a callback. A `null` sequence has no
+/// entities.
+struct EntitySeed<'f, F, E>(&'f mut F, PhantomData<fn(E)>);
+
+impl<'de, E: Deserialize<'de>, F: FnMut(E)> DeserializeSeed<'de> for EntitySeed<'_, F, E> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de, E: Deserialize<'de>, F: FnMut(E)> Visitor<'de> for EntitySeed<'_, F, E> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of entities")
+    }
+
+    fn visit_none<Er: de::Error>(self) -> std::result::Result<(), Er> {
+        Ok(())
+    }
+
+    fn visit_unit<Er: de::Error>(self) -> std::result::Result<(), Er> {
+        Ok(())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        while let Some(entity) = seq.next_element()? {
+            (self.0)(entity);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn header() -> Header {
+        serde_json::from_value(json!({"gtfs_realtime_version": "2.0", "timestamp": 1})).unwrap()
+    }
+
+    fn entity(value: Value) -> Entity {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn vehicle(id: &str, trip_id: &str, stop_id: &str) -> Entity {
+        entity(json!({
+            "id": id,
+            "vehicle": {"trip": {"trip_id": trip_id}, "vehicle": {"id": id}, "stop_id": stop_id}
+        }))
+    }
+
+    fn trip_update(trip_id: &str, delay: i32) -> Entity {
+        entity(
+            json!({"id": trip_id, "trip_update": {"trip": {"trip_id": trip_id}, "delay": delay}}),
+        )
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+        entities.sort_by(|a, b| a.id.cmp(&b.id));
+        entities
+    }
+
+    #[test]
+    fn attaches_trip_updates_to_vehicles() {
+        let mut merger = Merger::new();
+        merger.push(trip_update("t1", 30));
+        merger.push(vehicle("v1", "t1", "s1"));
+        merger.push(vehicle("v2", "t2", "s2"));
+        merger.push(trip_update("t3", 60));
+        assert_eq!(merger.len(), 4);
+
+        let combined = merger.finish(header());
+        assert_eq!(combined.entities.len(), 1);
+        assert_eq!(combined.entities[0].id, "v1");
+        let attached = combined.entities[0].trip_update.as_ref().unwrap();
+        assert_eq!(attached.delay, Some(30));
+
+        let unmatched = sorted(combined.unmatched);
+        let ids: Vec<&str> = unmatched.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["t3", "v2"]);
+    }
+
+    #[test]
+    fn clones_trip_updates_for_each_vehicle_on_a_trip() {
+        let mut merger = Merger::new();
+        merger.push(vehicle("v1", "t1", "s1"));
+        merger.push(trip_update("t1", 45));
+        merger.push(vehicle("v2", "t1", "s1"));
+
+        let combined = merger.finish(header());
+        assert!(combined.unmatched.is_empty());
+        let merged = sorted(combined.entities);
+        assert_eq!(merged.len(), 2);
+        for (entity, id) in merged.iter().zip(["v1", "v2"]) {
+            assert_eq!(entity.id, id);
+            assert_eq!(entity.trip_update.as_ref().unwrap().delay, Some(45));
+        }
+    }
+
+    #[test]
+    fn last_entity_with_an_id_wins() {
+        let mut merger = Merger::new();
+        merger.push(trip_update("t1", 10));
+        merger.push(trip_update("t1", 20));
+        merger.push(vehicle("v1", "t1", "s1"));
+        merger.push(vehicle("v1", "t1", "s2"));
+        // A vehicle position replaces a trip update with the same ID, and the other way around.
+        merger.push(trip_update("x", 0));
+        merger.push(vehicle("x", "t9", "s9"));
+        merger.push(vehicle("y", "t9", "s9"));
+        merger.push(trip_update("y", 5));
+        assert_eq!(merger.len(), 4);
+
+        let combined = merger.finish(header());
+        assert_eq!(combined.entities.len(), 1);
+        let merged = &combined.entities[0];
+        let position = merged.vehicle.as_ref().unwrap();
+        assert_eq!(position.stop_id.as_deref(), Some("s2"));
+        assert_eq!(merged.trip_update.as_ref().unwrap().delay, Some(20));
+
+        let unmatched = sorted(combined.unmatched);
+        assert_eq!(unmatched.len(), 2);
+        assert!(unmatched[0].vehicle.is_some() && unmatched[0].trip_update.is_none());
+        assert!(unmatched[1].vehicle.is_none() && unmatched[1].trip_update.is_some());
+    }
+
+    #[test]
+    fn empty_merger_gives_an_empty_snapshot() {
+        let merger = Merger::new();
+        assert!(merger.is_empty());
+        assert!(merger.finish(header()).is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+//! Monitoring of changes to the schema of AT's responses.
+//!
+//! The decoder ignores fields it does not model, so when AT adds a field to its responses the
+//! crate carries on without it and nobody notices. A [`SchemaMonitor`] attached to a client with
+//! [`Realtime::with_schema_monitor`] compares every raw realtime response against the fields and
+//! enum values the crate knows, and raises a [`SchemaDrift`] event the first time it sees
+//! something new, so maintainers and users notice upstream changes early.
+//!
+//! The monitor parses each response a second time, so it adds to the cost of every fetch.
+//!
+//! [`Realtime::with_schema_monitor`]: crate::Realtime::with_schema_monitor
+
+use std::{collections::HashSet, fmt, sync::Mutex};
+
+use serde_json::Value;
+
+type DriftHook = Box<dyn Fn(&SchemaDrift) + Send + Sync>;
+
+/// A part of AT's responses which the crate does not model.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SchemaDrift {
+    /// An object has a field which the crate does not know.
+    UnknownField {
+        /// The path of the object, such as `entity.vehicle.position`.
+        path: String,
+        /// The name of the field.
+        field: String,
+    },
+    /// An enum field has a value which the crate does not know.
+    UnknownEnumValue {
+        /// The path of the field, such as `entity.vehicle.occupancy_status`.
+        path: String,
+        /// The value, as it appeared in the response.
+        value: String,
+    },
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaDrift::UnknownField { path, field } if path.is_empty() => {
+                write!(f, "unknown field `{}` in the response", field)
+            }
+            SchemaDrift::UnknownField { path, field } => {
+                write!(f, "unknown field `{}` in `{}`", field, path)
+            }
+            SchemaDrift::UnknownEnumValue { path, value } => {
+                write!(f, "unknown value {} of `{}`", value, path)
+            }
+        }
+    }
+}
+
+/// The shape the crate expects a part of a response to have.
+enum Node {
+    /// An object with the given fields. Objects in arrays are checked element by element.
+    Object(&'static [(&'static str, Node)]),
+    /// A number which is one of the given values.
+    Enum(&'static [u64]),
+    /// Any value.
+    Any,
+}
+
+const STOP_TIME_EVENT: Node = Node::Object(&[
+    ("delay", Node::Any),
+    ("time", Node::Any),
+    ("uncertainty", Node::Any),
+]);
+
+const TRIP_DESCRIPTOR: Node = Node::Object(&[
+    ("trip_id", Node::Any),
+    ("route_id", Node::Any),
+    ("direction_id", Node::Any),
+    ("start_time", Node::Any),
+    ("start_date", Node::Any),
+    ("schedule_relationship", Node::Enum(&[0, 1, 2, 3])),
+]);
+
+const VEHICLE_DESCRIPTOR: Node = Node::Object(&[
+    ("id", Node::Any),
+    ("label", Node::Any),
+    ("license_plate", Node::Any),
+]);
+
+const TRIP_UPDATE: Node = Node::Object(&[
+    ("trip", TRIP_DESCRIPTOR),
+    ("vehicle", VEHICLE_DESCRIPTOR),
+    (
+        "stop_time_update",
+        Node::Object(&[
+            ("stop_sequence", Node::Any),
+            ("stop_id", Node::Any),
+            ("arrival", STOP_TIME_EVENT),
+            ("departure", STOP_TIME_EVENT),
+            ("schedule_relationship", Node::Enum(&[0, 1, 2])),
+        ]),
+    ),
+    ("timestamp", Node::Any),
+    ("delay", Node::Any),
+]);
+
+const VEHICLE_POSITION: Node = Node::Object(&[
+    ("trip", TRIP_DESCRIPTOR),
+    ("vehicle", VEHICLE_DESCRIPTOR),
+    (
+        "position",
+        Node::Object(&[
+            ("latitude", Node::Any),
+            ("longitude", Node::Any),
+            ("bearing", Node::Any),
+            ("odometer", Node::Any),
+            ("speed", Node::Any),
+        ]),
+    ),
+    ("current_stop_sequence", Node::Any),
+    ("stop_id", Node::Any),
+    ("current_status", Node::Enum(&[0, 1, 2])),
+    ("timestamp", Node::Any),
+    ("congestion_level", Node::Enum(&[0, 1, 2, 3, 4])),
+    ("occupancy_status", Node::Enum(&[0, 1, 2, 3, 4, 5, 6])),
+]);
+
+const HEADER: Node = Node::Object(&[
+    ("gtfs_realtime_version", Node::Any),
+    ("incrementality", Node::Enum(&[0, 1])),
+    ("timestamp", Node::Any),
+]);
+
+const ENTITY: Node = Node::Object(&[
+    ("id", Node::Any),
+    ("trip_update", TRIP_UPDATE),
+    ("vehicle", VEHICLE_POSITION),
+    ("is_deleted", Node::Any),
+    // Part of GTFS-RT, but not used by AT.
+    ("alert", Node::Any),
+]);
+
+/// A feed message, either at the top level or wrapped in a `response` field.
+const FEED: Node = Node::Object(&[("header", HEADER), ("entity", ENTITY)]);
+
+const RESPONSE: Node = Node::Object(&[
+    ("status", Node::Any),
+    ("error", Node::Any),
+    ("response", FEED),
+    ("header", HEADER),
+    ("entity", ENTITY),
+]);
+
+/// Compares raw realtime responses against the schema the crate knows, and raises each change
+/// once.
+#[derive(Default)]
+pub struct SchemaMonitor {
+    seen: Mutex<HashSet<SchemaDrift>>,
+    on_drift: Vec<DriftHook>,
+}
+
+impl fmt::Debug for SchemaMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemaMonitor")
+            .field("seen", &self.seen)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SchemaMonitor {
+    /// Creates a monitor which has not seen a response yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback which runs the first time each change is seen. With the `tracing`
+    /// feature, a warning is also logged.
+    ///
+    /// # Parameters
+    ///
+    /// * `f` - The callback, called with the change.
+    pub fn on_drift<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SchemaDrift) + Send + Sync + 'static,
+    {
+        self.on_drift.push(Box::new(f));
+        self
+    }
+
+    /// Compares a raw realtime response against the known schema. Bodies which are not JSON are
+    /// ignored, as they fail to decode anyway.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The raw response body received from AT.
+    ///
+    /// # Returns
+    ///
+    /// Returns the changes which had not been seen before, after running the callbacks for them.
+    pub fn inspect(&self, body: &[u8]) -> Vec<SchemaDrift> {
+        let value: Value = match serde_json::from_slice(body) {
+            Ok(value) => value,
+            Err(_) => return vec![],
+        };
+
+        let mut found = vec![];
+        walk(&RESPONSE, &value, &mut String::new(), &mut found);
+
+        let new: Vec<SchemaDrift> = {
+            let mut seen = self.seen.lock().unwrap();
+            found
+                .into_iter()
+                .filter(|drift| seen.insert(drift.clone()))
+                .collect()
+        };
+        for drift in &new {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(%drift, "schema drift in AT response");
+
+            for hook in &self.on_drift {
+                hook(drift);
+            }
+        }
+        new
+    }
+
+    /// Returns every change seen so far.
+    pub fn seen(&self) -> Vec<SchemaDrift> {
+        self.seen.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Compares a value against the shape expected at a path, collecting any differences.
+fn walk(node: &Node, value: &Value, path: &mut String, found: &mut Vec<SchemaDrift>) {
+    match (node, value) {
+        (_, Value::Array(values)) => {
+            for value in values {
+                walk(node, value, path, found);
+            }
+        }
+        (Node::Object(fields), Value::Object(object)) => {
+            for (name, value) in object {
+                match fields.iter().find(|(field, _)| field == name) {
+                    Some((_, node)) => {
+                        let len = path.len();
+                        // The wrapper is left out, so paths are the same in both forms.
+                        if !(path.is_empty() && name == "response") {
+                            if !path.is_empty() {
+                                path.push('.');
+                            }
+                            path.push_str(name);
+                        }
+                        walk(node, value, path, found);
+                        path.truncate(len);
+                    }
+                    None => found.push(SchemaDrift::UnknownField {
+                        path: path.clone(),
+                        field: name.clone(),
+                    }),
+                }
+            }
+        }
+        (Node::Enum(values), Value::Number(number))
+            if !number.as_u64().is_some_and(|n| values.contains(&n)) =>
+        {
+            found.push(SchemaDrift::UnknownEnumValue {
+                path: path.clone(),
+                value: number.to_string(),
+            });
+        }
+        (Node::Enum(_), Value::String(string)) => found.push(SchemaDrift::UnknownEnumValue {
+            path: path.clone(),
+            value: format!("{:?}", string),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    fn unknown_field(path: &str, field: &str) -> SchemaDrift {
+        SchemaDrift::UnknownField {
+            path: path.into(),
+            field: field.into(),
+        }
+    }
+
+    #[test]
+    fn paths_are_the_same_with_and_without_the_wrapper() {
+        let feed = r#"{
+            "header": {"gtfs_realtime_version": "2.0", "source": "at"},
+            "entity": [{"id": "1", "vehicle": {"position": {"latitude": 1, "altitude": 2}}}]
+        }"#;
+        let expected = [
+            unknown_field("entity.vehicle.position", "altitude"),
+            unknown_field("header", "source"),
+        ];
+
+        assert_eq!(SchemaMonitor::new().inspect(feed.as_bytes()), expected);
+        let wrapped = format!(r#"{{"status": "OK", "response": {}, "extra": 1}}"#, feed);
+        let mut found = SchemaMonitor::new().inspect(wrapped.as_bytes());
+        assert_eq!(found.remove(0), unknown_field("", "extra"));
+        assert_eq!(found, expected);
+        assert_eq!(found[1].to_string(), "unknown field `source` in `header`");
+    }
+
+    #[test]
+    fn finds_unknown_enum_values() {
+        let body = br#"{"entity": [
+            {"vehicle": {"occupancy_status": 6, "congestion_level": 5}},
+            {"vehicle": {"current_status": "STOPPED_AT"}},
+            {"trip_update": {"trip": {"schedule_relationship": -1}}}
+        ]}"#;
+        assert_eq!(
+            SchemaMonitor::new().inspect(body),
+            [
+                SchemaDrift::UnknownEnumValue {
+                    path: "entity.vehicle.congestion_level".into(),
+                    value: "5".into(),
+                },
+                SchemaDrift::UnknownEnumValue {
+                    path: "entity.vehicle.current_status".into(),
+                    value: r#""STOPPED_AT""#.into(),
+                },
+                SchemaDrift::UnknownEnumValue {
+                    path: "entity.trip_update.trip.schedule_relationship".into(),
+                    value: "-1".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_each_drift_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let monitor = SchemaMonitor::new().on_drift(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let body = br#"{"header": {"source": "at"}, "entity": [{"a": 1}, {"a": 2}]}"#;
+        assert_eq!(monitor.inspect(body).len(), 2);
+        assert!(monitor.inspect(body).is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let mut seen = monitor.seen();
+        seen.sort_by_key(|drift| drift.to_string());
+        assert_eq!(
+            seen,
+            [
+                unknown_field("entity", "a"),
+                unknown_field("header", "source")
+            ]
+        );
+        assert!(monitor.inspect(b"not json").is_empty());
+    }
+}
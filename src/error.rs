@@ -1,19 +1,425 @@
 //! Error and result types which are passed by the library.
 
-use reqwest::Error as HTTPError;
+use reqwest::{header::InvalidHeaderValue, Error as HTTPError, StatusCode};
+use serde::Deserialize;
+use serde_json::Error as JSONError;
 use std::error::Error as StdError;
 use std::fmt::Display;
 use std::result::Result as StdResult;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
 
 /// The base Result type which is used in the library.
 pub type Result<T> = StdResult<T, Error>;
 
+/// The number of bytes of the body kept before the position of a decode error.
+const FRAGMENT_BEFORE: usize = 80;
+/// The number of bytes of the body kept after the position of a decode error.
+const FRAGMENT_AFTER: usize = 40;
+
 /// An error which is returned from the library.
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
     /// An error occured while interacting with the AT API.
     Request(Box<HTTPError>),
+    /// An error occured while decoding a GTFS-RT protobuf message.
+    Protobuf(String),
+    /// An error occured while reading or writing files.
+    Io(std::io::Error),
+    /// An error occured while serializing or deserializing JSON.
+    Json(JSONError),
+    /// The response body from AT could not be deserialized, such as when a field changes type.
+    Decode {
+        /// The underlying deserialization error, including the line and column it occured at.
+        source: JSONError,
+        /// The part of the body surrounding the position of the error.
+        fragment: String,
+        /// The full response body.
+        body: String,
+        /// The URL the response was received from, if it came from a request.
+        url: Option<String>,
+    },
+    /// An error occured while building a request URL.
+    Url(url::ParseError),
+    /// The response body was not received within the configured read timeout.
+    Timeout {
+        /// The URL the request was sent to.
+        url: String,
+    },
+    /// The response body was larger than the configured maximum response size, and was not read
+    /// any further.
+    ResponseTooLarge {
+        /// The maximum response size in bytes.
+        limit: usize,
+        /// The URL the request was sent to.
+        url: String,
+    },
+    /// No API key was given, and the `AT_API_KEY` environment variable is not set.
+    MissingApiKey,
+    /// A header value (such as the API key) contained invalid characters.
+    InvalidHeader(InvalidHeaderValue),
+    /// An error returned by a custom [`Transport`].
+    ///
+    /// [`Transport`]: crate::transport::Transport
+    Transport(Box<dyn StdError + Send + Sync>),
+    /// AT rejected the API key with `401 Unauthorized`.
+    Unauthorized {
+        /// The URL the request was sent to.
+        url: String,
+        /// The structured error returned by the API gateway, if the body contained one.
+        error: Option<ApiError>,
+    },
+    /// AT refused access with `403 Forbidden`, such as when the subscription does not include
+    /// the requested API.
+    Forbidden {
+        /// The URL the request was sent to.
+        url: String,
+        /// The structured error returned by the API gateway, if the body contained one.
+        error: Option<ApiError>,
+    },
+    /// AT responded with a 5xx status.
+    ServerError {
+        /// The status code of the response.
+        status: StatusCode,
+        /// The body of the response.
+        body: String,
+        /// The URL the request was sent to.
+        url: String,
+    },
+    /// AT responded with an error status not covered by a more specific variant.
+    Api {
+        /// The status code of the response.
+        status: StatusCode,
+        /// The body of the response.
+        body: String,
+        /// The URL the request was sent to.
+        url: String,
+        /// The structured error returned by the API gateway, if the body contained one.
+        error: Option<ApiError>,
+    },
+    /// The API quota was exceeded and AT responded with `429 Too Many Requests`.
+    RateLimited {
+        /// How long AT asked the client to wait before retrying, from the `Retry-After` header.
+        retry_after: Option<Duration>,
+        /// The URL the request was sent to.
+        url: String,
+    },
+    /// The request was not sent because the circuit breaker is open after repeated failures.
+    CircuitOpen {
+        /// How long until the circuit breaker lets a request through again.
+        retry_after: Duration,
+    },
+    /// An error from a request which was shared between concurrent identical calls, as received
+    /// by the calls which waited on the one which sent it. Use the methods of [`Error`], such as
+    /// [`Error::status`], to inspect the error regardless of whether it was shared.
+    Shared(Arc<Error>),
+    /// An error from a request to the AT API which does not carry the URL of the request itself,
+    /// such as an error from a custom [`Transport`] or a body which could not be decoded. Use the
+    /// methods of [`Error`] to inspect the error regardless of whether it is wrapped.
+    ///
+    /// [`Transport`]: crate::transport::Transport
+    WithUrl {
+        /// The URL the request was sent to.
+        url: String,
+        /// The error the request failed with.
+        source: Box<Error>,
+    },
+    /// An error occured while running the embedded HTTP server.
+    #[cfg(feature = "server")]
+    Server(hyper::Error),
+}
+
+/// A structured error returned by the Azure API Management gateway in front of the AT API, such
+/// as when the subscription key is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct ApiError {
+    /// The status code given in the body, which usually matches the response status.
+    #[serde(rename = "statusCode")]
+    pub status_code: Option<u16>,
+    /// The error code, if the gateway gave one.
+    pub code: Option<String>,
+    /// The human readable error message.
+    pub message: String,
+}
+
+impl ApiError {
+    /// Parses an error envelope from a response body, returning [`None`] if the body is not one.
+    ///
+    /// Both the flat `{ "statusCode": .., "message": .. }` format and the nested
+    /// `{ "error": { "code": .., "message": .. } }` format are accepted.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The response body.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Envelope {
+            Nested { error: ApiError },
+            Flat(ApiError),
+        }
+
+        match serde_json::from_slice(body).ok()? {
+            Envelope::Nested { error } | Envelope::Flat(error) => Some(error),
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self.code.as_ref() {
+            Some(code) => write!(f, "{} ({})", self.message, code),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error {
+    /// Creates the error for a response with an unsuccessful status.
+    ///
+    /// # Parameters
+    ///
+    /// * `status` - The status code of the response.
+    /// * `body` - The body of the response.
+    /// * `url` - The URL the request was sent to.
+    pub(crate) fn from_status(status: StatusCode, body: &[u8], url: &str) -> Self {
+        let url = url.to_string();
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized {
+                url,
+                error: ApiError::parse(body),
+            },
+            StatusCode::FORBIDDEN => Self::Forbidden {
+                url,
+                error: ApiError::parse(body),
+            },
+            status if status.is_server_error() => Self::ServerError {
+                status,
+                body: String::from_utf8_lossy(body).into_owned(),
+                url,
+            },
+            status => Self::Api {
+                status,
+                body: String::from_utf8_lossy(body).into_owned(),
+                url,
+                error: ApiError::parse(body),
+            },
+        }
+    }
+
+    /// Creates a decode error for a response body which failed to deserialize.
+    ///
+    /// # Parameters
+    ///
+    /// * `source` - The deserialization error.
+    /// * `body` - The response body.
+    pub(crate) fn decode(source: JSONError, body: &[u8]) -> Self {
+        let offset = body
+            .split(|&b| b == b'\n')
+            .take(source.line().saturating_sub(1))
+            .map(|line| line.len() + 1)
+            .sum::<usize>()
+            + source.column().saturating_sub(1);
+        let offset = offset.min(body.len());
+        let start = offset.saturating_sub(FRAGMENT_BEFORE);
+        let end = (offset + FRAGMENT_AFTER).min(body.len());
+
+        Self::Decode {
+            fragment: String::from_utf8_lossy(&body[start..end]).into_owned(),
+            body: String::from_utf8_lossy(body).into_owned(),
+            url: None,
+            source,
+        }
+    }
+
+    /// Attaches the URL of the request which caused the error, if the error does not already
+    /// carry it. Errors without a field for the URL are wrapped in [`Error::WithUrl`].
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL the request was sent to.
+    pub(crate) fn with_url(self, url: &str) -> Self {
+        match self {
+            Self::Decode {
+                source,
+                fragment,
+                body,
+                url: None,
+            } => Self::Decode {
+                source,
+                fragment,
+                body,
+                url: Some(url.to_string()),
+            },
+            Self::Request(e) if e.url().is_none() => match Url::parse(url) {
+                Ok(url) => Self::Request(Box::new(e.with_url(url))),
+                Err(_) => Self::WithUrl {
+                    url: url.to_string(),
+                    source: Box::new(Self::Request(e)),
+                },
+            },
+            e if e.url().is_some() => e,
+            e => Self::WithUrl {
+                url: url.to_string(),
+                source: Box::new(e),
+            },
+        }
+    }
+
+    /// Returns whether the error is likely to be transient, so that the same request may succeed
+    /// if sent again later.
+    ///
+    /// This is the case for server errors, timeouts, network errors, rate limiting and an open
+    /// circuit breaker. Client errors such as an invalid API key or a response which cannot be
+    /// decoded are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Request(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            Self::Transport(_)
+            | Self::Timeout { .. }
+            | Self::ServerError { .. }
+            | Self::RateLimited { .. }
+            | Self::CircuitOpen { .. } => true,
+            Self::Shared(e) => e.is_retryable(),
+            Self::WithUrl { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the request timed out.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::Request(e) => e.is_timeout(),
+            Self::Timeout { .. } => true,
+            Self::Shared(e) => e.is_timeout(),
+            Self::WithUrl { source, .. } => source.is_timeout(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the request was rejected because the account quota was exceeded.
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } => true,
+            Self::Shared(e) => e.is_rate_limited(),
+            Self::WithUrl { source, .. } => source.is_rate_limited(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the request was rejected because of the API key, either because it is
+    /// missing, invalid or not allowed to access the API.
+    pub fn is_auth(&self) -> bool {
+        match self {
+            Self::MissingApiKey | Self::Unauthorized { .. } | Self::Forbidden { .. } => true,
+            Self::Shared(e) => e.is_auth(),
+            Self::WithUrl { source, .. } => source.is_auth(),
+            _ => false,
+        }
+    }
+
+    /// Returns the status code of the response which caused the error, if the error was caused
+    /// by an error status.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Request(e) => e.status(),
+            Self::Unauthorized { .. } => Some(StatusCode::UNAUTHORIZED),
+            Self::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+            Self::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Self::ServerError { status, .. } | Self::Api { status, .. } => Some(*status),
+            Self::Shared(e) => e.status(),
+            Self::WithUrl { source, .. } => source.status(),
+            _ => None,
+        }
+    }
+
+    /// Returns how long to wait before sending the request again, if AT or the circuit breaker
+    /// said.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } => *retry_after,
+            Self::CircuitOpen { retry_after } => Some(*retry_after),
+            Self::Shared(e) => e.retry_after(),
+            Self::WithUrl { source, .. } => source.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// Copies the error, if it only holds data which can be copied, so it can be shared with
+    /// concurrent calls. Errors which wrap errors from other libraries cannot be copied.
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        Some(match self {
+            Self::Protobuf(message) => Self::Protobuf(message.clone()),
+            Self::Url(e) => Self::Url(*e),
+            Self::Timeout { url } => Self::Timeout { url: url.clone() },
+            Self::ResponseTooLarge { limit, url } => Self::ResponseTooLarge {
+                limit: *limit,
+                url: url.clone(),
+            },
+            Self::MissingApiKey => Self::MissingApiKey,
+            Self::Unauthorized { url, error } => Self::Unauthorized {
+                url: url.clone(),
+                error: error.clone(),
+            },
+            Self::Forbidden { url, error } => Self::Forbidden {
+                url: url.clone(),
+                error: error.clone(),
+            },
+            Self::ServerError { status, body, url } => Self::ServerError {
+                status: *status,
+                body: body.clone(),
+                url: url.clone(),
+            },
+            Self::Api {
+                status,
+                body,
+                url,
+                error,
+            } => Self::Api {
+                status: *status,
+                body: body.clone(),
+                url: url.clone(),
+                error: error.clone(),
+            },
+            Self::RateLimited { retry_after, url } => Self::RateLimited {
+                retry_after: *retry_after,
+                url: url.clone(),
+            },
+            Self::CircuitOpen { retry_after } => Self::CircuitOpen {
+                retry_after: *retry_after,
+            },
+            Self::Shared(e) => Self::Shared(Arc::clone(e)),
+            Self::WithUrl { url, source } => Self::WithUrl {
+                url: url.clone(),
+                source: Box::new(source.try_clone()?),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Returns the URL, including query parameters, of the request which caused the error, if
+    /// the error was caused by a request.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Self::Request(e) => e.url().map(Url::as_str),
+            Self::Decode { url, .. } => url.as_deref(),
+            Self::Timeout { url }
+            | Self::ResponseTooLarge { url, .. }
+            | Self::Unauthorized { url, .. }
+            | Self::Forbidden { url, .. }
+            | Self::ServerError { url, .. }
+            | Self::Api { url, .. }
+            | Self::RateLimited { url, .. }
+            | Self::WithUrl { url, .. } => Some(url),
+            Self::Shared(e) => e.url(),
+            _ => None,
+        }
+    }
 }
 
 impl From<HTTPError> for Error {
@@ -22,12 +428,250 @@ impl From<HTTPError> for Error {
     }
 }
 
-impl StdError for Error {}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<JSONError> for Error {
+    fn from(e: JSONError) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Self::Url(e)
+    }
+}
+
+impl From<InvalidHeaderValue> for Error {
+    fn from(e: InvalidHeaderValue) -> Self {
+        Self::InvalidHeader(e)
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Self::Server(e)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Request(e) => Some(e.as_ref()),
+            Error::Io(e) => Some(e),
+            Error::Json(e) | Error::Decode { source: e, .. } => Some(e),
+            Error::Url(e) => Some(e),
+            Error::InvalidHeader(e) => Some(e),
+            Error::Transport(e) => Some(e.as_ref()),
+            // A shared error is displayed as the error it wraps, so it has the same source.
+            Error::Shared(e) => e.source(),
+            Error::WithUrl { source, .. } => Some(source.as_ref()),
+            #[cfg(feature = "server")]
+            Error::Server(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         match self {
             Error::Request(e) => write!(f, "HTTP request error: {}", e),
+            Error::Protobuf(e) => write!(f, "GTFS-RT protobuf decode error: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Decode {
+                source,
+                fragment,
+                url: Some(url),
+                ..
+            } => write!(
+                f,
+                "failed to decode response from {}: {} near `{}`",
+                url, source, fragment
+            ),
+            Error::Decode {
+                source, fragment, ..
+            } => write!(
+                f,
+                "failed to decode response: {} near `{}`",
+                source, fragment
+            ),
+            Error::Url(e) => write!(f, "URL error: {}", e),
+            Error::Timeout { url } => {
+                write!(f, "timed out while reading the response body from {}", url)
+            }
+            Error::ResponseTooLarge { limit, url } => write!(
+                f,
+                "response body from {} exceeded the maximum size of {} bytes",
+                url, limit
+            ),
+            Error::MissingApiKey => write!(
+                f,
+                "no API key was given, and the AT_API_KEY environment variable is not set"
+            ),
+            Error::InvalidHeader(e) => write!(f, "invalid header value: {}", e),
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Unauthorized { url, error } => {
+                write!(f, "AT API rejected the API key for {}", url)?;
+                match error {
+                    Some(error) => write!(f, ": {}", error),
+                    None => Ok(()),
+                }
+            }
+            Error::Forbidden { url, error } => {
+                write!(f, "AT API refused access to {}", url)?;
+                match error {
+                    Some(error) => write!(f, ": {}", error),
+                    None => Ok(()),
+                }
+            }
+            Error::ServerError { status, url, .. } => {
+                write!(f, "AT API server error {} for {}", status, url)
+            }
+            Error::Api {
+                status,
+                url,
+                error: Some(error),
+                ..
+            } => write!(f, "AT API returned {} for {}: {}", status, url, error),
+            Error::Api { status, url, .. } => {
+                write!(f, "AT API returned {} for {}", status, url)
+            }
+            Error::RateLimited {
+                retry_after: Some(retry_after),
+                url,
+            } => write!(
+                f,
+                "rate limited by the AT API for {}, retry after {} seconds",
+                url,
+                retry_after.as_secs()
+            ),
+            Error::RateLimited {
+                retry_after: None,
+                url,
+            } => write!(f, "rate limited by the AT API for {}", url),
+            Error::CircuitOpen { retry_after } => write!(
+                f,
+                "circuit breaker is open after repeated failures, retry after {} seconds",
+                retry_after.as_secs()
+            ),
+            Error::Shared(e) => write!(f, "{}", e),
+            Error::WithUrl { url, source } => write!(f, "request to {} failed: {}", url, source),
+            #[cfg(feature = "server")]
+            Error::Server(e) => write!(f, "HTTP server error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_see_through_shared_errors() {
+        let error = Error::from_status(StatusCode::NOT_FOUND, b"", "https://api.at.govt.nz/x");
+        let shared = Error::Shared(Arc::new(error.try_clone().unwrap()));
+
+        assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(shared.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(shared.url(), Some("https://api.at.govt.nz/x"));
+        assert!(!shared.is_retryable());
+
+        let rate_limited = Error::Shared(Arc::new(Error::RateLimited {
+            retry_after: Some(Duration::from_secs(30)),
+            url: String::new(),
+        }));
+        assert_eq!(rate_limited.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(rate_limited.retry_after(), Some(Duration::from_secs(30)));
+        assert!(rate_limited.is_rate_limited());
+    }
+
+    #[test]
+    fn exposes_sources() {
+        let json = serde_json::from_str::<u8>("x").unwrap_err();
+        let message = json.to_string();
+        let error = Error::decode(json, b"x");
+        assert_eq!(error.source().unwrap().to_string(), message);
+
+        let shared = Error::Shared(Arc::new(error));
+        assert_eq!(shared.source().unwrap().to_string(), message);
+
+        let url = Url::parse("not a url").unwrap_err();
+        assert_eq!(
+            Error::from(url).source().unwrap().to_string(),
+            url.to_string()
+        );
+        assert!(Error::MissingApiKey.source().is_none());
+    }
+
+    #[test]
+    fn copies_only_plain_errors() {
+        let timeout = Error::Timeout { url: "u".into() };
+        assert!(matches!(timeout.try_clone(), Some(Error::Timeout { url }) if url == "u"));
+
+        let json = Error::Json(serde_json::from_str::<u8>("x").unwrap_err());
+        assert!(json.try_clone().is_none());
+    }
+
+    #[test]
+    fn every_error_gets_a_url() {
+        let url = "https://api.at.govt.nz/x";
+
+        let transport = Error::Transport("connection reset".into()).with_url(url);
+        assert_eq!(transport.url(), Some(url));
+        assert!(transport.is_retryable());
+        assert_eq!(
+            transport.to_string(),
+            "request to https://api.at.govt.nz/x failed: transport error: connection reset"
+        );
+        assert_eq!(
+            transport.source().unwrap().to_string(),
+            "transport error: connection reset"
+        );
+
+        let io = Error::Io(std::io::ErrorKind::UnexpectedEof.into()).with_url(url);
+        assert_eq!(io.url(), Some(url));
+        let json = Error::Json(serde_json::from_str::<u8>("x").unwrap_err()).with_url(url);
+        assert_eq!(json.url(), Some(url));
+        let decode = Error::decode(serde_json::from_str::<u8>("x").unwrap_err(), b"x");
+        assert!(matches!(
+            decode.with_url(url),
+            Error::Decode { url: Some(_), .. }
+        ));
+
+        // Errors which already carry a URL are left as they are.
+        let timeout = Error::Timeout { url: "u".into() }.with_url(url);
+        assert!(matches!(timeout, Error::Timeout { url } if url == "u"));
+        let server = Error::from_status(StatusCode::BAD_GATEWAY, b"", "u").with_url(url);
+        assert_eq!(server.url(), Some("u"));
+        assert_eq!(server.status(), Some(StatusCode::BAD_GATEWAY));
+
+        let wrapped = Error::Protobuf("bad tag".into()).with_url(url);
+        assert!(matches!(wrapped.try_clone(), Some(Error::WithUrl { .. })));
+    }
+
+    #[test]
+    fn parses_api_errors() {
+        let flat = ApiError::parse(br#"{"statusCode": 401, "message": "Access denied"}"#);
+        assert_eq!(flat.unwrap().message, "Access denied");
+
+        let unauthorized = Error::from_status(
+            StatusCode::UNAUTHORIZED,
+            br#"{"error": {"code": "InvalidKey", "message": "Invalid key"}}"#,
+            "u",
+        );
+        match unauthorized {
+            Error::Unauthorized {
+                error: Some(error), ..
+            } => assert_eq!(error.code.as_deref(), Some("InvalidKey")),
+            e => panic!("unexpected error {:?}", e),
         }
+        assert!(ApiError::parse(b"<html>").is_none());
     }
 }
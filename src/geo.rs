@@ -0,0 +1,41 @@
+//! Geometry helpers shared by the simulator and the analysis trackers.
+
+/// Mean radius of the earth in metres.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Returns the distance between two points in metres, using the equirectangular approximation.
+pub(crate) fn distance(a: (f32, f32), b: (f32, f32)) -> f64 {
+    let (lat1, lon1) = (f64::from(a.0).to_radians(), f64::from(a.1).to_radians());
+    let (lat2, lon2) = (f64::from(b.0).to_radians(), f64::from(b.1).to_radians());
+    let x = (lon2 - lon1) * ((lat1 + lat2) / 2.0).cos();
+    let y = lat2 - lat1;
+    (x * x + y * y).sqrt() * EARTH_RADIUS
+}
+
+/// Projects a point onto the line between two points, using the same approximation as
+/// [`distance`].
+///
+/// # Returns
+///
+/// Returns the distance from `a` to the nearest point on the line, and the distance from the
+/// point to the line, both in metres.
+pub(crate) fn project(point: (f32, f32), a: (f32, f32), b: (f32, f32)) -> (f64, f64) {
+    let scale = f64::from(a.0).to_radians().cos();
+    let to_plane = |p: (f32, f32)| {
+        (
+            (f64::from(p.1) - f64::from(a.1)).to_radians() * scale * EARTH_RADIUS,
+            (f64::from(p.0) - f64::from(a.0)).to_radians() * EARTH_RADIUS,
+        )
+    };
+    let (px, py) = to_plane(point);
+    let (bx, by) = to_plane(b);
+
+    let length = bx * bx + by * by;
+    let t = if length > 0.0 {
+        ((px * bx + py * by) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (dx, dy) = (px - t * bx, py - t * by);
+    (t * length.sqrt(), (dx * dx + dy * dy).sqrt())
+}
@@ -0,0 +1,59 @@
+//! Connectivity checks for readiness probes and startup.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::error::Error;
+
+/// The result of [`Realtime::health_check`].
+///
+/// [`Realtime::health_check`]: crate::Realtime::health_check
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Health {
+    /// AT accepted the API key and responded successfully.
+    Ok {
+        /// How long the request took.
+        latency: Duration,
+    },
+    /// AT rejected the API key with `401 Unauthorized`.
+    InvalidKey,
+    /// AT refused access with `403 Forbidden`, such as when the subscription of the API key does
+    /// not include the realtime API.
+    Forbidden,
+    /// The API quota is exceeded.
+    RateLimited {
+        /// How long AT asked the client to wait, from the `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
+    /// AT could not be reached or responded with an error, such as a server error or a timeout.
+    Unavailable(Error),
+}
+
+impl Health {
+    /// Classifies the result of the request sent by a health check.
+    ///
+    /// # Parameters
+    ///
+    /// * `result` - The result of the request.
+    /// * `latency` - How long the request took.
+    pub(crate) fn from_result<T>(result: Result<T, Error>, latency: Duration) -> Self {
+        match result {
+            Ok(_) => Health::Ok { latency },
+            Err(e) => match e.status() {
+                Some(StatusCode::UNAUTHORIZED) => Health::InvalidKey,
+                Some(StatusCode::FORBIDDEN) => Health::Forbidden,
+                Some(StatusCode::TOO_MANY_REQUESTS) => Health::RateLimited {
+                    retry_after: e.retry_after(),
+                },
+                _ => Health::Unavailable(e),
+            },
+        }
+    }
+
+    /// Returns true if the check succeeded.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Health::Ok { .. })
+    }
+}
@@ -0,0 +1,89 @@
+//! Callbacks which run as the polling stream fetches new data.
+
+use crate::{
+    error::Error,
+    types::{gtfs::Entity, Header},
+    CombinedResponse,
+};
+
+type SnapshotHook = Box<dyn Fn(&Header, &[Entity]) + Send + Sync>;
+type ErrorHook = Box<dyn Fn(&Error) + Send + Sync>;
+
+/// A set of callbacks run by [`Realtime::stream_with_hooks`].
+///
+/// Callbacks run before the corresponding item is yielded from the stream, in the order they
+/// were registered.
+///
+/// [`Realtime::stream_with_hooks`]: crate::Realtime::stream_with_hooks
+#[derive(Default)]
+pub struct StreamHooks {
+    on_fetch: Vec<SnapshotHook>,
+    on_error: Vec<ErrorHook>,
+    on_snapshot: Vec<SnapshotHook>,
+}
+
+impl StreamHooks {
+    /// Creates an empty set of hooks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback which runs after every successful fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `f` - The callback, called with the header and merged entities fetched.
+    pub fn on_fetch<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Header, &[Entity]) + Send + Sync + 'static,
+    {
+        self.on_fetch.push(Box::new(f));
+        self
+    }
+
+    /// Registers a callback which runs after every failed fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `f` - The callback, called with the error returned by the fetch.
+    pub fn on_error<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.on_error.push(Box::new(f));
+        self
+    }
+
+    /// Registers a callback which runs when a fetch returns a new snapshot, that is when the
+    /// header timestamp differs from the previous successful fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `f` - The callback, called with the header and merged entities of the new snapshot.
+    pub fn on_snapshot<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Header, &[Entity]) + Send + Sync + 'static,
+    {
+        self.on_snapshot.push(Box::new(f));
+        self
+    }
+
+    /// Runs the callbacks for a fetch result.
+    ///
+    /// # Parameters
+    ///
+    /// * `result` - The result of the fetch.
+    /// * `changed` - Whether the fetch returned a new snapshot.
+    pub(crate) fn run(&self, result: &Result<CombinedResponse, Error>, changed: bool) {
+        match result {
+            Ok(combined) => {
+                let (header, entities) = (&combined.header, &combined.entities);
+                self.on_fetch.iter().for_each(|f| f(header, entities));
+                if changed {
+                    self.on_snapshot.iter().for_each(|f| f(header, entities));
+                }
+            }
+            Err(e) => self.on_error.iter().for_each(|f| f(e)),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Lists of trip and vehicle IDs used to filter fetches.
+
+use std::iter::FromIterator;
+
+/// A list of trip or vehicle IDs to search for, or no filter at all.
+///
+/// The fetch methods accept anything which converts into `Ids`, so IDs can be passed as arrays,
+/// slices or vectors of either borrowed or owned strings without building a temporary
+/// `Vec<&str>` first, such as `realtime.fetch_combined(None, &vehicle_ids)` with a
+/// `Vec<String>`. [`None`] means all trips or vehicles.
+///
+/// [`None`]: std::option::Option::None
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ids {
+    /// The IDs joined with commas, as sent in the query string.
+    joined: Option<String>,
+    len: usize,
+}
+
+impl Ids {
+    /// Returns a filter which matches all trips or vehicles.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Creates a list of IDs.
+    ///
+    /// # Parameters
+    ///
+    /// * `ids` - The IDs to search for.
+    pub fn new<I>(ids: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut joined = String::new();
+        let mut len = 0;
+        for id in ids {
+            if len > 0 {
+                joined.push(',');
+            }
+            joined.push_str(id.as_ref());
+            len += 1;
+        }
+
+        Self {
+            joined: Some(joined),
+            len,
+        }
+    }
+
+    /// Returns true if this matches all trips or vehicles.
+    pub fn is_all(&self) -> bool {
+        self.joined.is_none()
+    }
+
+    /// Returns the number of IDs in the list, which is zero if this matches all trips or
+    /// vehicles.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the list has no IDs, either because it is empty or because this matches
+    /// all trips or vehicles.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the list contains the given ID. Always false if this matches all trips
+    /// or vehicles.
+    ///
+    /// # Parameters
+    ///
+    /// * `id` - The ID to look for.
+    pub fn contains(&self, id: &str) -> bool {
+        self.joined
+            .as_deref()
+            .is_some_and(|joined| joined.split(',').any(|i| i == id))
+    }
+
+    /// Returns the IDs joined with commas, or [`None`] if this matches all trips or vehicles.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub(crate) fn as_query(&self) -> Option<&str> {
+        self.joined.as_deref()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Ids {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self::new(iter)
+    }
+}
+
+impl From<Option<&Vec<&str>>> for Ids {
+    fn from(ids: Option<&Vec<&str>>) -> Self {
+        ids.map(Self::new).unwrap_or_default()
+    }
+}
+
+impl<S: AsRef<str>> From<Vec<S>> for Ids {
+    fn from(ids: Vec<S>) -> Self {
+        Self::new(ids)
+    }
+}
+
+impl<S: AsRef<str>> From<&Vec<S>> for Ids {
+    fn from(ids: &Vec<S>) -> Self {
+        Self::new(ids)
+    }
+}
+
+impl<S: AsRef<str>> From<&[S]> for Ids {
+    fn from(ids: &[S]) -> Self {
+        Self::new(ids)
+    }
+}
+
+impl<S: AsRef<str>, const N: usize> From<[S; N]> for Ids {
+    fn from(ids: [S; N]) -> Self {
+        Self::new(ids)
+    }
+}
+
+impl From<&str> for Ids {
+    fn from(id: &str) -> Self {
+        Self::new([id])
+    }
+}
+
+impl From<&Ids> for Ids {
+    fn from(ids: &Ids) -> Self {
+        ids.clone()
+    }
+}
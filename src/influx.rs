@@ -0,0 +1,176 @@
+//! Conversion of realtime data into the InfluxDB line protocol.
+//!
+//! Each vehicle in a snapshot is written as one point, tagged with the vehicle, route and trip
+//! IDs, so service health (delays, speeds and occupancy) can be plotted per vehicle or per route.
+//! See the [line protocol reference](https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/)
+//! for details on the format.
+
+use std::fmt::Write;
+
+use crate::types::{gtfs::Entity, Header};
+
+/// Converts a snapshot into InfluxDB line protocol, one line per vehicle.
+///
+/// The following fields are written when present: `delay` (seconds), `speed`, `latitude`,
+/// `longitude`, `bearing`, `occupancy_status`, `congestion_level` and `current_status`. Values
+/// which are NaN or infinite are left out, and entities without any of these fields are skipped,
+/// as a point must have at least one field. Points are timestamped with the vehicle timestamp (falling back to the header timestamp) in nanosecond
+/// precision.
+///
+/// # Parameters
+///
+/// * `measurement` - The measurement to write points to.
+/// * `header` - The response header received from AT.
+/// * `entities` - The entities to convert.
+///
+/// # Returns
+///
+/// Returns the points, separated by newlines.
+pub fn encode_entities(measurement: &str, header: &Header, entities: &[Entity]) -> String {
+    let mut out = String::new();
+
+    for entity in entities {
+        let vehicle = entity.vehicle.as_ref();
+        let mut fields = vec![];
+
+        if let Some(delay) = entity.trip_update.as_ref().and_then(|tu| tu.delay) {
+            fields.push(("delay", format!("{}i", delay)));
+        }
+
+        if let Some(position) = vehicle.and_then(|v| v.position.as_ref()) {
+            let floats = [
+                ("speed", position.speed),
+                ("latitude", Some(position.latitude)),
+                ("longitude", Some(position.longitude)),
+                ("bearing", position.bearing),
+            ];
+            for (key, value) in floats.iter() {
+                // The line protocol has no representation of NaN or infinity.
+                if let Some(value) = value.filter(|value| value.is_finite()) {
+                    fields.push((*key, value.to_string()));
+                }
+            }
+        }
+
+        if let Some(vehicle) = vehicle {
+            if let Some(occupancy) = vehicle.occupancy_status {
+                fields.push(("occupancy_status", format!("{}i", occupancy as u8)));
+            }
+            if let Some(congestion) = vehicle.congestion_level {
+                fields.push(("congestion_level", format!("{}i", congestion as u8)));
+            }
+            fields.push((
+                "current_status",
+                format!("{}i", vehicle.current_status as u8),
+            ));
+        }
+
+        if fields.is_empty() {
+            continue;
+        }
+
+        let vehicle_id = vehicle
+            .and_then(|v| v.vehicle.as_ref())
+            .and_then(|v| v.id.as_deref())
+            .unwrap_or(&entity.id);
+        let route_id = entity
+            .route_id()
+            .or_else(|| vehicle?.trip.as_ref()?.route_id.clone());
+        let trip_id = entity
+            .trip_id()
+            .or_else(|| vehicle?.trip.as_ref()?.trip_id.clone());
+
+        out.push_str(&escape(measurement, &[',', ' ']));
+        write_tag(&mut out, "vehicle_id", Some(vehicle_id));
+        write_tag(&mut out, "route_id", route_id.as_deref());
+        write_tag(&mut out, "trip_id", trip_id.as_deref());
+
+        for (i, (key, value)) in fields.iter().enumerate() {
+            out.push(if i == 0 { ' ' } else { ',' });
+            let _ = write!(out, "{}={}", key, value);
+        }
+
+        let timestamp = vehicle
+            .and_then(|v| v.timestamp)
+            .or_else(|| header.timestamp.map(|t| t as u64));
+        if let Some(timestamp) = timestamp {
+            let _ = write!(out, " {}", u128::from(timestamp) * 1_000_000_000);
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+fn write_tag(out: &mut String, key: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|v| !v.is_empty()) {
+        let _ = write!(out, ",{}={}", key, escape(value, &[',', '=', ' ']));
+    }
+}
+
+/// Escapes the given special characters with a backslash.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::combined::tests::header;
+
+    #[test]
+    fn encodes_escaped_points() {
+        let entities: Vec<Entity> = serde_json::from_value(json!([{
+            "id": "e1",
+            "trip_update": {"trip": {"trip_id": "t1-202", "route_id": "82-202"}, "delay": 90},
+            "vehicle": {
+                "vehicle": {"id": "bus 1,a=b"},
+                "position": {"latitude": -36.85, "longitude": 174.76, "speed": 12.5},
+                "current_status": 2,
+                "congestion_level": 1,
+                "timestamp": 1_700_000_000
+            }
+        }]))
+        .unwrap();
+
+        assert_eq!(
+            encode_entities("bus positions,v2", &header(1), &entities),
+            "bus\\ positions\\,v2,vehicle_id=bus\\ 1\\,a\\=b,route_id=82,trip_id=t1 \
+             delay=90i,speed=12.5,latitude=-36.85,longitude=174.76,congestion_level=1i,\
+             current_status=2i 1700000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn leaves_out_non_finite_values() {
+        let mut entities: Vec<Entity> = serde_json::from_value(json!([
+            {"id": "e1", "vehicle": {"position": {"latitude": 0.0, "longitude": 174.76}}},
+            {"id": "e2", "trip_update": {"trip": {}}},
+        ]))
+        .unwrap();
+        let position = entities[0]
+            .vehicle
+            .as_mut()
+            .and_then(|v| v.position.as_mut())
+            .unwrap();
+        position.latitude = f32::NAN;
+        position.speed = Some(f32::INFINITY);
+        position.bearing = Some(f32::NEG_INFINITY);
+
+        // The second entity has no fields, and the header timestamp is used for the first.
+        assert_eq!(
+            encode_entities("m", &header(1_700_000_000), &entities),
+            "m,vehicle_id=e1 longitude=174.76,current_status=2i 1700000000000000000\n"
+        );
+    }
+}
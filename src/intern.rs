@@ -0,0 +1,174 @@
+//! Interning of the IDs which repeat across entities and snapshots.
+//!
+//! The same trip, route, stop and vehicle IDs appear in every snapshot, so an application which
+//! keeps many snapshots around holds many copies of each. An [`Interner`] hands out one shared
+//! [`Arc<str>`] per distinct ID instead, and [`EntityIds`] holds the interned IDs of an entity
+//! for trackers which only need to keep the IDs.
+//!
+//! The trackers of this crate, such as [`SpeedTracker`] and [`CancellationTracker`], intern the
+//! IDs they keep between snapshots. Pass them the same interner to share one copy of each ID
+//! between them.
+//!
+//! [`SpeedTracker`]: crate::analysis::speed::SpeedTracker
+//! [`CancellationTracker`]: crate::cancellations::CancellationTracker
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use crate::types::gtfs::Entity;
+
+/// A pool of interned strings, where identical strings share one allocation.
+///
+/// Cloning an interner is cheap and the clones share the same pool, so one interner can be used
+/// across tasks and snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    strings: Arc<Mutex<HashSet<Arc<str>>>>,
+}
+
+/// The interned IDs of an entity. The trip, route and stop IDs are the full IDs sent by AT,
+/// including the GTFS version.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct EntityIds {
+    /// The ID of the entity.
+    pub id: Arc<str>,
+    /// The ID of the trip the vehicle is serving, or the trip of the trip update.
+    pub trip_id: Option<Arc<str>>,
+    /// The ID of the route of the trip.
+    pub route_id: Option<Arc<str>>,
+    /// The ID of the current stop of the vehicle, or the stop of the trip update.
+    pub stop_id: Option<Arc<str>>,
+    /// The ID of the vehicle.
+    pub vehicle_id: Option<Arc<str>>,
+}
+
+impl Interner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared copy of a string, adding it to the pool if it has not been seen before.
+    ///
+    /// # Parameters
+    ///
+    /// * `s` - The string to intern.
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap();
+        match strings.get(s) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Arc<str> = s.into();
+                strings.insert(interned.clone());
+                interned
+            }
+        }
+    }
+
+    /// Interns the IDs of an entity.
+    ///
+    /// # Parameters
+    ///
+    /// * `entity` - The entity to intern the IDs of.
+    pub fn entity_ids(&self, entity: &Entity) -> EntityIds {
+        let vehicle = entity.vehicle.as_ref();
+        let trip_update = entity.trip_update.as_ref();
+        let trip = vehicle
+            .and_then(|v| v.trip.as_ref())
+            .or(trip_update.map(|tu| &tu.trip));
+
+        EntityIds {
+            id: self.intern(&entity.id),
+            trip_id: self.intern_opt(trip.and_then(|t| t.trip_id.as_deref())),
+            route_id: self.intern_opt(trip.and_then(|t| t.route_id.as_deref())),
+            stop_id: self.intern_opt(
+                vehicle
+                    .and_then(|v| v.stop_id.as_deref())
+                    .or_else(|| trip_update?.stop_time_update.as_ref()?.stop_id.as_deref()),
+            ),
+            vehicle_id: self.intern_opt(
+                vehicle
+                    .and_then(|v| v.vehicle.as_ref())
+                    .or(trip_update.and_then(|tu| tu.vehicle.as_ref()))
+                    .and_then(|v| v.id.as_deref()),
+            ),
+        }
+    }
+
+    /// Returns the number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap().len()
+    }
+
+    /// Returns true if the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.strings.lock().unwrap().is_empty()
+    }
+
+    /// Removes the strings which are no longer used outside of the pool, such as the IDs of trips
+    /// which have finished. Applications which run for a long time should call this
+    /// periodically, as the pool otherwise grows with every new ID.
+    pub fn shrink(&self) {
+        self.strings
+            .lock()
+            .unwrap()
+            .retain(|s| Arc::strong_count(s) > 1);
+    }
+
+    fn intern_opt(&self, s: Option<&str>) -> Option<Arc<str>> {
+        s.map(|s| self.intern(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{cancellations::CancellationTracker, types::Header, CombinedResponse};
+
+    #[test]
+    fn shares_one_copy_of_each_string() {
+        let interner = Interner::new();
+        let a = interner.intern("trip-1");
+        let b = interner.intern(&String::from("trip-1"));
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(interner.len(), 1);
+
+        drop((a, b));
+        interner.shrink();
+        assert!(interner.is_empty());
+    }
+
+    #[test]
+    fn trackers_release_ids_which_leave_the_feed() {
+        let header: Header =
+            serde_json::from_value(json!({"gtfs_realtime_version": "2.0", "timestamp": 1}))
+                .unwrap();
+        let cancelled = serde_json::from_value(json!([{
+            "id": "1",
+            "trip_update": {"trip": {"trip_id": "trip-1", "schedule_relationship": 3}}
+        }]))
+        .unwrap();
+
+        let interner = Interner::new();
+        let mut tracker = CancellationTracker::new().interner(interner.clone());
+        assert_eq!(
+            tracker
+                .observe(&CombinedResponse::new(header.clone(), cancelled))
+                .len(),
+            1
+        );
+        assert!(Arc::ptr_eq(
+            &interner.intern("trip-1"),
+            &interner.intern("trip-1")
+        ));
+        assert_eq!(interner.len(), 1);
+
+        tracker.observe(&CombinedResponse::empty(header));
+        assert!(interner.is_empty());
+    }
+}
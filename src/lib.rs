@@ -3,11 +3,75 @@
 //! Tools for interacting with the [Auckland Transport API](https://dev-portal.at.govt.nz/).
 //! You must register to receive an API key to use this library.
 
+pub mod analysis;
+pub mod api;
+pub mod archive;
+pub mod arrivals;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod breaker;
+pub mod buffer;
+mod builder;
+pub mod cache;
+pub mod cancellations;
+mod client;
+mod combined;
+pub mod compare;
+mod config;
+pub mod decode;
+pub mod drift;
 pub mod error;
+mod geo;
+pub mod health;
+pub mod hooks;
+mod ids;
+pub mod influx;
+pub mod intern;
+pub mod limiter;
+pub mod live;
+mod meta;
+pub mod middleware;
+mod options;
+mod outcome;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod protobuf;
+pub mod query;
+mod quota;
 mod realtime;
+pub mod recorder;
+pub mod resolver;
+mod retry;
+pub mod rollover;
+pub mod rules;
+mod schedule;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod simulator;
+pub mod skipped;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timer;
+pub mod transport;
 pub mod types;
+pub mod validation;
+mod version;
 
 // Auckland Transport base API URL.
-pub(crate) const BASE_API_URL: &str = "https://api.at.govt.nz/v2";
+pub(crate) const BASE_API_URL: &str = "https://api.at.govt.nz";
 
+pub use builder::{
+    RealtimeBuilder, BASE_URL_ENV, DEFAULT_CONNECT_TIMEOUT, DEFAULT_MIN_POLL_INTERVAL,
+    DEFAULT_READ_TIMEOUT, DEFAULT_TIMEOUT, DEFAULT_USER_AGENT,
+};
+pub use client::{AtClient, Gtfs};
+pub use combined::CombinedResponse;
+pub use config::{Config, API_KEY_ENV};
+pub use ids::Ids;
+pub use meta::ResponseMeta;
+pub use options::RequestOptions;
+pub use outcome::FetchOutcome;
+pub use quota::QuotaInfo;
 pub use realtime::Realtime;
+pub use retry::RetryPolicy;
+pub use version::{ApiVersion, FetchStrategy};
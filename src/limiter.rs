@@ -0,0 +1,176 @@
+//! Client-side rate limiting of requests to AT.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::timer::{Timer, TokioTimer};
+
+/// A token-bucket rate limiter which delays requests so that the account quota is not
+/// exceeded.
+///
+/// The bucket holds up to `burst` tokens and is refilled at a steady rate, each request taking
+/// one token. When the bucket is empty, requests wait until a token becomes available instead
+/// of being sent. Retried attempts each take a token.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter which allows the given number of requests per period, with bursts
+    /// of up to the same number of requests.
+    ///
+    /// # Parameters
+    ///
+    /// * `requests` - The number of requests allowed per period.
+    /// * `per` - The period.
+    pub fn new(requests: u32, per: Duration) -> Self {
+        let requests = f64::from(requests.max(1));
+        Self {
+            rate: requests / per.as_secs_f64().max(f64::MIN_POSITIVE),
+            burst: requests,
+            bucket: Mutex::new(Bucket {
+                tokens: requests,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Creates a rate limiter which allows the given number of requests per second.
+    ///
+    /// # Parameters
+    ///
+    /// * `requests` - The number of requests allowed per second.
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    /// Creates a rate limiter which allows the given number of requests per minute.
+    ///
+    /// # Parameters
+    ///
+    /// * `requests` - The number of requests allowed per minute.
+    pub fn per_minute(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(60))
+    }
+
+    /// Sets the maximum number of requests which can be sent at once after a period of
+    /// inactivity.
+    ///
+    /// # Parameters
+    ///
+    /// * `burst` - The burst size.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = f64::from(burst.max(1));
+        let bucket = self.bucket.get_mut().unwrap();
+        bucket.tokens = bucket.tokens.min(self.burst);
+        self
+    }
+
+    /// Waits until a request may be sent, and takes a token from the bucket.
+    pub async fn acquire(&self) {
+        self.acquire_with(&TokioTimer).await
+    }
+
+    /// Waits until a request may be sent using the given timer, and takes a token from the
+    /// bucket.
+    ///
+    /// # Parameters
+    ///
+    /// * `timer` - The timer to wait with.
+    pub(crate) async fn acquire_with(&self, timer: &dyn Timer) {
+        while let Some(wait) = self.try_acquire() {
+            timer.sleep(wait).await;
+        }
+    }
+
+    /// Takes a token from the bucket if one is available, otherwise returns how long to wait
+    /// until one is.
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+    use crate::{timer::Sleep, transport::tests::block_on};
+
+    #[test]
+    fn allows_a_burst_then_waits() {
+        let limiter = RateLimiter::per_second(3);
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire(), None);
+        }
+        let wait = limiter.try_acquire().unwrap();
+        assert!(wait > Duration::from_millis(300) && wait <= Duration::from_millis(334));
+
+        let limiter = RateLimiter::per_minute(60).with_burst(1);
+        assert_eq!(limiter.try_acquire(), None);
+        let wait = limiter.try_acquire().unwrap();
+        assert!(wait > Duration::from_millis(990) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn refills_up_to_the_burst() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(20));
+        assert_eq!(limiter.try_acquire(), None);
+        assert_eq!(limiter.try_acquire(), None);
+        assert!(limiter.try_acquire().is_some());
+
+        // Waiting for longer than the period refills the bucket, but no further than the burst.
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(limiter.try_acquire(), None);
+        assert_eq!(limiter.try_acquire(), None);
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[test]
+    fn acquiring_waits_on_the_timer() {
+        let waits = Arc::new(Mutex::new(vec![]));
+        let timer = {
+            let waits = waits.clone();
+            move |duration: Duration| -> Sleep {
+                waits.lock().unwrap().push(duration);
+                Box::pin(tokio::time::sleep(duration))
+            }
+        };
+
+        let limiter = RateLimiter::new(2, Duration::from_millis(40));
+        block_on(async {
+            for _ in 0..3 {
+                limiter.acquire_with(&timer).await;
+            }
+        });
+
+        let waits = waits.lock().unwrap();
+        assert!(!waits.is_empty());
+        assert!(waits[0] <= Duration::from_millis(20));
+    }
+}
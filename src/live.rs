@@ -0,0 +1,68 @@
+//! A handle to the latest snapshot for synchronous readers.
+//!
+//! Renderers such as GUIs and game loops cannot await a fetch every frame. A [`LiveFeed`] is
+//! filled by a background poller, and [`LiveFeed::latest`] returns the most recent snapshot
+//! straight away without awaiting. Reading only takes a short-lived read lock to clone an
+//! [`Arc`], so a reader never waits on a fetch.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::CombinedResponse;
+
+/// A shared handle to the most recent snapshot fetched by a background poller. Created by
+/// [`Realtime::live_feed`].
+///
+/// Handles are cheap to clone, and the poller stops once every handle has been dropped.
+///
+/// [`Realtime::live_feed`]: crate::Realtime::live_feed
+#[derive(Debug, Clone)]
+pub struct LiveFeed {
+    rx: watch::Receiver<Option<Arc<CombinedResponse>>>,
+}
+
+impl LiveFeed {
+    /// Creates a feed with no snapshot, and the sender its poller publishes snapshots through.
+    pub(crate) fn new() -> (watch::Sender<Option<Arc<CombinedResponse>>>, Self) {
+        let (tx, rx) = watch::channel(None);
+        (tx, Self { rx })
+    }
+
+    /// Returns the most recent snapshot without waiting, or [`None`] if the first fetch has not
+    /// completed yet. Failed fetches leave the previous snapshot in place.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn latest(&self) -> Option<Arc<CombinedResponse>> {
+        self.rx.borrow().clone()
+    }
+
+    /// Returns true if a snapshot was published since the last call to [`latest_if_changed`] on
+    /// this handle.
+    ///
+    /// [`latest_if_changed`]: LiveFeed::latest_if_changed
+    pub fn has_changed(&self) -> bool {
+        self.rx.has_changed().unwrap_or(false)
+    }
+
+    /// Returns the most recent snapshot if one was published since the last call on this handle,
+    /// so a renderer can skip work on frames without new data.
+    pub fn latest_if_changed(&mut self) -> Option<Arc<CombinedResponse>> {
+        if !self.has_changed() {
+            return None;
+        }
+        self.rx.borrow_and_update().clone()
+    }
+
+    /// Waits until a new snapshot is published, for async consumers of the same feed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the new snapshot, or [`None`] if the poller has stopped.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub async fn changed(&mut self) -> Option<Arc<CombinedResponse>> {
+        self.rx.changed().await.ok()?;
+        self.rx.borrow_and_update().clone()
+    }
+}
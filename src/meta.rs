@@ -0,0 +1,69 @@
+//! Metadata about the HTTP responses a fetch was built from.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::{header::HeaderMap, StatusCode};
+
+use crate::quota::QuotaInfo;
+
+/// The headers the API gateway may send the ID of a request in, in order of preference.
+const REQUEST_ID_HEADERS: [&str; 4] = [
+    "x-request-id",
+    "request-id",
+    "apim-request-id",
+    "x-ms-request-id",
+];
+
+/// Metadata about the response a fetch was built from, as returned by
+/// [`Realtime::fetch_combined_with_meta`]. Include the request ID in support tickets with AT.
+///
+/// [`Realtime::fetch_combined_with_meta`]: crate::Realtime::fetch_combined_with_meta
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ResponseMeta {
+    /// The URL the request was sent to.
+    pub url: String,
+    /// The ID the API gateway gave the request, if it sent one.
+    pub request_id: Option<String>,
+    /// The HTTP status of the response.
+    pub status: StatusCode,
+    /// The quota reported by the rate limit headers of the response, if it sent any.
+    pub quota: Option<QuotaInfo>,
+    /// How long it took to receive the response headers after the request was sent.
+    pub latency: Duration,
+}
+
+impl ResponseMeta {
+    /// Reads the metadata of a response.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL the request was sent to.
+    /// * `status` - The HTTP status of the response.
+    /// * `headers` - The response headers.
+    /// * `latency` - How long it took to receive the response.
+    pub(crate) fn new(
+        url: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        latency: Duration,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            request_id: REQUEST_ID_HEADERS
+                .iter()
+                .filter_map(|name| headers.get(*name))
+                .find_map(|value| Some(value.to_str().ok()?.to_string())),
+            status,
+            quota: QuotaInfo::from_headers(headers),
+            latency,
+        }
+    }
+}
+
+/// Where the metadata of the most recent response to a call is kept, shared by the requests the
+/// call sends.
+pub(crate) type MetaSink = Arc<Mutex<Option<ResponseMeta>>>;
@@ -0,0 +1,46 @@
+//! Hooks which run around every request made by a client.
+//!
+//! Middleware registered with [`Realtime::with_middleware`] can mutate outgoing requests (for
+//! example to add custom authentication headers or sign requests) and observe the responses and
+//! errors received, for logging or instrumentation.
+//!
+//! [`Realtime::with_middleware`]: crate::Realtime::with_middleware
+
+use reqwest::{Request, Response};
+
+use crate::error::{Error, Result};
+
+/// A hook which runs around every request made by a client.
+///
+/// Middleware run in the order they were registered. All methods have a default implementation
+/// which does nothing, so only the relevant hooks need to be implemented.
+pub trait Middleware: Send + Sync {
+    /// Called before a request is sent. Returning an error aborts the request, and the error is
+    /// returned to the caller.
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The request about to be sent, which may be modified.
+    fn on_request(&self, request: &mut Request) -> Result<()> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called after a response has been received, before its body is read.
+    ///
+    /// # Parameters
+    ///
+    /// * `response` - The response received.
+    fn on_response(&self, response: &Response) {
+        let _ = response;
+    }
+
+    /// Called when a request fails without receiving a response.
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The error returned by the transport.
+    fn on_error(&self, error: &Error) {
+        let _ = error;
+    }
+}
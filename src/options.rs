@@ -0,0 +1,72 @@
+//! Options which override the client configuration for a single request.
+
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{meta::MetaSink, retry::RetryPolicy};
+
+/// Options which override the client configuration for a single call, such as
+/// [`Realtime::fetch_combined_with_options`].
+///
+/// Options which are not set keep the value configured on the client.
+///
+/// [`Realtime::fetch_combined_with_options`]: crate::Realtime::fetch_combined_with_options
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) retry: Option<RetryPolicy>,
+    pub(crate) conditional: bool,
+    /// Where to keep the metadata of each response received for the call, if it is wanted.
+    pub(crate) meta: Option<MetaSink>,
+}
+
+impl RequestOptions {
+    /// Creates a set of options which override nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides the total request timeout.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The request timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Overrides the read timeout.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - The read timeout.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Overrides the retry policy.
+    ///
+    /// # Parameters
+    ///
+    /// * `policy` - The retry policy.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Adds a header to the request, replacing any header of the same name set by the client.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the header.
+    /// * `value` - The value of the header.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+}
@@ -0,0 +1,40 @@
+//! Outcomes of fetches which may not return new data.
+
+/// The outcome of a fetch which may find that the feed has not changed since the previous fetch,
+/// such as [`Realtime::fetch_combined_if_modified`].
+///
+/// [`Realtime::fetch_combined_if_modified`]: crate::Realtime::fetch_combined_if_modified
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum FetchOutcome<T> {
+    /// New data was received.
+    Updated(T),
+    /// AT responded with `304 Not Modified`, so the data from the previous fetch is still
+    /// current.
+    NotModified,
+    /// AT sent the feed again, but it is identical to the feed received by the previous fetch,
+    /// so it was not decoded or merged again.
+    Unchanged,
+}
+
+impl<T> FetchOutcome<T> {
+    /// Returns the new data, or [`None`] if the feed has not changed.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn updated(self) -> Option<T> {
+        match self {
+            Self::Updated(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns true if AT responded with `304 Not Modified`.
+    pub fn is_not_modified(&self) -> bool {
+        matches!(self, Self::NotModified)
+    }
+
+    /// Returns true if AT sent a feed identical to the previous one.
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, Self::Unchanged)
+    }
+}
@@ -0,0 +1,289 @@
+//! Prometheus metrics derived from realtime fetches.
+//!
+//! A [`Metrics`] registry can be attached to a [`Realtime`] client with
+//! [`Realtime::with_metrics`], after which every fetch updates the registry. The host application
+//! exposes the metrics by serving the output of [`Metrics::render`] on its scrape endpoint.
+//!
+//! [`Realtime`]: crate::Realtime
+//! [`Realtime::with_metrics`]: crate::Realtime::with_metrics
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{error::Error, quota::QuotaInfo, CombinedResponse};
+
+/// A registry of gauges and counters describing the realtime feed and the client.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    fetches: u64,
+    errors: BTreeMap<&'static str, u64>,
+    latency_sum: f64,
+    last_latency: f64,
+    active_vehicles: usize,
+    route_delays: BTreeMap<String, f64>,
+    quota_remaining: Option<u64>,
+    quota_limit: Option<u64>,
+}
+
+impl Metrics {
+    /// Creates a new, empty metrics registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a successful fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `latency` - How long the fetch took.
+    /// * `combined` - The snapshot returned by the fetch. Both the merged and the unmatched
+    ///   entities are counted.
+    pub fn record_fetch(&self, latency: Duration, combined: &CombinedResponse) {
+        let entities = || combined.entities.iter().chain(combined.unmatched.iter());
+        let mut delays: HashMap<String, (i64, i64)> = HashMap::new();
+        for entity in entities() {
+            let delay = entity.trip_update.as_ref().and_then(|tu| tu.delay);
+            if let (Some(route_id), Some(delay)) = (entity.route_id(), delay) {
+                let (sum, count) = delays.entry(route_id).or_default();
+                *sum += i64::from(delay);
+                *count += 1;
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.fetches += 1;
+        state.latency_sum += latency.as_secs_f64();
+        state.last_latency = latency.as_secs_f64();
+        state.active_vehicles = entities().filter(|e| e.vehicle.is_some()).count();
+        state.route_delays = delays
+            .into_iter()
+            .map(|(route, (sum, count))| (route, sum as f64 / count as f64))
+            .collect();
+    }
+
+    /// Records a failed fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The error returned by the fetch.
+    pub fn record_error(&self, error: &Error) {
+        let kind = error_kind(error);
+        *self.state.lock().unwrap().errors.entry(kind).or_default() += 1;
+    }
+
+    /// Records the account quota reported by the most recent response, such as the value of
+    /// [`Realtime::quota`].
+    ///
+    /// # Parameters
+    ///
+    /// * `quota` - The quota reported by the API gateway.
+    ///
+    /// [`Realtime::quota`]: crate::Realtime::quota
+    pub fn record_quota(&self, quota: &QuotaInfo) {
+        let mut state = self.state.lock().unwrap();
+        state.quota_remaining = quota.remaining;
+        state.quota_limit = quota.limit;
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP at_fetches_total Number of successful fetches.");
+        let _ = writeln!(out, "# TYPE at_fetches_total counter");
+        let _ = writeln!(out, "at_fetches_total {}", state.fetches);
+
+        let _ = writeln!(
+            out,
+            "# HELP at_fetch_errors_total Number of failed fetches."
+        );
+        let _ = writeln!(out, "# TYPE at_fetch_errors_total counter");
+        for (kind, count) in state.errors.iter() {
+            let _ = writeln!(out, "at_fetch_errors_total{{kind=\"{}\"}} {}", kind, count);
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP at_fetch_duration_seconds Time taken by fetches."
+        );
+        let _ = writeln!(out, "# TYPE at_fetch_duration_seconds summary");
+        let _ = writeln!(out, "at_fetch_duration_seconds_sum {}", state.latency_sum);
+        let _ = writeln!(out, "at_fetch_duration_seconds_count {}", state.fetches);
+
+        let _ = writeln!(
+            out,
+            "# HELP at_last_fetch_duration_seconds Time taken by the last fetch."
+        );
+        let _ = writeln!(out, "# TYPE at_last_fetch_duration_seconds gauge");
+        let _ = writeln!(out, "at_last_fetch_duration_seconds {}", state.last_latency);
+
+        let _ = writeln!(
+            out,
+            "# HELP at_active_vehicles Number of vehicles in the last fetch."
+        );
+        let _ = writeln!(out, "# TYPE at_active_vehicles gauge");
+        let _ = writeln!(out, "at_active_vehicles {}", state.active_vehicles);
+
+        let _ = writeln!(
+            out,
+            "# HELP at_route_delay_seconds Average delay of trips per route."
+        );
+        let _ = writeln!(out, "# TYPE at_route_delay_seconds gauge");
+        for (route, delay) in state.route_delays.iter() {
+            let _ = writeln!(
+                out,
+                "at_route_delay_seconds{{route_id=\"{}\"}} {}",
+                escape_label(route),
+                delay
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP at_quota_remaining Requests remaining in the current quota window."
+        );
+        let _ = writeln!(out, "# TYPE at_quota_remaining gauge");
+        if let Some(remaining) = state.quota_remaining {
+            let _ = writeln!(out, "at_quota_remaining {}", remaining);
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP at_quota_limit Requests allowed in the current quota window."
+        );
+        let _ = writeln!(out, "# TYPE at_quota_limit gauge");
+        if let Some(limit) = state.quota_limit {
+            let _ = writeln!(out, "at_quota_limit {}", limit);
+        }
+
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Returns the `kind` label for an error.
+///
+/// # Parameters
+///
+/// * `error` - The error to label.
+fn error_kind(error: &Error) -> &'static str {
+    match error {
+        Error::Request(_) => "request",
+        Error::Protobuf(_) => "protobuf",
+        Error::Io(_) => "io",
+        Error::Json(_) => "json",
+        Error::Decode { .. } => "decode",
+        Error::Url(_) => "url",
+        Error::Timeout { .. } => "timeout",
+        Error::ResponseTooLarge { .. } => "response_too_large",
+        Error::MissingApiKey => "missing_api_key",
+        Error::InvalidHeader(_) => "invalid_header",
+        Error::Transport(_) => "transport",
+        Error::Unauthorized { .. } => "unauthorized",
+        Error::Forbidden { .. } => "forbidden",
+        Error::ServerError { .. } => "server_error",
+        Error::Api { .. } => "api",
+        Error::RateLimited { .. } => "rate_limited",
+        Error::CircuitOpen { .. } => "circuit_open",
+        Error::Shared(e) => error_kind(e),
+        Error::WithUrl { source, .. } => error_kind(source),
+        #[cfg(feature = "server")]
+        Error::Server(_) => "server",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::combined::tests::{snapshot, unmatched};
+
+    #[test]
+    fn renders_every_metric() {
+        let metrics = Metrics::new();
+        let mut combined = snapshot(json!([{
+            "id": "e1",
+            "trip_update": {"trip": {"trip_id": "t1", "route_id": "82-202"}, "delay": 60},
+            "vehicle": {"vehicle": {"id": "v1"}}
+        }]));
+        let trip_update = |trip_id: &str, route_id: &str, delay: i32| {
+            json!({
+                "id": trip_id,
+                "trip_update": {"trip": {"trip_id": trip_id, "route_id": route_id}, "delay": delay}
+            })
+        };
+        combined.unmatched = unmatched(json!([
+            {"id": "e2", "vehicle": {"vehicle": {"id": "v2"}}},
+            trip_update("t3", "82-202", 120),
+            trip_update("t4", "N\"X-202", -30),
+        ]))
+        .unmatched;
+
+        metrics.record_fetch(Duration::from_millis(500), &combined);
+        metrics.record_fetch(Duration::from_millis(250), &combined);
+        metrics.record_error(&Error::MissingApiKey);
+        metrics.record_quota(&QuotaInfo {
+            limit: Some(1000),
+            remaining: Some(950),
+            reset: None,
+            updated_at: SystemTime::now(),
+        });
+
+        let expected = "\
+# HELP at_fetches_total Number of successful fetches.
+# TYPE at_fetches_total counter
+at_fetches_total 2
+# HELP at_fetch_errors_total Number of failed fetches.
+# TYPE at_fetch_errors_total counter
+at_fetch_errors_total{kind=\"missing_api_key\"} 1
+# HELP at_fetch_duration_seconds Time taken by fetches.
+# TYPE at_fetch_duration_seconds summary
+at_fetch_duration_seconds_sum 0.75
+at_fetch_duration_seconds_count 2
+# HELP at_last_fetch_duration_seconds Time taken by the last fetch.
+# TYPE at_last_fetch_duration_seconds gauge
+at_last_fetch_duration_seconds 0.25
+# HELP at_active_vehicles Number of vehicles in the last fetch.
+# TYPE at_active_vehicles gauge
+at_active_vehicles 2
+# HELP at_route_delay_seconds Average delay of trips per route.
+# TYPE at_route_delay_seconds gauge
+at_route_delay_seconds{route_id=\"82\"} 90
+at_route_delay_seconds{route_id=\"N\\\"X\"} -30
+# HELP at_quota_remaining Requests remaining in the current quota window.
+# TYPE at_quota_remaining gauge
+at_quota_remaining 950
+# HELP at_quota_limit Requests allowed in the current quota window.
+# TYPE at_quota_limit gauge
+at_quota_limit 1000
+";
+        assert_eq!(metrics.render(), expected);
+    }
+
+    #[test]
+    fn leaves_out_unknown_quotas() {
+        let rendered = Metrics::new().render();
+        assert!(rendered.contains("# TYPE at_quota_remaining gauge\n# HELP at_quota_limit"));
+        assert!(rendered.ends_with("# TYPE at_quota_limit gauge\n"));
+        assert!(rendered.contains("\nat_active_vehicles 0\n"));
+    }
+}
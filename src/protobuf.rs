@@ -0,0 +1,853 @@
+//! Encoding of realtime data into the standard GTFS-RT protobuf format.
+//!
+//! Auckland Transport serves its realtime feed as JSON, which most GTFS-RT consumers (such as
+//! OpenTripPlanner or OneBusAway) do not understand. The functions in this module re-encode
+//! entities into a spec-compliant `FeedMessage` as defined by
+//! [gtfs-realtime.proto](https://gtfs.org/realtime/proto/), and decode standard feeds back into
+//! the types used by this library.
+
+use std::convert::{TryFrom, TryInto};
+
+use crate::{
+    error::{Error, Result},
+    types::{
+        gtfs::{
+            CongestionLevel, Entity, OccupancyStatus, Position, ScheduleRelationship,
+            ScheduleRelationshipTripDescriptor, StopTimeEvent, StopTimeUpdate, TripDescriptor,
+            TripUpdate, VehicleDescriptor, VehiclePosition, VehicleStopStatus,
+        },
+        Header, Incrementality,
+    },
+};
+
+/// Protobuf wire type for varint encoded fields.
+const WIRE_VARINT: u32 = 0;
+/// Protobuf wire type for 64-bit fixed width fields.
+const WIRE_FIXED64: u32 = 1;
+/// Protobuf wire type for length delimited fields.
+const WIRE_LEN: u32 = 2;
+/// Protobuf wire type for 32-bit fixed width fields.
+const WIRE_FIXED32: u32 = 5;
+
+/// Encodes a header and a list of entities into a GTFS-RT `FeedMessage`.
+///
+/// The GTFS-RT specification requires each `FeedEntity` to contain exactly one of a trip update
+/// or a vehicle position, so merged entities (as returned by [`fetch_combined`]) are split into
+/// two feed entities. The vehicle position keeps the entity ID, while the trip update is
+/// identified by its trip ID.
+///
+/// # Parameters
+///
+/// * `header` - The feed header received from AT.
+/// * `entities` - The entities to include in the feed.
+///
+/// # Returns
+///
+/// Returns the encoded `FeedMessage` bytes.
+///
+/// [`fetch_combined`]: crate::Realtime::fetch_combined
+pub fn encode_feed(header: &Header, entities: &[Entity]) -> Vec<u8> {
+    let mut buf = Writer::default();
+    buf.message(1, |w| write_header(w, header));
+
+    for entity in entities {
+        if let Some(vehicle) = entity.vehicle.as_ref() {
+            buf.message(2, |w| {
+                w.string(1, &entity.id);
+                w.bool(2, entity.is_deleted);
+                w.message(4, |w| write_vehicle_position(w, vehicle));
+            });
+        }
+
+        if let Some(trip_update) = entity.trip_update.as_ref() {
+            let id = match (entity.vehicle.as_ref(), trip_update.trip.trip_id.as_ref()) {
+                (None, _) => entity.id.clone(),
+                (Some(_), Some(trip_id)) if *trip_id != entity.id => trip_id.clone(),
+                (Some(_), _) => format!("{}:trip_update", entity.id),
+            };
+
+            buf.message(2, |w| {
+                w.string(1, &id);
+                w.bool(2, entity.is_deleted);
+                w.message(3, |w| write_trip_update(w, trip_update));
+            });
+        }
+
+        if entity.vehicle.is_none() && entity.trip_update.is_none() && entity.is_deleted {
+            buf.message(2, |w| {
+                w.string(1, &entity.id);
+                w.bool(2, true);
+            });
+        }
+    }
+
+    buf.into_inner()
+}
+
+fn write_header(w: &mut Writer, header: &Header) {
+    w.string(1, &header.gtfs_realtime_version);
+    w.varint_field(2, header.incrementality as u64);
+    if let Some(timestamp) = header.timestamp {
+        w.varint_field(3, timestamp as u64);
+    }
+}
+
+fn write_trip_update(w: &mut Writer, trip_update: &TripUpdate) {
+    w.message(1, |w| write_trip_descriptor(w, &trip_update.trip));
+    if let Some(stu) = trip_update.stop_time_update.as_ref() {
+        w.message(2, |w| write_stop_time_update(w, stu));
+    }
+    if let Some(vehicle) = trip_update.vehicle.as_ref() {
+        w.message(3, |w| write_vehicle_descriptor(w, vehicle));
+    }
+    w.optional(4, trip_update.timestamp, Writer::varint_field);
+    w.optional(5, trip_update.delay, Writer::int32);
+}
+
+fn write_stop_time_update(w: &mut Writer, stu: &StopTimeUpdate) {
+    w.optional(1, stu.stop_sequence.map(u64::from), Writer::varint_field);
+    if let Some(arrival) = stu.arrival.as_ref() {
+        w.message(2, |w| write_stop_time_event(w, arrival));
+    }
+    if let Some(departure) = stu.departure.as_ref() {
+        w.message(3, |w| write_stop_time_event(w, departure));
+    }
+    if let Some(stop_id) = stu.stop_id.as_ref() {
+        w.string(4, stop_id);
+    }
+    w.varint_field(5, stu.schedule_relationship as u64);
+}
+
+fn write_stop_time_event(w: &mut Writer, event: &StopTimeEvent) {
+    w.optional(1, event.delay, Writer::int32);
+    w.optional(2, event.time, Writer::int64);
+    w.optional(3, event.uncertainty, Writer::int32);
+}
+
+fn write_vehicle_position(w: &mut Writer, vp: &VehiclePosition) {
+    if let Some(trip) = vp.trip.as_ref() {
+        w.message(1, |w| write_trip_descriptor(w, trip));
+    }
+    if let Some(position) = vp.position.as_ref() {
+        w.message(2, |w| write_position(w, position));
+    }
+    w.optional(
+        3,
+        vp.current_stop_sequence.map(u64::from),
+        Writer::varint_field,
+    );
+    w.varint_field(4, vp.current_status as u64);
+    w.optional(5, vp.timestamp, Writer::varint_field);
+    w.optional(
+        6,
+        vp.congestion_level.map(|c| c as u64),
+        Writer::varint_field,
+    );
+    if let Some(stop_id) = vp.stop_id.as_ref() {
+        w.string(7, stop_id);
+    }
+    if let Some(vehicle) = vp.vehicle.as_ref() {
+        w.message(8, |w| write_vehicle_descriptor(w, vehicle));
+    }
+    w.optional(
+        9,
+        vp.occupancy_status.map(|o| o as u64),
+        Writer::varint_field,
+    );
+}
+
+fn write_position(w: &mut Writer, position: &Position) {
+    w.float(1, position.latitude);
+    w.float(2, position.longitude);
+    w.optional(3, position.bearing, Writer::float);
+    w.optional(4, position.odometer, Writer::double);
+    w.optional(5, position.speed, Writer::float);
+}
+
+fn write_trip_descriptor(w: &mut Writer, trip: &TripDescriptor) {
+    if let Some(trip_id) = trip.trip_id.as_ref() {
+        w.string(1, trip_id);
+    }
+    if let Some(start_time) = trip.start_time.as_ref() {
+        w.string(2, start_time);
+    }
+    if let Some(start_date) = trip.start_date.as_ref() {
+        w.string(3, start_date);
+    }
+    w.optional(
+        4,
+        trip.schedule_relationship.map(|s| s as u64),
+        Writer::varint_field,
+    );
+    if let Some(route_id) = trip.route_id.as_ref() {
+        w.string(5, route_id);
+    }
+    w.optional(6, trip.direction_id.map(u64::from), Writer::varint_field);
+}
+
+fn write_vehicle_descriptor(w: &mut Writer, vehicle: &VehicleDescriptor) {
+    if let Some(id) = vehicle.id.as_ref() {
+        w.string(1, id);
+    }
+    if let Some(label) = vehicle.label.as_ref() {
+        w.string(2, label);
+    }
+    if let Some(license_plate) = vehicle.license_plate.as_ref() {
+        w.string(3, license_plate);
+    }
+}
+
+/// A minimal protobuf wire format writer.
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from((field << 3) | wire_type));
+    }
+
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn optional<T>(&mut self, field: u32, value: Option<T>, f: fn(&mut Self, u32, T)) {
+        if let Some(value) = value {
+            f(self, field, value);
+        }
+    }
+
+    fn varint_field(&mut self, field: u32, v: u64) {
+        self.key(field, WIRE_VARINT);
+        self.varint(v);
+    }
+
+    fn int32(&mut self, field: u32, v: i32) {
+        // Negative `int32` values are sign extended to 64 bits on the wire.
+        self.varint_field(field, i64::from(v) as u64);
+    }
+
+    fn int64(&mut self, field: u32, v: i64) {
+        self.varint_field(field, v as u64);
+    }
+
+    fn bool(&mut self, field: u32, v: bool) {
+        if v {
+            self.varint_field(field, 1);
+        }
+    }
+
+    fn float(&mut self, field: u32, v: f32) {
+        self.key(field, WIRE_FIXED32);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn double(&mut self, field: u32, v: f64) {
+        self.key(field, WIRE_FIXED64);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn string(&mut self, field: u32, v: &str) {
+        self.key(field, WIRE_LEN);
+        self.varint(v.len() as u64);
+        self.buf.extend_from_slice(v.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, f: impl FnOnce(&mut Self)) {
+        let mut inner = Writer::default();
+        f(&mut inner);
+
+        self.key(field, WIRE_LEN);
+        self.varint(inner.buf.len() as u64);
+        self.buf.extend_from_slice(&inner.buf);
+    }
+}
+
+/// Decodes a GTFS-RT `FeedMessage` into a header and a list of entities.
+///
+/// This is the inverse of [`encode_feed`], and can be used to consume feeds produced by other
+/// GTFS-RT tooling (such as the `gtfs-rt` crate, via `Message::encode_to_vec`). Each decoded
+/// `FeedEntity` becomes one [`Entity`]; trip updates and vehicle positions are not merged. As
+/// [`TripUpdate`] only holds a single stop time update, only the first one of each trip update is
+/// kept. Unknown fields are skipped, and as in proto2, enum values the crate does not know (such
+/// as those added to GTFS-RT after it was written) leave their field unset.
+///
+/// # Parameters
+///
+/// * `bytes` - The encoded `FeedMessage`.
+///
+/// # Returns
+///
+/// Returns a tuple where the first item is the feed header, and the second item is a vector of
+/// the feed entities.
+pub fn decode_feed(bytes: &[u8]) -> Result<(Header, Vec<Entity>)> {
+    let mut header = None;
+    let mut entities = vec![];
+
+    Reader::new(bytes).fields(|field, value| {
+        match field {
+            1 => header = Some(read_header(value.message()?)?),
+            2 => entities.push(read_entity(value.message()?)?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    let header = header.ok_or_else(|| decode_error("missing feed header"))?;
+    Ok((header, entities))
+}
+
+fn read_header(mut r: Reader) -> Result<Header> {
+    let mut header = Header {
+        gtfs_realtime_version: String::new(),
+        incrementality: Incrementality::FullDataset,
+        timestamp: None,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => header.gtfs_realtime_version = value.string()?,
+            2 => match value.varint()? {
+                0 => header.incrementality = Incrementality::FullDataset,
+                1 => header.incrementality = Incrementality::Differential,
+                _ => {}
+            },
+            3 => header.timestamp = Some(value.varint()? as f64),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(header)
+}
+
+fn read_entity(mut r: Reader) -> Result<Entity> {
+    let mut entity = Entity {
+        id: String::new(),
+        trip_update: None,
+        vehicle: None,
+        is_deleted: false,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => entity.id = value.string()?,
+            2 => entity.is_deleted = value.varint()? != 0,
+            3 => entity.trip_update = Some(read_trip_update(value.message()?)?),
+            4 => entity.vehicle = Some(read_vehicle_position(value.message()?)?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(entity)
+}
+
+fn read_trip_update(mut r: Reader) -> Result<TripUpdate> {
+    let mut trip = None;
+    let mut trip_update = TripUpdate {
+        trip: empty_trip_descriptor(),
+        vehicle: None,
+        stop_time_update: None,
+        timestamp: None,
+        delay: None,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => trip = Some(read_trip_descriptor(value.message()?)?),
+            2 => {
+                let stu = read_stop_time_update(value.message()?)?;
+                trip_update.stop_time_update.get_or_insert(stu);
+            }
+            3 => trip_update.vehicle = Some(read_vehicle_descriptor(value.message()?)?),
+            4 => trip_update.timestamp = Some(value.varint()?),
+            5 => trip_update.delay = Some(value.int32()?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    trip_update.trip = trip.ok_or_else(|| decode_error("missing trip descriptor"))?;
+    Ok(trip_update)
+}
+
+fn read_stop_time_update(mut r: Reader) -> Result<StopTimeUpdate> {
+    let mut stu = StopTimeUpdate {
+        stop_sequence: None,
+        stop_id: None,
+        arrival: None,
+        departure: None,
+        schedule_relationship: ScheduleRelationship::Scheduled,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => stu.stop_sequence = Some(value.uint32()?),
+            2 => stu.arrival = Some(read_stop_time_event(value.message()?)?),
+            3 => stu.departure = Some(read_stop_time_event(value.message()?)?),
+            4 => stu.stop_id = Some(value.string()?),
+            5 => match value.varint()? {
+                0 => stu.schedule_relationship = ScheduleRelationship::Scheduled,
+                1 => stu.schedule_relationship = ScheduleRelationship::Skipped,
+                2 => stu.schedule_relationship = ScheduleRelationship::NoData,
+                _ => {}
+            },
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(stu)
+}
+
+fn read_stop_time_event(mut r: Reader) -> Result<StopTimeEvent> {
+    let mut event = StopTimeEvent {
+        delay: None,
+        time: None,
+        uncertainty: None,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => event.delay = Some(value.int32()?),
+            2 => event.time = Some(value.varint()? as i64),
+            3 => event.uncertainty = Some(value.int32()?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(event)
+}
+
+fn read_vehicle_position(mut r: Reader) -> Result<VehiclePosition> {
+    let mut vp = VehiclePosition {
+        trip: None,
+        vehicle: None,
+        position: None,
+        current_stop_sequence: None,
+        stop_id: None,
+        current_status: VehicleStopStatus::InTransitTo,
+        timestamp: None,
+        congestion_level: None,
+        occupancy_status: None,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => vp.trip = Some(read_trip_descriptor(value.message()?)?),
+            2 => vp.position = Some(read_position(value.message()?)?),
+            3 => vp.current_stop_sequence = Some(value.uint32()?),
+            4 => match value.varint()? {
+                0 => vp.current_status = VehicleStopStatus::IncomingAt,
+                1 => vp.current_status = VehicleStopStatus::StoppedAt,
+                2 => vp.current_status = VehicleStopStatus::InTransitTo,
+                _ => {}
+            },
+            5 => vp.timestamp = Some(value.varint()?),
+            6 => {
+                vp.congestion_level = match value.varint()? {
+                    0 => Some(CongestionLevel::UnknownCongestionLevel),
+                    1 => Some(CongestionLevel::RunningSmoothly),
+                    2 => Some(CongestionLevel::StopAndGo),
+                    3 => Some(CongestionLevel::Congestion),
+                    4 => Some(CongestionLevel::SevereCongestion),
+                    _ => None,
+                }
+            }
+            7 => vp.stop_id = Some(value.string()?),
+            8 => vp.vehicle = Some(read_vehicle_descriptor(value.message()?)?),
+            9 => {
+                vp.occupancy_status = match value.varint()? {
+                    0 => Some(OccupancyStatus::Empty),
+                    1 => Some(OccupancyStatus::ManySeatsAvailable),
+                    2 => Some(OccupancyStatus::FewSeatsAvailable),
+                    3 => Some(OccupancyStatus::StandingRoomOnly),
+                    4 => Some(OccupancyStatus::CrushedStandingRoomOnly),
+                    5 => Some(OccupancyStatus::Full),
+                    6 => Some(OccupancyStatus::NotAcceptingPassengers),
+                    _ => None,
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(vp)
+}
+
+fn read_position(mut r: Reader) -> Result<Position> {
+    let mut latitude = None;
+    let mut longitude = None;
+    let mut position = Position {
+        latitude: 0.0,
+        longitude: 0.0,
+        bearing: None,
+        odometer: None,
+        speed: None,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => latitude = Some(value.float()?),
+            2 => longitude = Some(value.float()?),
+            3 => position.bearing = Some(value.float()?),
+            4 => position.odometer = Some(value.double()?),
+            5 => position.speed = Some(value.float()?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    position.latitude = latitude.ok_or_else(|| decode_error("missing latitude"))?;
+    position.longitude = longitude.ok_or_else(|| decode_error("missing longitude"))?;
+    Ok(position)
+}
+
+fn read_trip_descriptor(mut r: Reader) -> Result<TripDescriptor> {
+    let mut trip = empty_trip_descriptor();
+
+    r.fields(|field, value| {
+        match field {
+            1 => trip.trip_id = Some(value.string()?),
+            2 => trip.start_time = Some(value.string()?),
+            3 => trip.start_date = Some(value.string()?),
+            4 => {
+                trip.schedule_relationship = match value.varint()? {
+                    0 => Some(ScheduleRelationshipTripDescriptor::Scheduled),
+                    1 => Some(ScheduleRelationshipTripDescriptor::Added),
+                    2 => Some(ScheduleRelationshipTripDescriptor::Unscheduled),
+                    3 => Some(ScheduleRelationshipTripDescriptor::Cancelled),
+                    _ => None,
+                }
+            }
+            5 => trip.route_id = Some(value.string()?),
+            6 => trip.direction_id = Some(value.uint32()?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(trip)
+}
+
+fn read_vehicle_descriptor(mut r: Reader) -> Result<VehicleDescriptor> {
+    let mut vehicle = VehicleDescriptor {
+        id: None,
+        label: None,
+        license_plate: None,
+    };
+
+    r.fields(|field, value| {
+        match field {
+            1 => vehicle.id = Some(value.string()?),
+            2 => vehicle.label = Some(value.string()?),
+            3 => vehicle.license_plate = Some(value.string()?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(vehicle)
+}
+
+fn empty_trip_descriptor() -> TripDescriptor {
+    TripDescriptor {
+        trip_id: None,
+        route_id: None,
+        direction_id: None,
+        start_time: None,
+        start_date: None,
+        schedule_relationship: None,
+    }
+}
+
+fn decode_error(msg: &str) -> Error {
+    Error::Protobuf(msg.to_string())
+}
+
+/// A minimal protobuf wire format reader.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+/// The value of a single field read from the wire.
+enum Value<'a> {
+    Varint(u64),
+    Fixed64([u8; 8]),
+    Fixed32([u8; 4]),
+    Len(&'a [u8]),
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Calls `f` with the field number and value of each field in the message.
+    fn fields(&mut self, mut f: impl FnMut(u32, Value<'a>) -> Result<()>) -> Result<()> {
+        while !self.buf.is_empty() {
+            let key = self.varint()?;
+            let field =
+                u32::try_from(key >> 3).map_err(|_| decode_error("invalid field number"))?;
+            let value = match (key & 0x7) as u32 {
+                WIRE_VARINT => Value::Varint(self.varint()?),
+                WIRE_FIXED64 => Value::Fixed64(self.take(8)?.try_into().unwrap()),
+                WIRE_LEN => {
+                    let len = usize::try_from(self.varint()?)
+                        .map_err(|_| decode_error("invalid length"))?;
+                    Value::Len(self.take(len)?)
+                }
+                WIRE_FIXED32 => Value::Fixed32(self.take(4)?.try_into().unwrap()),
+                _ => return Err(decode_error("unsupported wire type")),
+            };
+            f(field, value)?;
+        }
+
+        Ok(())
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self
+                .buf
+                .split_first()
+                .ok_or_else(|| decode_error("unexpected end of buffer"))?;
+            self.buf = rest;
+            v |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+
+        Err(decode_error("varint too long"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(decode_error("unexpected end of buffer"));
+        }
+
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+}
+
+impl<'a> Value<'a> {
+    fn varint(self) -> Result<u64> {
+        match self {
+            Value::Varint(v) => Ok(v),
+            _ => Err(decode_error("expected varint")),
+        }
+    }
+
+    fn uint32(self) -> Result<u32> {
+        u32::try_from(self.varint()?).map_err(|_| decode_error("uint32 out of range"))
+    }
+
+    fn int32(self) -> Result<i32> {
+        // Negative `int32` values are sign extended to 64 bits on the wire.
+        Ok(self.varint()? as i64 as i32)
+    }
+
+    fn float(self) -> Result<f32> {
+        match self {
+            Value::Fixed32(bytes) => Ok(f32::from_le_bytes(bytes)),
+            _ => Err(decode_error("expected float")),
+        }
+    }
+
+    fn double(self) -> Result<f64> {
+        match self {
+            Value::Fixed64(bytes) => Ok(f64::from_le_bytes(bytes)),
+            _ => Err(decode_error("expected double")),
+        }
+    }
+
+    fn bytes(self) -> Result<&'a [u8]> {
+        match self {
+            Value::Len(bytes) => Ok(bytes),
+            _ => Err(decode_error("expected length delimited field")),
+        }
+    }
+
+    fn string(self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| decode_error("invalid utf-8"))
+    }
+
+    fn message(self) -> Result<Reader<'a>> {
+        Ok(Reader::new(self.bytes()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        serde_json::from_str(
+            r#"{"gtfs_realtime_version": "2.0", "incrementality": 0, "timestamp": 1633045120}"#,
+        )
+        .unwrap()
+    }
+
+    fn entities() -> Vec<Entity> {
+        serde_json::from_str(
+            r#"[
+                {
+                    "id": "59729",
+                    "vehicle": {
+                        "trip": {
+                            "trip_id": "1141101952-20210927110507_v105.39",
+                            "route_id": "11101-20210927110507_v105.39",
+                            "direction_id": 1,
+                            "start_time": "06:45:00",
+                            "start_date": "20211001",
+                            "schedule_relationship": 0
+                        },
+                        "vehicle": {"id": "59729", "label": "GB5729", "license_plate": "HZJ989"},
+                        "position": {
+                            "latitude": -36.84429,
+                            "longitude": 174.76753,
+                            "bearing": 90,
+                            "odometer": 1250.5,
+                            "speed": 8.5
+                        },
+                        "current_stop_sequence": 4,
+                        "stop_id": "7155-9ea764b3",
+                        "current_status": 1,
+                        "timestamp": 1633045080,
+                        "congestion_level": 2,
+                        "occupancy_status": 3
+                    }
+                },
+                {
+                    "id": "1141101952-20210927110507_v105.39",
+                    "trip_update": {
+                        "trip": {
+                            "trip_id": "1141101952-20210927110507_v105.39",
+                            "route_id": "11101-20210927110507_v105.39",
+                            "schedule_relationship": 0
+                        },
+                        "vehicle": {"id": "59729"},
+                        "stop_time_update": {
+                            "stop_sequence": 4,
+                            "stop_id": "7155-9ea764b3",
+                            "arrival": {"delay": -30, "time": 1633045050, "uncertainty": 0},
+                            "departure": {"delay": 124, "time": 1633045204},
+                            "schedule_relationship": 0
+                        },
+                        "timestamp": 1633045080,
+                        "delay": 124
+                    }
+                },
+                {"id": "deleted", "is_deleted": true}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_feed() {
+        let (header, entities) = (header(), entities());
+        let (decoded_header, decoded) = decode_feed(&encode_feed(&header, &entities)).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&decoded_header).unwrap(),
+            serde_json::to_value(&header).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&entities).unwrap()
+        );
+    }
+
+    #[test]
+    fn splits_merged_entities() {
+        let mut entities = entities();
+        let trip_update = entities.remove(1).trip_update;
+        entities[0].trip_update = trip_update;
+
+        let (_, decoded) = decode_feed(&encode_feed(&header(), &entities[..1])).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert!(decoded[0].vehicle.is_some() && decoded[0].trip_update.is_none());
+        assert!(decoded[1].trip_update.is_some() && decoded[1].vehicle.is_none());
+        assert_eq!(decoded[1].id, "1141101952-20210927110507_v105.39");
+    }
+
+    #[test]
+    fn unknown_enum_values_are_unset() {
+        let mut w = Writer::default();
+        w.message(1, |w| {
+            w.string(1, "2.0");
+            w.varint_field(2, 9);
+        });
+        w.message(2, |w| {
+            w.string(1, "vehicle");
+            w.message(4, |w| {
+                // SCHEDULE_RELATIONSHIP 5 is DUPLICATED, added after this crate was written.
+                w.message(1, |w| {
+                    w.string(1, "trip");
+                    w.varint_field(4, 5);
+                });
+                w.varint_field(4, 7);
+                w.varint_field(6, 9);
+                // OCCUPANCY_STATUS 7 is NO_DATA_AVAILABLE.
+                w.varint_field(9, 7);
+            });
+        });
+        w.message(2, |w| {
+            w.string(1, "trip");
+            w.message(3, |w| {
+                w.message(1, |w| w.string(1, "trip"));
+                // STOP_TIME_UPDATE SCHEDULE_RELATIONSHIP 3 is UNSCHEDULED.
+                w.message(2, |w| {
+                    w.varint_field(1, 1);
+                    w.varint_field(5, 3);
+                });
+            });
+        });
+
+        let (header, entities) = decode_feed(&w.into_inner()).unwrap();
+        assert!(matches!(header.incrementality, Incrementality::FullDataset));
+
+        let vehicle = entities[0].vehicle.as_ref().unwrap();
+        let trip = vehicle.trip.as_ref().unwrap();
+        assert_eq!(trip.trip_id.as_deref(), Some("trip"));
+        assert!(trip.schedule_relationship.is_none());
+        assert!(matches!(
+            vehicle.current_status,
+            VehicleStopStatus::InTransitTo
+        ));
+        assert!(vehicle.congestion_level.is_none());
+        assert!(vehicle.occupancy_status.is_none());
+
+        let trip_update = entities[1].trip_update.as_ref().unwrap();
+        let stu = trip_update.stop_time_update.as_ref().unwrap();
+        assert_eq!(stu.stop_sequence, Some(1));
+        assert!(matches!(
+            stu.schedule_relationship,
+            ScheduleRelationship::Scheduled
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_feed() {
+        let bytes = encode_feed(&header(), &entities());
+        assert!(decode_feed(&bytes[..bytes.len() - 3]).is_err());
+        assert!(decode_feed(&[]).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Encoding of query strings with control over which characters are escaped.
+//!
+//! `reqwest::RequestBuilder::query` percent-encodes every reserved character, but the AT API
+//! requires lists of IDs to be separated by literal commas and does not accept `%2C`. A
+//! [`QueryEncoder`] escapes values in the same way, except for the characters it has been told to
+//! leave alone, so endpoints with similar quirks can share one implementation.
+
+/// Percent-encodes query string keys and values.
+///
+/// By default every character other than the RFC 3986 unreserved characters (ASCII letters,
+/// digits, `-`, `.`, `_` and `~`) is escaped. Further ASCII characters can be left unescaped with
+/// [`leave_unescaped`].
+///
+/// [`leave_unescaped`]: QueryEncoder::leave_unescaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryEncoder {
+    /// A bit for each ASCII character, set if the character is written as is.
+    unescaped: u128,
+}
+
+/// The encoder used for AT API queries, which leaves commas unescaped.
+pub const AT_QUERY: QueryEncoder = QueryEncoder::new().leave_unescaped(",");
+
+impl QueryEncoder {
+    /// Creates an encoder which escapes everything except the unreserved characters.
+    pub const fn new() -> Self {
+        Self { unescaped: 0 }
+            .leave_unescaped("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~")
+    }
+
+    /// Leaves the given characters unescaped. Non-ASCII characters are always escaped, and are
+    /// ignored if given here.
+    ///
+    /// # Parameters
+    ///
+    /// * `chars` - The characters to write as is.
+    pub const fn leave_unescaped(mut self, chars: &str) -> Self {
+        let bytes = chars.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i].is_ascii() {
+                self.unescaped |= 1 << bytes[i];
+            }
+            i += 1;
+        }
+        self
+    }
+
+    /// Returns whether the encoder writes the given byte as is.
+    ///
+    /// # Parameters
+    ///
+    /// * `byte` - The byte to check.
+    pub const fn is_unescaped(&self, byte: u8) -> bool {
+        byte.is_ascii() && self.unescaped & (1 << byte) != 0
+    }
+
+    /// Returns the length of a string once encoded.
+    ///
+    /// # Parameters
+    ///
+    /// * `s` - The string to encode.
+    pub fn encoded_len(&self, s: &str) -> usize {
+        s.bytes()
+            .map(|b| if self.is_unescaped(b) { 1 } else { 3 })
+            .sum()
+    }
+
+    /// Appends an encoded string to a buffer.
+    ///
+    /// # Parameters
+    ///
+    /// * `out` - The buffer to append to.
+    /// * `s` - The string to encode.
+    pub fn encode_into(&self, out: &mut String, s: &str) {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+        // Copy runs of unescaped characters at once. These are always ASCII, so the runs start
+        // and end on character boundaries even when the escaped bytes before them do not.
+        let mut start = 0;
+        for (i, b) in s.bytes().enumerate() {
+            if self.is_unescaped(b) {
+                continue;
+            }
+            if start < i {
+                out.push_str(&s[start..i]);
+            }
+            out.push('%');
+            out.push(HEX[usize::from(b >> 4)] as char);
+            out.push(HEX[usize::from(b & 0xf)] as char);
+            start = i + 1;
+        }
+        out.push_str(&s[start..]);
+    }
+
+    /// Encodes a string.
+    ///
+    /// # Parameters
+    ///
+    /// * `s` - The string to encode.
+    pub fn encode(&self, s: &str) -> String {
+        let mut out = String::with_capacity(self.encoded_len(s));
+        self.encode_into(&mut out, s);
+        out
+    }
+
+    /// Returns the length of a `key=value` pair once encoded, including the separator before it.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the parameter.
+    /// * `value` - The value of the parameter.
+    pub fn pair_len(&self, key: &str, value: &str) -> usize {
+        1 + self.encoded_len(key) + 1 + self.encoded_len(value)
+    }
+
+    /// Appends an encoded `key=value` pair to a URL, preceded by `?` if the URL has no query
+    /// string yet, or `&` otherwise.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL to append to.
+    /// * `key` - The key of the parameter.
+    /// * `value` - The value of the parameter.
+    pub fn append_pair(&self, url: &mut String, key: &str, value: &str) {
+        url.push(if url.contains('?') { '&' } else { '?' });
+        self.encode_into(url, key);
+        url.push('=');
+        self.encode_into(url, value);
+    }
+}
+
+impl Default for QueryEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_all_but_unreserved_characters() {
+        let encoder = QueryEncoder::new();
+        assert_eq!(encoder.encode("aZ09-._~"), "aZ09-._~");
+        assert_eq!(encoder.encode("a b,c&d=e/f"), "a%20b%2Cc%26d%3De%2Ff");
+        assert_eq!(encoder.encode("%"), "%25");
+        assert_eq!(encoder.encode(""), "");
+    }
+
+    #[test]
+    fn escapes_non_ascii_bytes() {
+        let encoder = QueryEncoder::new().leave_unescaped("é,");
+        assert_eq!(encoder.encode("Māngere,é"), "M%C4%81ngere,%C3%A9");
+        assert!(!encoder.is_unescaped(0xC3));
+    }
+
+    #[test]
+    fn leaves_commas_in_at_queries() {
+        assert_eq!(AT_QUERY.encode("1,2 3"), "1,2%203");
+        assert_eq!(QueryEncoder::new().encode("1,2"), "1%2C2");
+    }
+
+    #[test]
+    fn lengths_match_the_encoding() {
+        for s in ["", "abc", "a b", "Māngere", "1,2,3"] {
+            assert_eq!(AT_QUERY.encoded_len(s), AT_QUERY.encode(s).len());
+        }
+
+        let mut url = String::from("https://example.com/path");
+        AT_QUERY.append_pair(&mut url, "tripid", "1,2");
+        assert_eq!(url.len(), 24 + AT_QUERY.pair_len("tripid", "1,2"));
+        AT_QUERY.append_pair(&mut url, "vehicle id", "a&b");
+        assert_eq!(
+            url,
+            "https://example.com/path?tripid=1,2&vehicle%20id=a%26b"
+        );
+    }
+}
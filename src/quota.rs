@@ -0,0 +1,78 @@
+//! Quota usage reported by the AT API gateway.
+
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::HeaderMap;
+
+/// The state of the account quota, as reported by the rate limit headers of the most recent
+/// response.
+///
+/// Both the `X-RateLimit-*` headers and the standard `RateLimit-*` headers are read. Values
+/// which the gateway did not send are [`None`].
+///
+/// [`None`]: std::option::Option::None
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct QuotaInfo {
+    /// The number of requests allowed in the current window.
+    pub limit: Option<u64>,
+    /// The number of requests remaining in the current window.
+    pub remaining: Option<u64>,
+    /// How long until the current window resets, as of `updated_at`.
+    pub reset: Option<Duration>,
+    /// When the response carrying these values was received.
+    pub updated_at: SystemTime,
+}
+
+impl QuotaInfo {
+    /// Reads the quota from the headers of a response, returning [`None`] if the response
+    /// carried no rate limit headers.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - The response headers.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let limit = header_u64(headers, "limit");
+        let remaining = header_u64(headers, "remaining");
+        let reset = header_u64(headers, "reset").map(Duration::from_secs);
+
+        if limit.is_none() && remaining.is_none() && reset.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            limit,
+            remaining,
+            reset,
+            updated_at: SystemTime::now(),
+        })
+    }
+
+    /// Returns the fraction of the quota which remains in the current window, between 0 and 1.
+    pub fn remaining_fraction(&self) -> Option<f64> {
+        match (self.remaining, self.limit) {
+            (Some(remaining), Some(limit)) if limit > 0 => {
+                Some((remaining as f64 / limit as f64).min(1.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads the rate limit header with the given suffix as an integer.
+///
+/// # Parameters
+///
+/// * `headers` - The response headers.
+/// * `suffix` - The suffix of the header name, such as `remaining`.
+fn header_u64(headers: &HeaderMap, suffix: &str) -> Option<u64> {
+    [
+        format!("x-ratelimit-{}", suffix),
+        format!("ratelimit-{}", suffix),
+    ]
+    .iter()
+    .filter_map(|name| headers.get(name.as_str()))
+    .find_map(|value| value.to_str().ok()?.trim().parse().ok())
+}
@@ -1,31 +1,324 @@
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
 use crate::{
-    error::Result,
-    types::{gtfs::Entity, ATResponse, Header},
-    BASE_API_URL,
+    breaker::CircuitBreaker,
+    buffer::{self, BufferStats, OverflowPolicy},
+    builder::RealtimeBuilder,
+    cache::ResponseCache,
+    cancellations::{cancellation_events, CancellationEvent},
+    decode::{decode_entities, Merger},
+    drift::SchemaMonitor,
+    error::{Error, Result},
+    health::Health,
+    hooks::StreamHooks,
+    ids::Ids,
+    limiter::RateLimiter,
+    live::LiveFeed,
+    meta::ResponseMeta,
+    middleware::Middleware,
+    options::RequestOptions,
+    outcome::FetchOutcome,
+    query::AT_QUERY,
+    quota::QuotaInfo,
+    recorder::Recorder,
+    retry::{parse_retry_after, RetryPolicy},
+    rules::{self, Alert, AlertEngine},
+    timer::{timeout, Timer, TokioTimer},
+    transport::Transport,
+    types::{gtfs::Entity, Header},
+    validation::{self, ValidationReport, Validator},
+    ApiVersion, CombinedResponse, FetchStrategy, BASE_API_URL, DEFAULT_MIN_POLL_INTERVAL,
+    DEFAULT_USER_AGENT,
+};
+use bytes::{Bytes, BytesMut};
+use futures_util::{
+    future,
+    stream::{self, Stream},
+    StreamExt,
 };
-use reqwest::{Client, Method};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER,
+        USER_AGENT,
+    },
+    Method, Request, Response, StatusCode, Url,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+/// The sender used to share the response of an in-flight request with a waiting caller.
+type Waiter = oneshot::Sender<Result<Option<Bytes>>>;
+
+/// Requests which are currently being sent, keyed by URL and whether the request is conditional,
+/// with the callers waiting on them.
+type InFlight = Mutex<HashMap<(String, bool), Vec<Waiter>>>;
+
+/// Removes an in-flight request when its caller finishes or is cancelled.
+struct InFlightGuard<'a> {
+    in_flight: &'a InFlight,
+    key: (String, bool),
+}
+
+impl InFlightGuard<'_> {
+    /// Removes the in-flight request and returns the callers waiting on it.
+    fn finish(mut self) -> Vec<Waiter> {
+        let key = std::mem::take(&mut self.key);
+        let waiters = self.in_flight.lock().unwrap().remove(&key);
+        std::mem::forget(self);
+        waiters.unwrap_or_default()
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// The validators of a previous response, sent with conditional requests.
+#[derive(Debug, Clone)]
+struct Validators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+}
+
+impl Validators {
+    /// Reads the validators from the headers of a response, returning [`None`] if it has none.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - The response headers.
+    ///
+    /// [`None`]: std::option::Option::None
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let etag = headers.get(ETAG).cloned();
+        let last_modified = headers.get(LAST_MODIFIED).cloned();
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Adds the conditional request headers for the validators to a request.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - The request headers.
+    fn apply(&self, headers: &mut HeaderMap) {
+        if let Some(etag) = self.etag.as_ref() {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = self.last_modified.as_ref() {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+}
 
 /// A client for interacting with the Auckland Transport GTFS realtime API.
-pub struct Realtime<'a> {
-    client: Client,
-    api_key: &'a str,
+///
+/// The client is cheap to clone and can be shared between tasks and threads. Clones share the
+/// same transport (and so the same connection pool), and the same rate limiter, circuit breaker,
+/// cache and in-flight requests, so they behave as one client.
+#[derive(Clone)]
+pub struct Realtime {
+    transport: Arc<dyn Transport>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    api_key: Arc<str>,
+    pub(crate) base_url: Arc<str>,
+    pub(crate) api_version: ApiVersion,
+    pub(crate) fetch_strategy: FetchStrategy,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) max_response_size: Option<usize>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) breaker: Option<Arc<CircuitBreaker>>,
+    pub(crate) limiter: Option<Arc<RateLimiter>>,
+    quota: Arc<RwLock<Option<QuotaInfo>>>,
+    pub(crate) coalesce: bool,
+    pub(crate) cache: Option<Arc<ResponseCache>>,
+    pub(crate) min_poll_interval: Duration,
+    pub(crate) timer: Arc<dyn Timer>,
+    pub(crate) default_headers: HeaderMap,
+    in_flight: Arc<InFlight>,
+    validators: Arc<Mutex<HashMap<String, Validators>>>,
+    fingerprints: Arc<Mutex<HashMap<String, u64>>>,
+    recorder: Option<Recorder>,
+    schema_monitor: Option<Arc<SchemaMonitor>>,
+    #[cfg(feature = "prometheus")]
+    metrics: Option<Arc<crate::prometheus::Metrics>>,
 }
 
-impl<'a> Realtime<'a> {
+// The client must stay cloneable and thread-safe, as applications share it between tasks.
+const _: fn() = || {
+    fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+    assert_shareable::<Realtime>();
+};
+
+impl Realtime {
     /// Creates a new Auckland Transport GTFS realtime client.
     ///
     /// # Parameters
     ///
     /// * `api_key` - The API key to use when interacting with the API.
-    pub fn new(api_key: &'a str) -> Self {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self::builder(api_key).build()
+    }
+
+    /// Creates a new client using the API key in the `AT_API_KEY` environment variable.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::MissingApiKey`] if the environment variable is not set or empty.
+    ///
+    /// [`Error::MissingApiKey`]: crate::error::Error::MissingApiKey
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(crate::config::api_key_from_env()?))
+    }
+
+    /// Creates a new client from a JSON configuration file. See [`Config`] for the format of
+    /// the file.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the configuration file.
+    ///
+    /// [`Config`]: crate::Config
+    pub fn from_config_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Ok(crate::Config::from_file(path)?.builder()?.build())
+    }
+
+    /// Creates a builder for a client, which allows configuring the client before it is
+    /// constructed.
+    ///
+    /// # Parameters
+    ///
+    /// * `api_key` - The API key to use when interacting with the API.
+    pub fn builder<S: Into<String>>(api_key: S) -> RealtimeBuilder {
+        RealtimeBuilder::new(api_key.into().into())
+    }
+
+    pub(crate) fn from_parts(api_key: Arc<str>, transport: Arc<dyn Transport>) -> Self {
         Self {
-            client: Client::new(),
+            transport,
+            middleware: vec![],
             api_key,
+            base_url: BASE_API_URL.into(),
+            api_version: ApiVersion::default(),
+            fetch_strategy: FetchStrategy::default(),
+            timeout: None,
+            read_timeout: None,
+            max_response_size: None,
+            retry: RetryPolicy::none(),
+            breaker: None,
+            limiter: None,
+            quota: Arc::default(),
+            coalesce: true,
+            cache: None,
+            min_poll_interval: DEFAULT_MIN_POLL_INTERVAL,
+            timer: Arc::new(TokioTimer),
+            default_headers: default_headers(),
+            in_flight: Arc::default(),
+            validators: Arc::default(),
+            fingerprints: Arc::default(),
+            recorder: None,
+            schema_monitor: None,
+            #[cfg(feature = "prometheus")]
+            metrics: None,
         }
     }
 
+    /// Sets the transport used to execute HTTP requests, replacing the default reqwest client.
+    ///
+    /// # Parameters
+    ///
+    /// * `transport` - The transport to execute requests with.
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Adds a middleware to the client, which runs around every request made. Middleware run in
+    /// the order they are added.
+    ///
+    /// # Parameters
+    ///
+    /// * `middleware` - The middleware to add.
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Attaches a recorder to the client, which writes every raw realtime response received from
+    /// AT to disk before it is parsed. Responses which are unchanged since the previous
+    /// conditional fetch are not recorded again. A failure to write a response is logged with
+    /// the `tracing` feature, and does not fail the fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `recorder` - The recorder to write responses with.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Attaches a schema monitor to the client, which checks every raw realtime response for
+    /// fields and enum values the crate does not model. Each response is parsed an extra time.
+    ///
+    /// # Parameters
+    ///
+    /// * `monitor` - The monitor to check responses with, which can be shared between clients.
+    pub fn with_schema_monitor(mut self, monitor: Arc<SchemaMonitor>) -> Self {
+        self.schema_monitor = Some(monitor);
+        self
+    }
+
+    /// Attaches a Prometheus metrics registry to the client, which is updated after every fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `metrics` - The registry to update.
+    #[cfg(feature = "prometheus")]
+    pub fn with_metrics(mut self, metrics: Arc<crate::prometheus::Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Returns the account quota reported by the rate limit headers of the most recent response,
+    /// or [`None`] if no response has carried them yet.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn quota(&self) -> Option<QuotaInfo> {
+        self.quota.read().unwrap().clone()
+    }
+
+    /// Sends a minimal authenticated request to the AT API and classifies the result, so
+    /// services can verify connectivity and the API key at startup and in readiness probes.
+    ///
+    /// The request asks the vehicle positions endpoint for a vehicle which does not exist, so
+    /// the response is small. It is sent once, without retries, coalescing or the cache, but
+    /// through the rate limiter and middleware like any other request.
+    pub async fn health_check(&self) -> Health {
+        let url = self.endpoint(
+            self.api_version.vehicle_positions_path(),
+            &[("vehicleid", Some("health-check"))],
+        );
+
+        let start = std::time::Instant::now();
+        let result = self.get_once(&url, &RequestOptions::default()).await;
+        Health::from_result(result, start.elapsed())
+    }
+
     /// Fetches both trip updates and vehicle positions from the AT API.
     ///
     /// AT sends the trip updates and vehicle positions seperate, these are joined together upon
@@ -41,90 +334,1129 @@ impl<'a> Realtime<'a> {
     ///
     /// # Returns
     ///
-    /// Returns a tuple where the first item is the response header received from AT, and the
-    /// second item is a vector of AT vehicles.
+    /// Returns the response header received from AT and the merged vehicles, along with the
+    /// entities which could not be merged. See [`CombinedResponse`].
     ///
     /// [`None`]: std::option::Option::None
-    pub async fn fetch_combined<'b>(
+    /// [`CombinedResponse`]: crate::CombinedResponse
+    pub async fn fetch_combined(
         &self,
-        trip_ids: Option<&Vec<&'b str>>,
-        vehicle_ids: Option<&Vec<&'b str>>,
-    ) -> Result<(Header, Vec<Entity>)> {
-        let url = format!("{}/public/realtime", BASE_API_URL);
-        let mut params = vec![];
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<CombinedResponse> {
+        self.fetch_combined_with_options(trip_ids, vehicle_ids, &RequestOptions::default())
+            .await
+    }
 
-        if let Some(trips) = trip_ids {
-            params.push(("tripid", trips.join(",")));
+    /// Fetches both trip updates and vehicle positions from the AT API in the same way as
+    /// [`fetch_combined`], overriding the client configuration for this call.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    /// * `options` - The options to override for this call.
+    ///
+    /// [`fetch_combined`]: Realtime::fetch_combined
+    pub async fn fetch_combined_with_options(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+        options: &RequestOptions,
+    ) -> Result<CombinedResponse> {
+        match self
+            .fetch_combined_outcome(&trip_ids.into(), &vehicle_ids.into(), options)
+            .await?
+        {
+            FetchOutcome::Updated(combined) => Ok(combined),
+            FetchOutcome::NotModified | FetchOutcome::Unchanged => {
+                unreachable!("validators are only sent by conditional fetches")
+            }
         }
+    }
 
-        if let Some(vehicles) = vehicle_ids {
-            params.push(("vehicleid", vehicles.join(",")));
-        }
+    /// Fetches both trip updates and vehicle positions from the AT API in the same way as
+    /// [`fetch_combined`], and validates the response against the semantics of GTFS-RT. See
+    /// [`Validator`].
+    ///
+    /// Stop sequences can only be checked across snapshots, so use [`stream_validated`] to check
+    /// that trips do not go backwards.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// # Returns
+    ///
+    /// Returns the response in the same form as [`fetch_combined`], with the problems found in
+    /// it.
+    ///
+    /// [`fetch_combined`]: Realtime::fetch_combined
+    /// [`stream_validated`]: Realtime::stream_validated
+    /// [`Validator`]: crate::validation::Validator
+    pub async fn fetch_combined_validated(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<(CombinedResponse, ValidationReport)> {
+        let combined = self.fetch_combined(trip_ids, vehicle_ids).await?;
+        let report = Validator::new().validate(&combined);
+        Ok((combined, report))
+    }
 
-        let resp = self
-            .request(Method::GET, Self::build_query(url, &params))
-            .send()
-            .await?
-            .json::<ATResponse>()
+    /// Fetches both trip updates and vehicle positions from the AT API in the same way as
+    /// [`fetch_combined`], along with metadata about the response, such as the request ID the
+    /// API gateway gave it, which AT asks for in support tickets.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// # Returns
+    ///
+    /// Returns the response in the same form as [`fetch_combined`], with the metadata of the last
+    /// HTTP response it was built from. With the split fetch strategy, that is whichever of the
+    /// trip updates and vehicle positions arrived last. The metadata is [`None`] if no request
+    /// was sent, such as when the response was served from the cache or shared with a
+    /// concurrent fetch of the same URL.
+    ///
+    /// [`fetch_combined`]: Realtime::fetch_combined
+    /// [`None`]: std::option::Option::None
+    pub async fn fetch_combined_with_meta(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<(CombinedResponse, Option<ResponseMeta>)> {
+        let sink = Arc::default();
+        let options = RequestOptions {
+            meta: Some(Arc::clone(&sink)),
+            ..RequestOptions::default()
+        };
+        let combined = self
+            .fetch_combined_with_options(trip_ids, vehicle_ids, &options)
             .await?;
+        let meta = sink.lock().unwrap().take();
+        Ok((combined, meta))
+    }
+
+    /// Fetches both trip updates and vehicle positions from the AT API in the same way as
+    /// [`fetch_combined`], sending the validators from the previous response to the same query
+    /// so that AT can skip sending the feed if it has not changed.
+    ///
+    /// The `ETag` and `Last-Modified` headers of each response are kept, and sent back as
+    /// `If-None-Match` and `If-Modified-Since` on the next call with the same parameters. If AT
+    /// ignores them and sends the same feed again, the feed is recognised by a hash of its
+    /// content and returned as [`FetchOutcome::Unchanged`] without being decoded.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`FetchOutcome::NotModified`] if AT responded with `304 Not Modified`,
+    /// [`FetchOutcome::Unchanged`] if the feed is identical to the previous one, otherwise the
+    /// response in the same form as [`fetch_combined`].
+    ///
+    /// [`fetch_combined`]: Realtime::fetch_combined
+    pub async fn fetch_combined_if_modified(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<FetchOutcome<CombinedResponse>> {
+        let options = RequestOptions {
+            conditional: true,
+            ..RequestOptions::default()
+        };
+        self.fetch_combined_outcome(&trip_ids.into(), &vehicle_ids.into(), &options)
+            .await
+    }
+
+    /// Fetches both trip updates and vehicle positions, recording the outcome in the tracing
+    /// span and metrics.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    /// * `options` - The options to override for this call.
+    async fn fetch_combined_outcome(
+        &self,
+        trip_ids: &Ids,
+        vehicle_ids: &Ids,
+        options: &RequestOptions,
+    ) -> Result<FetchOutcome<CombinedResponse>> {
+        #[cfg(any(feature = "prometheus", feature = "tracing"))]
+        let start = Instant::now();
+        let fut = self.fetch_combined_inner(trip_ids, vehicle_ids, options);
+
+        #[cfg(feature = "tracing")]
+        let result = {
+            use tracing::Instrument;
+
+            let span = tracing::debug_span!(
+                "fetch_combined",
+                trip_ids = trip_ids.len(),
+                vehicle_ids = vehicle_ids.len(),
+            );
+            fut.instrument(span).await
+        };
+        #[cfg(not(feature = "tracing"))]
+        let result = fut.await;
+
+        #[cfg(feature = "tracing")]
+        match result.as_ref() {
+            Ok(FetchOutcome::Updated(combined)) => tracing::debug!(
+                entities = combined.entities.len(),
+                elapsed_ms = start.elapsed().as_millis() as u64,
+                "fetched combined feed"
+            ),
+            Ok(_) => tracing::debug!(
+                elapsed_ms = start.elapsed().as_millis() as u64,
+                "combined feed not modified"
+            ),
+            Err(e) => tracing::warn!(error = %e, "failed to fetch combined feed"),
+        }
+
+        #[cfg(feature = "prometheus")]
+        if let Some(metrics) = self.metrics.as_ref() {
+            match result.as_ref() {
+                Ok(FetchOutcome::Updated(combined)) => {
+                    metrics.record_fetch(start.elapsed(), combined)
+                }
+                Ok(_) => {}
+                Err(e) => metrics.record_error(e),
+            }
+            if let Some(quota) = self.quota() {
+                metrics.record_quota(&quota);
+            }
+        }
+
+        result
+    }
+
+    async fn fetch_combined_inner(
+        &self,
+        trip_ids: &Ids,
+        vehicle_ids: &Ids,
+        options: &RequestOptions,
+    ) -> Result<FetchOutcome<CombinedResponse>> {
+        let params = Self::query_params(trip_ids, vehicle_ids);
+        let url = self.endpoint(self.api_version.realtime_path(), &params);
+        // A cached response may be one the caller has already seen, so conditional fetches send
+        // their validators instead, and only unconditional fetches are served from the cache.
+        if !options.conditional {
+            if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.get(&url)) {
+                return Ok(FetchOutcome::Updated(cached));
+            }
+        }
+
+        let mut merger = Merger::new();
+        let header = match self.fetch_strategy {
+            FetchStrategy::Split => {
+                let trips_url = self.endpoint(self.api_version.trip_updates_path(), &params);
+                let vehicles_url =
+                    self.endpoint(self.api_version.vehicle_positions_path(), &params);
+                let (trips, vehicles) = future::join(
+                    self.get_body(&trips_url, options),
+                    self.get_body(&vehicles_url, options),
+                )
+                .await;
 
-        let mut merged = vec![];
-        let entities: HashMap<_, _> = resp
-            .response
-            .entity
+                let unconditional = RequestOptions {
+                    conditional: false,
+                    ..options.clone()
+                };
+                let (trips, vehicles) = (trips?, vehicles?);
+                let changed = |url: &str, body: &Option<Bytes>| {
+                    body.as_ref()
+                        .is_some_and(|body| !self.is_unchanged(url, body, options))
+                };
+                if !changed(&trips_url, &trips) && !changed(&vehicles_url, &vehicles) {
+                    return Ok(match (trips, vehicles) {
+                        (None, None) => FetchOutcome::NotModified,
+                        _ => FetchOutcome::Unchanged,
+                    });
+                }
+
+                let (trips, vehicles) = match (trips, vehicles) {
+                    (Some(trips), Some(vehicles)) => (trips, vehicles),
+                    // Only one of the feeds changed, so the other is needed in full to merge.
+                    (trips, vehicles) => (
+                        match trips {
+                            Some(trips) => trips,
+                            None => self.get_required(&trips_url, &unconditional).await?,
+                        },
+                        match vehicles {
+                            Some(vehicles) => vehicles,
+                            None => self.get_required(&vehicles_url, &unconditional).await?,
+                        },
+                    ),
+                };
+
+                if let Some(recorder) = self.recorder.as_ref() {
+                    log_record_error(recorder.record_split(self.api_version, &trips, &vehicles));
+                }
+
+                let header = self.decode_into(&trips_url, &trips, &mut merger)?;
+                self.decode_into(&vehicles_url, &vehicles, &mut merger)?;
+                self.remember(&trips_url, &trips, options);
+                self.remember(&vehicles_url, &vehicles, options);
+                header
+            }
+            FetchStrategy::Combined => match self.get_body(&url, options).await? {
+                Some(body) if self.is_unchanged(&url, &body, options) => {
+                    return Ok(FetchOutcome::Unchanged)
+                }
+                Some(body) => {
+                    if let Some(recorder) = self.recorder.as_ref() {
+                        log_record_error(recorder.record(self.api_version, &body));
+                    }
+
+                    let header = self.decode_into(&url, &body, &mut merger)?;
+                    self.remember(&url, &body, options);
+                    header
+                }
+                None => return Ok(FetchOutcome::NotModified),
+            },
+        };
+
+        let combined = merger.finish(header);
+        if let Some(cache) = self.cache.as_ref() {
+            cache.put(&url, &combined);
+        }
+
+        Ok(FetchOutcome::Updated(combined))
+    }
+
+    /// Builds the requests [`fetch_combined_with_options`] would send, without sending them.
+    ///
+    /// This is one request with the combined fetch strategy, or a request for the trip updates
+    /// followed by a request for the vehicle positions with the split strategy. The requests
+    /// include the API key and default headers, but middleware has not been run on them.
+    /// They can be inspected, or executed through a different HTTP stack.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    /// * `options` - The options to override for these requests.
+    ///
+    /// [`fetch_combined_with_options`]: Realtime::fetch_combined_with_options
+    pub fn prepare_combined(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+        options: &RequestOptions,
+    ) -> Result<Vec<Request>> {
+        let (trip_ids, vehicle_ids) = (trip_ids.into(), vehicle_ids.into());
+        let params = Self::query_params(&trip_ids, &vehicle_ids);
+        let paths = match self.fetch_strategy {
+            FetchStrategy::Combined => vec![self.api_version.realtime_path()],
+            FetchStrategy::Split => vec![
+                self.api_version.trip_updates_path(),
+                self.api_version.vehicle_positions_path(),
+            ],
+        };
+
+        paths
             .into_iter()
-            .map(|e| (e.id.clone(), e))
-            .collect();
+            .map(|path| self.get_request(&self.endpoint(path, &params), options))
+            .collect()
+    }
+
+    /// Returns the query parameters of a combined fetch.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    fn query_params<'a>(
+        trip_ids: &'a Ids,
+        vehicle_ids: &'a Ids,
+    ) -> [(&'static str, Option<&'a str>); 2] {
+        [
+            ("tripid", trip_ids.as_query()),
+            ("vehicleid", vehicle_ids.as_query()),
+        ]
+    }
+
+    /// Builds the URL of an endpoint with the given query parameters.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the endpoint, relative to the base URL.
+    /// * `params` - The query parameters.
+    fn endpoint(&self, path: &str, params: &[(&str, Option<&str>)]) -> String {
+        // Parameters without values are left out. The encoder leaves commas unescaped as AT
+        // requires them to separate the vehicle and trip IDs.
+        let params = params.iter().filter_map(|(k, value)| Some((*k, (*value)?)));
+        let query_len: usize = params.clone().map(|(k, v)| AT_QUERY.pair_len(k, v)).sum();
+
+        let mut url = String::with_capacity(self.base_url.len() + path.len() + query_len);
+        url.push_str(&self.base_url);
+        url.push_str(path);
+        for (k, value) in params {
+            AT_QUERY.append_pair(&mut url, k, value);
+        }
+        url
+    }
+
+    /// Fetches a realtime response body, or returns [`None`] if the request was
+    /// conditional and AT responded with `304 Not Modified`.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL to fetch.
+    /// * `options` - The options to override for this request.
+    ///
+    /// [`None`]: std::option::Option::None
+    async fn get_body(&self, url: &str, options: &RequestOptions) -> Result<Option<Bytes>> {
+        let body = match self.get(url, options).await? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+
+        if let Some(monitor) = self.schema_monitor.as_ref() {
+            monitor.inspect(&body);
+        }
+
+        Ok(Some(body))
+    }
+
+    /// Fetches a realtime response body with an unconditional request.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL to fetch.
+    /// * `options` - The options to override for this request, which must not be conditional.
+    async fn get_required(&self, url: &str, options: &RequestOptions) -> Result<Bytes> {
+        match self.get_body(url, options).await? {
+            Some(body) => Ok(body),
+            None => unreachable!("validators are only sent by conditional fetches"),
+        }
+    }
+
+    /// Returns whether a body is identical to the last body decoded from the same URL. This is
+    /// only checked for conditional fetches.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL the body was received from.
+    /// * `body` - The response body.
+    /// * `options` - The options of the fetch.
+    fn is_unchanged(&self, url: &str, body: &[u8], options: &RequestOptions) -> bool {
+        options.conditional
+            && self.fingerprints.lock().unwrap().get(url) == Some(&fingerprint(body))
+    }
+
+    /// Remembers the fingerprint of a body which was decoded for a conditional fetch, so an
+    /// identical body can be recognised by [`is_unchanged`].
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL the body was received from.
+    /// * `body` - The response body.
+    /// * `options` - The options of the fetch.
+    ///
+    /// [`is_unchanged`]: Realtime::is_unchanged
+    fn remember(&self, url: &str, body: &[u8], options: &RequestOptions) {
+        if options.conditional {
+            let mut fingerprints = self.fingerprints.lock().unwrap();
+            fingerprints.insert(url.to_string(), fingerprint(body));
+        }
+    }
+
+    /// Decodes a realtime response body, feeding its entities into a merger as they are parsed.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL the body was received from.
+    /// * `body` - The response body.
+    /// * `merger` - The merger to add the entities to.
+    ///
+    /// # Returns
+    ///
+    /// Returns the header of the response.
+    fn decode_into(&self, url: &str, body: &[u8], merger: &mut Merger) -> Result<Header> {
+        let header = decode_entities(self.api_version, body, |entity| merger.push(entity))
+            .map_err(|e| e.with_url(url))?;
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            bytes = body.len(),
+            entities = merger.len(),
+            "parsed response"
+        );
+
+        Ok(header)
+    }
+
+    /// Polls the AT API for all trip updates and vehicle positions at a fixed interval.
+    ///
+    /// The first fetch is made immediately when the stream is first polled, and subsequent
+    /// fetches are made `interval` after the previous fetch completed. Errors are yielded as
+    /// items and do not end the stream.
+    ///
+    /// Intervals shorter than the client's minimum poll interval are clamped to it.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    ///
+    /// # Returns
+    ///
+    /// Returns a never-ending stream of merged snapshots, in the same form as returned by
+    /// [`fetch_combined`].
+    ///
+    /// [`fetch_combined`]: Realtime::fetch_combined
+    pub fn stream(&self, interval: Duration) -> impl Stream<Item = Result<CombinedResponse>> + '_ {
+        self.stream_with_hooks(interval, StreamHooks::default())
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], running the given
+    /// hooks as each fetch completes.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    /// * `hooks` - The callbacks to run.
+    ///
+    /// [`stream`]: Realtime::stream
+    pub fn stream_with_hooks(
+        &self,
+        interval: Duration,
+        hooks: StreamHooks,
+    ) -> impl Stream<Item = Result<CombinedResponse>> + '_ {
+        self.stream_until_cancelled(interval, hooks, CancellationToken::new())
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], but from a separate
+    /// future which hands snapshots to the stream through a buffer of fixed capacity. See
+    /// [`buffer`] for how this differs from [`stream`].
+    ///
+    /// The returned poller must be awaited or spawned for the stream to yield anything, such as
+    /// with `tokio::spawn(poller)`. It runs until the stream is dropped, and borrows nothing from
+    /// this client, as it polls through a clone of it.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    /// * `capacity` - The maximum number of snapshots to buffer, at least 1.
+    /// * `policy` - What to do with a new snapshot when the buffer is full.
+    ///
+    /// # Returns
+    ///
+    /// Returns the poller, the stream of snapshots, and the counters of the buffer.
+    ///
+    /// [`stream`]: Realtime::stream
+    /// [`buffer`]: crate::buffer
+    pub fn buffered_stream(
+        &self,
+        interval: Duration,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> (
+        impl Future<Output = ()> + Send + 'static,
+        impl Stream<Item = Result<CombinedResponse>> + Send + 'static,
+        BufferStats,
+    ) {
+        let (sender, consumer, stats) = buffer::channel(capacity, policy);
+        let realtime = self.clone();
+        let poller = async move { sender.forward(realtime.stream(interval)).await };
+        (poller, consumer, stats)
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], publishing each
+    /// snapshot to a [`LiveFeed`] which synchronous readers can take the latest snapshot from.
+    ///
+    /// The returned poller must be awaited or spawned for the feed to be filled, such as with
+    /// `tokio::spawn(poller)`. It runs until every handle to the feed has been dropped.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    ///
+    /// [`stream`]: Realtime::stream
+    /// [`LiveFeed`]: crate::live::LiveFeed
+    pub fn live_feed(&self, interval: Duration) -> (LiveFeed, impl Future<Output = ()> + Send) {
+        let (tx, feed) = LiveFeed::new();
+        let realtime = self.clone();
+        let poller = async move {
+            let publish = realtime.stream(interval).for_each(|result| {
+                if let Ok(combined) = result {
+                    tx.send_replace(Some(Arc::new(combined)));
+                }
+                future::ready(())
+            });
+            future::select(Box::pin(tx.closed()), Box::pin(publish)).await;
+        };
+
+        (feed, poller)
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], yielding the alerts
+    /// raised by the rules of an engine. See [`rules`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    /// * `engine` - The engine with the rules to evaluate.
+    ///
+    /// [`stream`]: Realtime::stream
+    /// [`rules`]: crate::rules
+    pub fn stream_alerts(
+        &self,
+        interval: Duration,
+        engine: AlertEngine,
+    ) -> impl Stream<Item = Result<Alert>> + '_ {
+        rules::alerts(self.stream(interval), engine)
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], yielding an event each
+    /// time a trip is cancelled or reinstated. See [`cancellation_events`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    ///
+    /// [`stream`]: Realtime::stream
+    /// [`cancellation_events`]: crate::cancellations::cancellation_events
+    pub fn stream_cancellations(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<CancellationEvent>> + '_ {
+        cancellation_events(self.stream(interval))
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], yielding the entities
+    /// for trips which are not in the static schedule from each snapshot. See
+    /// [`CombinedResponse::extra_services`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    ///
+    /// [`stream`]: Realtime::stream
+    /// [`CombinedResponse::extra_services`]: crate::CombinedResponse::extra_services
+    pub fn stream_extra_services(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<Vec<Entity>>> + '_ {
+        self.stream(interval)
+            .map(|result| result.map(|combined| combined.extra_services().cloned().collect()))
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream`], yielding each snapshot
+    /// with the problems found in it. See [`Validator`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    ///
+    /// [`stream`]: Realtime::stream
+    /// [`Validator`]: crate::validation::Validator
+    pub fn stream_validated(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<(CombinedResponse, ValidationReport)>> + '_ {
+        validation::validated(self.stream(interval))
+    }
+
+    /// Polls the AT API at a fixed interval in the same way as [`stream_with_hooks`], until the
+    /// given token is cancelled.
+    ///
+    /// Once the token is cancelled, any in-flight fetch or wait is abandoned and the stream ends.
+    /// Dropping the stream stops polling in the same way.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between fetches.
+    /// * `hooks` - The callbacks to run.
+    /// * `token` - The token which ends the stream when cancelled.
+    ///
+    /// [`stream_with_hooks`]: Realtime::stream_with_hooks
+    pub fn stream_until_cancelled(
+        &self,
+        interval: Duration,
+        hooks: StreamHooks,
+        token: CancellationToken,
+    ) -> impl Stream<Item = Result<CombinedResponse>> + '_ {
+        let interval = self.poll_interval(interval);
+
+        struct State {
+            hooks: StreamHooks,
+            token: CancellationToken,
+            first: bool,
+            last_timestamp: Option<Option<f64>>,
+        }
+
+        let state = State {
+            hooks,
+            token,
+            first: true,
+            last_timestamp: None,
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            let first = state.first;
+            let fetch = async {
+                if !first {
+                    self.timer.sleep(interval).await;
+                }
+                self.fetch_combined(None, None).await
+            };
+
+            let result = state.token.run_until_cancelled(fetch).await?;
+            state.first = false;
+            let changed = match result.as_ref() {
+                Ok(combined) => {
+                    let timestamp = combined.header.timestamp;
+                    let changed = state.last_timestamp != Some(timestamp);
+                    state.last_timestamp = Some(timestamp);
+                    changed
+                }
+                Err(_) => false,
+            };
+
+            state.hooks.run(&result, changed);
+            Some((result, state))
+        })
+    }
+
+    /// Clamps a poll interval to the minimum configured for the client.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The requested poll interval.
+    pub(crate) fn poll_interval(&self, interval: Duration) -> Duration {
+        if interval >= self.min_poll_interval {
+            return interval;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            requested_ms = interval.as_millis() as u64,
+            min_ms = self.min_poll_interval.as_millis() as u64,
+            "poll interval is below the minimum, clamping"
+        );
+
+        self.min_poll_interval
+    }
 
-        fn merge(ent: &Entity, hm: &HashMap<String, Entity>) -> Option<Entity> {
-            let trip_id = ent.vehicle.as_ref()?.trip.as_ref()?.trip_id.as_ref()?;
-            let tu_ent = hm.get(trip_id)?;
-            let mut entity = ent.clone();
+    /// Sends a GET request to the given URL and reads the response body, or returns [`None`] if
+    /// the request was conditional and AT responded with `304 Not Modified`.
+    ///
+    /// If request coalescing is enabled and a request to the same URL is already in flight, no
+    /// new request is sent and the response of the in-flight request is shared instead. The
+    /// options of the request which was sent first apply. The caller which sent the request
+    /// receives its error as it is, while the waiting callers receive it as [`Error::Shared`].
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `options` - The options to override for this request.
+    ///
+    /// [`None`]: std::option::Option::None
+    async fn get(&self, url: &str, options: &RequestOptions) -> Result<Option<Bytes>> {
+        if !self.coalesce {
+            return self.get_guarded(url, options).await;
+        }
 
-            if let Some(trip_update) = tu_ent.trip_update.as_ref() {
-                entity.trip_update = Some(trip_update.clone());
+        let key = (url.to_string(), options.conditional);
+        let waiting = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get_mut(&key) {
+                Some(waiters) => {
+                    let (tx, rx) = oneshot::channel();
+                    waiters.push(tx);
+                    Some(rx)
+                }
+                None => {
+                    in_flight.insert(key.clone(), vec![]);
+                    None
+                }
             }
+        };
+
+        if let Some(rx) = waiting {
+            // The in-flight request is dropped without a response if its caller was cancelled,
+            // in which case the request is sent again.
+            return match rx.await {
+                Ok(result) => result,
+                Err(_) => self.get_guarded(url, options).await,
+            };
+        }
 
-            Some(entity)
+        let guard = InFlightGuard {
+            in_flight: &self.in_flight,
+            key,
+        };
+        let result = self.get_guarded(url, options).await;
+        let waiters = guard.finish();
+        if waiters.is_empty() {
+            return result;
         }
 
-        for (_, ent) in entities.iter() {
-            if let Some(ent) = merge(ent, &entities) {
-                merged.push(ent);
+        // The caller which sent the request keeps its own error. Errors which cannot be copied,
+        // such as network errors, are not sent to the waiting callers, which send the request
+        // again themselves.
+        let shared = result
+            .as_ref()
+            .err()
+            .and_then(Error::try_clone)
+            .map(Arc::new);
+        for waiter in waiters {
+            match (result.as_ref(), shared.as_ref()) {
+                (Ok(body), _) => {
+                    let _ = waiter.send(Ok(body.clone()));
+                }
+                (Err(_), Some(e)) => {
+                    let _ = waiter.send(Err(Error::Shared(Arc::clone(e))));
+                }
+                (Err(_), None) => {}
             }
         }
 
-        Ok((resp.response.header, merged))
+        result
     }
 
-    /// Creates a new Reqwest request builder with the given method and URL, with the
-    /// authentication header preset.
+    /// Fetches a path of the static GTFS API and decodes the `response` field of its body.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path to fetch, relative to the base URL.
+    pub(crate) async fn get_static<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        #[derive(Deserialize)]
+        struct StaticResponse<T> {
+            response: T,
+        }
+
+        let url = self.endpoint(path, &[]);
+        let body = match self.get(&url, &RequestOptions::default()).await? {
+            Some(body) => body,
+            None => unreachable!("validators are only sent by conditional fetches"),
+        };
+
+        serde_json::from_slice::<StaticResponse<T>>(&body)
+            .map(|r| r.response)
+            .map_err(|e| Error::decode(e, &body).with_url(&url))
+    }
+
+    /// Sends a GET request to the given URL through the circuit breaker, if configured, and
+    /// reads the response body.
     ///
     /// # Parameters
     ///
-    /// * `method` - The HTTP method to build the request with.
     /// * `url` - The URL to send the request to.
-    fn request(&self, method: Method, url: String) -> reqwest::RequestBuilder {
-        self.client
-            .request(method, url)
-            .header("Ocp-Apim-Subscription-Key", self.api_key)
+    /// * `options` - The options to override for this request.
+    async fn get_guarded(&self, url: &str, options: &RequestOptions) -> Result<Option<Bytes>> {
+        let breaker = match self.breaker.as_ref() {
+            Some(breaker) => breaker,
+            None => return self.get_with_retry(url, options).await,
+        };
+
+        let permit = breaker.check()?;
+        let result = self.get_with_retry(url, options).await;
+        permit.record(result.as_ref().err());
+        result
     }
 
-    /// Builds a query string.
+    /// Sends a GET request to the given URL, retrying according to the retry policy, and reads
+    /// the response body.
     ///
-    /// This is used instead of `RequestBuilder::query` as the AT API requires commas to seperate
-    /// the vehicle and trip IDs, but reqwest escapes commas which AT does not support.
-    fn build_query(mut url: String, params: &[(&str, String)]) -> String {
-        let mut queries = vec![];
-        for (k, v) in params {
-            queries.push(format!("{}={}", k, v));
+    /// # Parameters
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `options` - The options to override for this request.
+    async fn get_with_retry(&self, url: &str, options: &RequestOptions) -> Result<Option<Bytes>> {
+        let policy = options.retry.as_ref().unwrap_or(&self.retry);
+        let mut attempt = 1;
+
+        loop {
+            let result = self.get_once(url, options).await;
+            let retry = attempt < policy.max_attempts
+                && result.as_ref().is_err_and(|e| policy.should_retry_error(e));
+
+            if !retry {
+                return result;
+            }
+
+            let backoff = policy.delay(attempt, result.as_ref().err());
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                attempt,
+                backoff_ms = backoff.as_millis() as u64,
+                "retrying request"
+            );
+
+            self.timer.sleep(backoff).await;
+            attempt += 1;
         }
+    }
 
-        if !queries.is_empty() {
-            let queries = format!("?{}", queries.join("&"));
-            url += queries.as_str();
+    /// Sends a single GET request to the given URL and reads the response body.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `options` - The options to override for this request.
+    async fn get_once(&self, url: &str, options: &RequestOptions) -> Result<Option<Bytes>> {
+        let request = self.get_request(url, options)?;
+        if let Some(limiter) = self.limiter.as_ref() {
+            limiter.acquire_with(&*self.timer).await;
         }
 
-        url
+        let start = Instant::now();
+        let response = self.send(request).await.map_err(|e| e.with_url(url))?;
+        if let Some(quota) = QuotaInfo::from_headers(response.headers()) {
+            *self.quota.write().unwrap() = Some(quota);
+        }
+
+        let status = response.status();
+        if let Some(sink) = options.meta.as_ref() {
+            let meta = ResponseMeta::new(url, status, response.headers(), start.elapsed());
+            *sink.lock().unwrap() = Some(meta);
+        }
+        if options.conditional {
+            if status == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            if status.is_success() {
+                let validators = Validators::from_headers(response.headers());
+                let mut stored = self.validators.lock().unwrap();
+                match validators {
+                    Some(validators) => stored.insert(url.to_string(), validators),
+                    None => stored.remove(url),
+                };
+            }
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(parse_retry_after);
+            return Err(Error::RateLimited {
+                retry_after,
+                url: url.to_string(),
+            });
+        }
+
+        let body = read_body(response, self.max_response_size, url);
+        let body = match options.read_timeout.or(self.read_timeout) {
+            Some(duration) => {
+                timeout(&*self.timer, duration, body)
+                    .await
+                    .ok_or_else(|| Error::Timeout {
+                        url: url.to_string(),
+                    })?
+            }
+            None => body.await,
+        }
+        .map_err(|e| e.with_url(url))?;
+
+        if !status.is_success() {
+            return Err(Error::from_status(status, &body, url));
+        }
+
+        Ok(Some(body))
+    }
+
+    /// Sends a request through the middleware chain and the transport.
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The request to send.
+    async fn send(&self, mut request: Request) -> Result<Response> {
+        for middleware in self.middleware.iter() {
+            middleware.on_request(&mut request)?;
+        }
+
+        #[cfg(feature = "tracing")]
+        let (start, method, url) = (
+            Instant::now(),
+            request.method().clone(),
+            request.url().clone(),
+        );
+
+        let result = self.transport.execute(request).await;
+
+        #[cfg(feature = "tracing")]
+        match result.as_ref() {
+            Ok(response) => tracing::debug!(
+                %method,
+                %url,
+                status = response.status().as_u16(),
+                elapsed_ms = start.elapsed().as_millis() as u64,
+                "received response"
+            ),
+            Err(e) => tracing::debug!(%method, %url, error = %e, "request failed"),
+        }
+
+        for middleware in self.middleware.iter() {
+            match result.as_ref() {
+                Ok(response) => middleware.on_response(response),
+                Err(e) => middleware.on_error(e),
+            }
+        }
+
+        result
+    }
+
+    /// Builds the GET request sent to the given URL, with the options for this request applied.
+    ///
+    /// # Parameters
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `options` - The options to override for this request.
+    fn get_request(&self, url: &str, options: &RequestOptions) -> Result<Request> {
+        let mut request = self.request(Method::GET, url)?;
+        if let Some(timeout) = options.timeout {
+            *request.timeout_mut() = Some(timeout);
+        }
+        for (name, value) in options.headers.iter() {
+            request.headers_mut().insert(name, value.clone());
+        }
+        if options.conditional {
+            if let Some(validators) = self.validators.lock().unwrap().get(url) {
+                validators.apply(request.headers_mut());
+            }
+        }
+
+        Ok(request)
+    }
+
+    /// Creates a new Reqwest request with the given method and URL, with the default headers and
+    /// the authentication header preset.
+    ///
+    /// # Parameters
+    ///
+    /// * `method` - The HTTP method to build the request with.
+    /// * `url` - The URL to send the request to.
+    fn request(&self, method: Method, url: &str) -> Result<Request> {
+        let mut request = Request::new(method, Url::parse(url)?);
+        *request.headers_mut() = self.default_headers.clone();
+        request.headers_mut().insert(
+            "Ocp-Apim-Subscription-Key",
+            HeaderValue::from_str(&self.api_key)?,
+        );
+        *request.timeout_mut() = self.timeout;
+
+        Ok(request)
+    }
+}
+
+/// Returns the headers sent with every request by default, which identify this library.
+pub(crate) fn default_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+    headers
+}
+
+/// Returns a hash of a response body, used to recognise a feed which has not changed.
+///
+/// # Parameters
+///
+/// * `body` - The response body.
+fn fingerprint(body: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Logs a failure to record a response with the `tracing` feature, rather than failing the fetch
+/// it was received by.
+///
+/// # Parameters
+///
+/// * `result` - The result of recording the response.
+fn log_record_error(result: Result<()>) {
+    #[cfg(feature = "tracing")]
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "failed to record response");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = result;
+}
+
+/// Reads the body of a response, stopping early if it grows larger than the given limit.
+///
+/// # Parameters
+///
+/// * `response` - The response to read the body of.
+/// * `limit` - The maximum size of the body in bytes, if any.
+/// * `url` - The URL the request was sent to.
+async fn read_body(mut response: Response, limit: Option<usize>, url: &str) -> Result<Bytes> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(response.bytes().await?),
+    };
+    let too_large = || Error::ResponseTooLarge {
+        limit,
+        url: url.to_string(),
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = BytesMut::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache::ResponseCache,
+        outcome::FetchOutcome,
+        transport::tests::{block_on, response, StubTransport},
+        Ids,
+    };
+
+    use super::*;
+
+    const FEED: &str = r#"{
+        "status": "OK",
+        "response": {
+            "header": {"gtfs_realtime_version": "2.0", "timestamp": 1},
+            "entity": [{
+                "id": "a",
+                "vehicle": {"trip": {"trip_id": "t1"}, "vehicle": {"id": "a"}}
+            }]
+        }
+    }"#;
+
+    #[test]
+    fn conditional_fetches_skip_the_cache() {
+        let transport = StubTransport::new(|_| response(200, FEED));
+        let client = Realtime::builder("key")
+            .response_cache(Arc::new(ResponseCache::new(Duration::from_secs(60))))
+            .build()
+            .with_transport(transport.clone());
+        let fetch = || client.fetch_combined_if_modified(Ids::default(), Ids::default());
+
+        assert!(matches!(block_on(fetch()), Ok(FetchOutcome::Updated(_))));
+        // The feed is the same, so the cached response must not be reported as an update.
+        assert!(matches!(block_on(fetch()), Ok(FetchOutcome::Unchanged)));
+        assert_eq!(transport.requests(), 2);
+
+        // Unconditional fetches are still served from the cache.
+        let combined = block_on(client.fetch_combined(Ids::default(), Ids::default())).unwrap();
+        assert_eq!(combined.unmatched.len(), 1);
+        assert_eq!(transport.requests(), 2);
     }
 }
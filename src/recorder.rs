@@ -0,0 +1,418 @@
+//! Recording of raw realtime responses, and deterministic replay of recordings.
+//!
+//! A [`Recorder`] attached to a [`Realtime`] client with [`Realtime::with_recorder`] writes every
+//! raw response received from AT into a directory. A [`Replayer`] reads the directory back and
+//! yields the recorded responses through the same stream interface as [`Realtime::stream`], so
+//! integration tests and demos can run without network access. Responses can be yielded
+//! immediately, or with the gaps between them as they were recorded, sped up by a factor for load
+//! tests and demos. To answer questions about a
+//! recording instead, open it as an [`Archive`].
+//!
+//! [`Archive`]: crate::archive::Archive
+//! [`Realtime`]: crate::Realtime
+//! [`Realtime::with_recorder`]: crate::Realtime::with_recorder
+//! [`Realtime::stream`]: crate::Realtime::stream
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::stream::{self, Stream};
+
+use crate::{
+    decode::{decode_entities, Merger},
+    error::Result,
+    timer::{Timer, TokioTimer},
+    ApiVersion, CombinedResponse,
+};
+
+/// The names given to the parts of a snapshot fetched with [`FetchStrategy::Split`], in the order
+/// they are merged.
+///
+/// [`FetchStrategy::Split`]: crate::FetchStrategy::Split
+const SPLIT_PARTS: [&str; 2] = ["trips", "vehicles"];
+
+/// Writes raw responses received from AT to a directory.
+///
+/// Each snapshot is written to its own file, named after the UNIX timestamp (in milliseconds)
+/// at which it was received, a sequence number which keeps names unique within a millisecond,
+/// and the API version it was received from, such as `00000001633045120941-000000-v2.json`. A
+/// snapshot fetched with [`FetchStrategy::Split`] is written as one file for each response, such
+/// as `00000001633045120941-000001-v2-trips.json` and
+/// `00000001633045120941-000001-v2-vehicles.json`, which are replayed together as one snapshot.
+///
+/// [`FetchStrategy::Split`]: crate::FetchStrategy::Split
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    sequence: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    /// Creates a recorder writing to the given directory, creating the directory if it does not
+    /// exist.
+    ///
+    /// # Parameters
+    ///
+    /// * `dir` - The directory to write recordings to.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            sequence: Arc::default(),
+        })
+    }
+
+    /// Writes a raw response from the combined realtime endpoint to the recording directory.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The API version the response was received from.
+    /// * `body` - The raw response body received from AT.
+    pub fn record(&self, version: ApiVersion, body: &[u8]) -> Result<()> {
+        fs::write(
+            self.dir.join(format!("{}.json", self.next_name(version))),
+            body,
+        )?;
+        Ok(())
+    }
+
+    /// Writes the raw responses from the separate trip updates and vehicle positions endpoints
+    /// to the recording directory, to be replayed together as one snapshot.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The API version the responses were received from.
+    /// * `trips` - The raw trip updates response body.
+    /// * `vehicles` - The raw vehicle positions response body.
+    pub fn record_split(&self, version: ApiVersion, trips: &[u8], vehicles: &[u8]) -> Result<()> {
+        let name = self.next_name(version);
+        for (part, body) in SPLIT_PARTS.iter().zip([trips, vehicles]) {
+            fs::write(self.dir.join(format!("{}-{}.json", name, part)), body)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the next snapshot to be recorded, without an extension.
+    fn next_name(&self, version: ApiVersion) -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) % 1_000_000;
+
+        format!("{:020}-{:06}-{}", millis, sequence, version_name(version))
+    }
+}
+
+/// A recorded snapshot, made of one response or a pair of split responses.
+#[derive(Debug, Clone)]
+pub(crate) struct Recording {
+    /// When the snapshot was recorded, if the file name says.
+    pub(crate) recorded_at: Option<SystemTime>,
+    version: ApiVersion,
+    /// The files of the snapshot, in the order they are merged.
+    parts: Vec<PathBuf>,
+}
+
+impl Recording {
+    /// Reads and merges the recorded responses, with the time they were recorded as the
+    /// `fetched_at` of the snapshot.
+    pub(crate) fn read(&self) -> Result<CombinedResponse> {
+        let mut merger = Merger::new();
+        let mut header = None;
+        for path in &self.parts {
+            let body = fs::read(path)?;
+            let part = decode_entities(self.version, &body, |entity| merger.push(entity))?;
+            header.get_or_insert(part);
+        }
+
+        // Recordings always have at least one part.
+        let mut combined = merger.finish(header.expect("recording has no parts"));
+        if let Some(recorded_at) = self.recorded_at {
+            combined.fetched_at = recorded_at;
+        }
+        Ok(combined)
+    }
+}
+
+/// The name of an API version in the names of recordings.
+fn version_name(version: ApiVersion) -> &'static str {
+    match version {
+        ApiVersion::V2 => "v2",
+        ApiVersion::V3 => "v3",
+    }
+}
+
+/// The parts of the name of a recorded file.
+struct FileName<'a> {
+    /// The part of the name shared by the files of a snapshot.
+    key: &'a str,
+    millis: u64,
+    version: ApiVersion,
+    /// The position of the file in a split snapshot.
+    part: usize,
+}
+
+impl<'a> FileName<'a> {
+    /// Parses the name of a recorded file. Files recorded before names had a sequence number,
+    /// which are only the timestamp, were all received from the v2 API.
+    fn parse(path: &'a Path) -> Option<Self> {
+        let stem = path.file_stem()?.to_str()?;
+        let fields: Vec<&str> = stem.split('-').collect();
+        let (version, part) = match fields[..] {
+            [_] => ("v2", None),
+            [_, _, version] => (version, None),
+            [_, _, version, part] => (version, Some(part)),
+            _ => return None,
+        };
+
+        Some(Self {
+            key: match part {
+                Some(part) => &stem[..stem.len() - part.len() - 1],
+                None => stem,
+            },
+            millis: fields[0].parse().ok()?,
+            version: match version {
+                "v2" => ApiVersion::V2,
+                "v3" => ApiVersion::V3,
+                _ => return None,
+            },
+            part: match part {
+                Some(part) => SPLIT_PARTS.iter().position(|p| *p == part)?,
+                None => 0,
+            },
+        })
+    }
+}
+
+/// Replays responses written by a [`Recorder`].
+#[derive(Clone)]
+pub struct Replayer {
+    recordings: Vec<Recording>,
+    timer: Arc<dyn Timer>,
+}
+
+impl fmt::Debug for Replayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replayer")
+            .field("recordings", &self.recordings)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Replayer {
+    /// Opens the recordings in the given directory.
+    ///
+    /// # Parameters
+    ///
+    /// * `dir` - The directory the recordings were written to.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+
+        let mut names: Vec<_> = files
+            .iter()
+            .filter_map(|path| Some((FileName::parse(path)?, path)))
+            .collect();
+        names.sort_by(|(a, _), (b, _)| (a.key, a.part).cmp(&(b.key, b.part)));
+
+        let mut recordings: Vec<(&str, Recording)> = vec![];
+        for (name, path) in names {
+            match recordings.last_mut() {
+                Some((key, recording)) if *key == name.key => recording.parts.push(path.clone()),
+                _ => recordings.push((
+                    name.key,
+                    Recording {
+                        recorded_at: UNIX_EPOCH.checked_add(Duration::from_millis(name.millis)),
+                        version: name.version,
+                        parts: vec![path.clone()],
+                    },
+                )),
+            }
+        }
+
+        Ok(Self {
+            recordings: recordings.into_iter().map(|(_, r)| r).collect(),
+            timer: Arc::new(TokioTimer),
+        })
+    }
+
+    /// Sets the timer used to wait between responses replayed with [`Replayer::stream_at_speed`],
+    /// instead of tokio.
+    ///
+    /// # Parameters
+    ///
+    /// * `timer` - The timer to wait with.
+    pub fn with_timer<T: Timer + 'static>(mut self, timer: T) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Returns the number of recorded snapshots.
+    pub fn len(&self) -> usize {
+        self.recordings.len()
+    }
+
+    /// Returns true if there are no recorded snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.recordings.is_empty()
+    }
+
+    /// Replays the recorded snapshots in the order they were recorded.
+    ///
+    /// Responses are decoded with the API version they were recorded from and merged in the same
+    /// way as [`Realtime::fetch_combined`], with the two responses of a split fetch merged into
+    /// one snapshot. Snapshots are yielded immediately one after another, with the time each was
+    /// recorded as its `fetched_at`. The stream ends after the last recorded snapshot.
+    ///
+    /// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+    pub fn stream(&self) -> impl Stream<Item = Result<CombinedResponse>> + '_ {
+        stream::iter(self.recordings.iter().map(Recording::read))
+    }
+
+    /// Replays the recorded snapshots with the gaps between them as they were recorded, divided
+    /// by a speed factor, in the same form as [`Realtime::stream`].
+    ///
+    /// A speed of `1.0` replays in real time, and `60.0` replays an hour of recordings in a
+    /// minute. The first snapshot is yielded immediately, and a speed which is not positive
+    /// yields every snapshot immediately, like [`Replayer::stream`]. Snapshots keep the time they
+    /// were recorded as their `fetched_at`, so the timestamps in the feed stay consistent.
+    ///
+    /// # Parameters
+    ///
+    /// * `speed` - How many times faster than real time to replay.
+    ///
+    /// [`Realtime::stream`]: crate::Realtime::stream
+    pub fn stream_at_speed(&self, speed: f64) -> impl Stream<Item = Result<CombinedResponse>> + '_ {
+        stream::unfold((0, None), move |(index, previous)| async move {
+            let recording = self.recordings.get(index)?;
+            let at = recording.recorded_at;
+
+            if let (Some(previous), Some(at)) = (previous, at) {
+                let gap = at.duration_since(previous).unwrap_or_default();
+                self.timer.sleep(scale(gap, speed)).await;
+            }
+
+            Some((recording.read(), (index + 1, at.or(previous))))
+        })
+    }
+
+    /// Returns the recordings in the order they were recorded.
+    pub(crate) fn recordings(&self) -> &[Recording] {
+        &self.recordings
+    }
+}
+
+/// Divides a gap between recordings by a speed factor, saturating rather than overflowing.
+fn scale(gap: Duration, speed: f64) -> Duration {
+    if speed > 0.0 {
+        Duration::try_from_secs_f64(gap.as_secs_f64() / speed).unwrap_or(Duration::MAX)
+    } else {
+        Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"{"gtfs_realtime_version": "2.0", "timestamp": 1633045120}"#;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("at-api-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn feed(entities: &str) -> String {
+        format!(r#"{{"header": {}, "entity": [{}]}}"#, HEADER, entities)
+    }
+
+    #[test]
+    fn replays_split_and_v3_recordings() {
+        let dir = temp_dir("replay");
+        let recorder = Recorder::new(&dir).unwrap();
+
+        let trips = format!(
+            r#"{{"status": "OK", "response": {}, "error": null}}"#,
+            feed(r#"{"id": "t1", "trip_update": {"trip": {"trip_id": "t1"}, "delay": 60}}"#)
+        );
+        let vehicles = format!(
+            r#"{{"status": "OK", "response": {}, "error": null}}"#,
+            feed(
+                r#"{"id": "v1", "vehicle": {"trip": {"trip_id": "t1"}, "vehicle": {"id": "v1"}}}"#
+            )
+        );
+        recorder
+            .record_split(ApiVersion::V2, trips.as_bytes(), vehicles.as_bytes())
+            .unwrap();
+        recorder
+            .record(
+                ApiVersion::V3,
+                feed(r#"{"id": "v2", "vehicle": {}}"#).as_bytes(),
+            )
+            .unwrap();
+
+        let replayer = Replayer::open(&dir).unwrap();
+        assert_eq!(replayer.len(), 2);
+
+        let split = replayer.recordings()[0].read().unwrap();
+        assert_eq!(split.entities.len(), 1);
+        assert_eq!(split.entities[0].id, "v1");
+        assert_eq!(
+            split.entities[0].trip_update.as_ref().unwrap().delay,
+            Some(60)
+        );
+
+        let bare = replayer.recordings()[1].read().unwrap();
+        assert_eq!(bare.unmatched.len(), 1);
+        assert_eq!(bare.unmatched[0].id, "v2");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_legacy_file_names() {
+        let dir = temp_dir("legacy");
+        fs::create_dir_all(&dir).unwrap();
+        let body = format!(
+            r#"{{"status": "OK", "response": {}, "error": null}}"#,
+            feed("")
+        );
+        fs::write(dir.join("00000001633045120941.json"), body).unwrap();
+        fs::write(dir.join("notes.json"), "{}").unwrap();
+
+        let replayer = Replayer::open(&dir).unwrap();
+        assert_eq!(replayer.len(), 1);
+
+        let recording = &replayer.recordings()[0];
+        assert_eq!(
+            recording.recorded_at,
+            Some(UNIX_EPOCH + Duration::from_millis(1633045120941))
+        );
+        assert!(recording.read().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scales_gaps() {
+        assert_eq!(scale(Duration::from_secs(60), 60.0), Duration::from_secs(1));
+        assert_eq!(scale(Duration::from_secs(60), 0.0), Duration::ZERO);
+        assert_eq!(scale(Duration::MAX, 1e-300), Duration::MAX);
+    }
+}
@@ -0,0 +1,514 @@
+//! Cached lookups from the identifiers passengers use to the IDs used by the API.
+//!
+//! Passengers know routes by their route number, but realtime queries and entities use route IDs
+//! which include the GTFS version. The resolvers look these up through the static GTFS API and
+//! keep the results until a new GTFS version is published, as the IDs change with every version.
+//! Stops are found the same way from the code on their signage, and [`IdResolver`] turns the
+//! truncated IDs returned by methods such as [`Entity::trip_id`] back into full IDs.
+//!
+//! [`Entity::trip_id`]: crate::types::gtfs::Entity::trip_id
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Result,
+    types::schedule::{GtfsVersion, Stop},
+    Realtime,
+};
+
+/// How often the resolvers check for a new GTFS version by default.
+const DEFAULT_VERSION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A cached lookup from route numbers, such as `82` or `NX2`, to the versioned route IDs of the
+/// routes in the current schedule.
+///
+/// The resolver does not hold a client, so one resolver can be shared between clients and tasks.
+pub struct RouteResolver {
+    cache: VersionedCache<Vec<String>>,
+}
+
+impl RouteResolver {
+    /// Creates an empty resolver, which checks for a new GTFS version once an hour.
+    pub fn new() -> Self {
+        Self {
+            cache: VersionedCache::new(DEFAULT_VERSION_CHECK_INTERVAL),
+        }
+    }
+
+    /// Sets how often the resolver checks for a new GTFS version. The check is made as part of a
+    /// lookup, at most once per interval, and clears the cache if the published versions have
+    /// changed.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The time between checks.
+    pub fn version_check_interval(mut self, interval: Duration) -> Self {
+        self.cache.check_interval = interval;
+        self
+    }
+
+    /// Returns the versioned route IDs of the routes with the given route number, fetching them
+    /// from the static GTFS API if they are not cached.
+    ///
+    /// While a new GTFS version is being rolled out, a route number can match a route in both the
+    /// old and new versions, in which case both IDs are returned.
+    ///
+    /// # Parameters
+    ///
+    /// * `realtime` - The client used to query the static GTFS API.
+    /// * `short_name` - The route number shown to passengers.
+    ///
+    /// # Returns
+    ///
+    /// Returns the route IDs, which are empty if no route has the given number.
+    pub async fn resolve(&self, realtime: &Realtime, short_name: &str) -> Result<Vec<String>> {
+        self.cache
+            .get_or_fetch(realtime, short_name, || async move {
+                let routes = realtime.fetch_routes_by_short_name(short_name).await?;
+                Ok(routes.into_iter().map(|route| route.route_id).collect())
+            })
+            .await
+    }
+
+    /// Clears the cache, so every route number is looked up again on its next use.
+    pub fn invalidate(&self) {
+        self.cache.clear();
+    }
+}
+
+impl Default for RouteResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A cached lookup from the public stop codes shown on signage, such as `7036`, to the stops in
+/// the current schedule, with their GTFS stop IDs and coordinates.
+///
+/// Like [`RouteResolver`], the resolver does not hold a client and is cleared when a new GTFS
+/// version is published.
+pub struct StopResolver {
+    cache: VersionedCache<Vec<Stop>>,
+}
+
+impl StopResolver {
+    /// Creates an empty resolver, which checks for a new GTFS version once an hour.
+    pub fn new() -> Self {
+        Self {
+            cache: VersionedCache::new(DEFAULT_VERSION_CHECK_INTERVAL),
+        }
+    }
+
+    /// Sets how often the resolver checks for a new GTFS version. See
+    /// [`RouteResolver::version_check_interval`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The time between checks.
+    pub fn version_check_interval(mut self, interval: Duration) -> Self {
+        self.cache.check_interval = interval;
+        self
+    }
+
+    /// Returns the stops with the given stop code, fetching them from the static GTFS API if they
+    /// are not cached.
+    ///
+    /// # Parameters
+    ///
+    /// * `realtime` - The client used to query the static GTFS API.
+    /// * `code` - The code shown on the stop's signage. Leading and trailing whitespace is
+    ///   ignored.
+    ///
+    /// # Returns
+    ///
+    /// Returns the stops, which are empty if no stop has the given code. While a new GTFS version
+    /// is being rolled out, the stop of each version is returned.
+    pub async fn resolve(&self, realtime: &Realtime, code: &str) -> Result<Vec<Stop>> {
+        let code = code.trim();
+        self.cache
+            .get_or_fetch(realtime, code, || realtime.fetch_stops_by_code(code))
+            .await
+    }
+
+    /// Clears the cache, so every stop code is looked up again on its next use.
+    pub fn invalidate(&self) {
+        self.cache.clear();
+    }
+}
+
+impl Default for StopResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The kind of object an ID refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IdKind {
+    /// A route ID, as returned by [`Entity::route_id`].
+    ///
+    /// [`Entity::route_id`]: crate::types::gtfs::Entity::route_id
+    Route,
+    /// A stop ID, as returned by [`Entity::stop_id`].
+    ///
+    /// [`Entity::stop_id`]: crate::types::gtfs::Entity::stop_id
+    Stop,
+    /// A trip ID, as returned by [`Entity::trip_id`].
+    ///
+    /// [`Entity::trip_id`]: crate::types::gtfs::Entity::trip_id
+    Trip,
+}
+
+impl IdKind {
+    /// Returns the path of the static GTFS API endpoint which looks up an object by its full ID,
+    /// relative to the path the static GTFS API is served under.
+    pub(crate) fn static_path(self) -> &'static str {
+        match self {
+            IdKind::Route => "routes/routeId",
+            IdKind::Stop => "stops/stopId",
+            IdKind::Trip => "trips/tripId",
+        }
+    }
+
+    fn key(self, truncated: &str) -> String {
+        let prefix = match self {
+            IdKind::Route => "route",
+            IdKind::Stop => "stop",
+            IdKind::Trip => "trip",
+        };
+        format!("{}:{}", prefix, truncated)
+    }
+}
+
+/// A cached lookup from truncated IDs, which have had the GTFS version removed, to the full IDs
+/// needed to query the AT API.
+///
+/// AT appends the GTFS version to every route, stop and trip ID after a hyphen. Methods such as
+/// [`Entity::trip_id`] remove it so IDs can be compared across versions, and this resolver adds it
+/// back by trying each published version and checking which full ID the static GTFS API knows.
+///
+/// [`Entity::trip_id`]: crate::types::gtfs::Entity::trip_id
+pub struct IdResolver {
+    cache: VersionedCache<Option<String>>,
+}
+
+impl IdResolver {
+    /// Creates an empty resolver, which checks for a new GTFS version once an hour.
+    pub fn new() -> Self {
+        Self {
+            cache: VersionedCache::new(DEFAULT_VERSION_CHECK_INTERVAL),
+        }
+    }
+
+    /// Sets how often the resolver checks for a new GTFS version. See
+    /// [`RouteResolver::version_check_interval`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The time between checks.
+    pub fn version_check_interval(mut self, interval: Duration) -> Self {
+        self.cache.check_interval = interval;
+        self
+    }
+
+    /// Returns the full ID of a truncated ID, fetching the published GTFS versions and checking
+    /// the candidate IDs against the static GTFS API if it is not cached.
+    ///
+    /// # Parameters
+    ///
+    /// * `realtime` - The client used to query the static GTFS API.
+    /// * `kind` - The kind of object the ID refers to.
+    /// * `truncated` - The ID without its GTFS version.
+    ///
+    /// # Returns
+    ///
+    /// Returns the full ID in the newest version which contains the object, or [`None`] if no
+    /// published version contains it.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub async fn resolve(
+        &self,
+        realtime: &Realtime,
+        kind: IdKind,
+        truncated: &str,
+    ) -> Result<Option<String>> {
+        self.cache
+            .get_or_fetch(realtime, &kind.key(truncated), || async move {
+                for id in candidates(truncated, &self.cache.versions()) {
+                    if realtime.static_id_exists(kind, &id).await? {
+                        return Ok(Some(id));
+                    }
+                }
+                Ok(None)
+            })
+            .await
+    }
+
+    /// Clears the cache, so every ID is looked up again on its next use.
+    pub fn invalidate(&self) {
+        self.cache.clear();
+    }
+}
+
+impl Default for IdResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the possible full IDs of a truncated ID, one for each GTFS version, newest first.
+///
+/// # Parameters
+///
+/// * `truncated` - The ID without its GTFS version.
+/// * `versions` - The published GTFS versions.
+pub fn candidates(truncated: &str, versions: &[GtfsVersion]) -> Vec<String> {
+    let mut versions: Vec<&GtfsVersion> = versions.iter().collect();
+    versions.sort_by(|a, b| b.startdate.cmp(&a.startdate));
+    versions
+        .into_iter()
+        .map(|version| format!("{}-{}", truncated, version.version))
+        .collect()
+}
+
+/// A cache which is cleared whenever the published GTFS versions change.
+struct VersionedCache<V> {
+    check_interval: Duration,
+    state: Mutex<CacheState<V>>,
+}
+
+/// The cached values, and the GTFS versions they were looked up for.
+struct CacheState<V> {
+    versions: Option<Vec<GtfsVersion>>,
+    checked_at: Option<Instant>,
+    entries: HashMap<String, V>,
+}
+
+impl<V: Clone> VersionedCache<V> {
+    /// Creates an empty cache.
+    ///
+    /// # Parameters
+    ///
+    /// * `check_interval` - How often to check for a new GTFS version.
+    fn new(check_interval: Duration) -> Self {
+        Self {
+            check_interval,
+            state: Mutex::new(CacheState {
+                versions: None,
+                checked_at: None,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the cached value for a key, fetching and caching it if it is not cached.
+    ///
+    /// # Parameters
+    ///
+    /// * `realtime` - The client used to check for a new GTFS version.
+    /// * `key` - The key to look up.
+    /// * `fetch` - Fetches the value if it is not cached.
+    async fn get_or_fetch<F, Fut>(&self, realtime: &Realtime, key: &str, fetch: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        self.check_versions(realtime).await?;
+        if let Some(value) = self.state.lock().unwrap().entries.get(key) {
+            return Ok(value.clone());
+        }
+
+        let value = fetch().await?;
+        self.state
+            .lock()
+            .unwrap()
+            .entries
+            .insert(key.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Fetches the published GTFS versions if the check interval has passed, and clears the
+    /// cache if they have changed.
+    ///
+    /// # Parameters
+    ///
+    /// * `realtime` - The client used to query the static GTFS API.
+    async fn check_versions(&self, realtime: &Realtime) -> Result<()> {
+        let checked_at = self.state.lock().unwrap().checked_at;
+        let due = !matches!(
+            checked_at,
+            Some(checked_at) if checked_at.elapsed() < self.check_interval
+        );
+        if !due {
+            return Ok(());
+        }
+
+        let versions = realtime.fetch_versions().await?;
+        let mut state = self.state.lock().unwrap();
+        if state.versions.as_ref() != Some(&versions) {
+            state.entries.clear();
+            state.versions = Some(versions);
+        }
+        state.checked_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Returns the GTFS versions the cached values were looked up for.
+    fn versions(&self) -> Vec<GtfsVersion> {
+        self.state
+            .lock()
+            .unwrap()
+            .versions
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// Removes every cached value, and forces the GTFS versions to be checked on the next lookup.
+    fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.checked_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::tests::{block_on, response, StubTransport};
+
+    fn version(version: &str, startdate: &str) -> GtfsVersion {
+        GtfsVersion {
+            version: version.to_string(),
+            startdate: startdate.to_string(),
+            enddate: "20991231".to_string(),
+        }
+    }
+
+    /// Returns a transport serving the given versions and the routes of the newest of them,
+    /// recording the path of each request.
+    fn transport(
+        versions: Arc<Mutex<Vec<&'static str>>>,
+        paths: Arc<Mutex<Vec<String>>>,
+    ) -> StubTransport {
+        StubTransport::new(move |request| {
+            let path = request.url().path().to_string();
+            paths.lock().unwrap().push(path.clone());
+            let versions = versions.lock().unwrap().clone();
+            let body = if path.ends_with("/versions") {
+                let versions: Vec<_> = versions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        let startdate = format!("2024010{}", i + 1);
+                        json!({"version": v, "startdate": startdate, "enddate": "20991231"})
+                    })
+                    .collect();
+                json!({ "response": versions })
+            } else if path.ends_with("/routes/routeShortName/82") {
+                let route_id = format!("82-{}", versions.last().unwrap());
+                json!({"response": [{"route_id": route_id, "route_short_name": "82"}]})
+            } else if path.ends_with("/trips/tripId/t1-v1") {
+                json!({"response": [{"trip_id": "t1-v1"}]})
+            } else {
+                return response(404, "");
+            };
+            response(200, &body.to_string())
+        })
+    }
+
+    #[test]
+    fn candidates_are_newest_first() {
+        let versions = [
+            version("v2", "20240201"),
+            version("v3", "20240301"),
+            version("v1", "20240101"),
+        ];
+        assert_eq!(candidates("t1", &versions), ["t1-v3", "t1-v2", "t1-v1"]);
+        assert!(candidates("t1", &[]).is_empty());
+    }
+
+    #[test]
+    fn lookups_are_refetched_when_the_versions_change() {
+        let versions = Arc::new(Mutex::new(vec!["v1"]));
+        let paths = Arc::new(Mutex::new(vec![]));
+        let realtime =
+            Realtime::new("key").with_transport(transport(versions.clone(), paths.clone()));
+        let resolver = RouteResolver::new().version_check_interval(Duration::ZERO);
+        let route_lookups = || {
+            let paths = paths.lock().unwrap();
+            paths
+                .iter()
+                .filter(|p| p.contains("routeShortName"))
+                .count()
+        };
+
+        assert_eq!(
+            block_on(resolver.resolve(&realtime, "82")).unwrap(),
+            ["82-v1"]
+        );
+        assert_eq!(
+            block_on(resolver.resolve(&realtime, "82")).unwrap(),
+            ["82-v1"]
+        );
+        assert_eq!(route_lookups(), 1);
+
+        versions.lock().unwrap().push("v2");
+        assert_eq!(
+            block_on(resolver.resolve(&realtime, "82")).unwrap(),
+            ["82-v2"]
+        );
+        assert_eq!(route_lookups(), 2);
+
+        // The versions are only checked once per interval, unless the resolver is invalidated.
+        let resolver = RouteResolver::new();
+        block_on(resolver.resolve(&realtime, "82")).unwrap();
+        versions.lock().unwrap().push("v3");
+        assert_eq!(
+            block_on(resolver.resolve(&realtime, "82")).unwrap(),
+            ["82-v2"]
+        );
+        resolver.invalidate();
+        assert_eq!(
+            block_on(resolver.resolve(&realtime, "82")).unwrap(),
+            ["82-v3"]
+        );
+    }
+
+    #[test]
+    fn ids_are_resolved_in_the_newest_version_first() {
+        let versions = Arc::new(Mutex::new(vec!["v1", "v2"]));
+        let paths = Arc::new(Mutex::new(vec![]));
+        let realtime = Realtime::new("key").with_transport(transport(versions, paths.clone()));
+        let resolver = IdResolver::new();
+
+        let id = block_on(resolver.resolve(&realtime, IdKind::Trip, "t1")).unwrap();
+        assert_eq!(id.as_deref(), Some("t1-v1"));
+        let tried: Vec<_> = paths
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|path| path.rsplit('/').next().map(String::from))
+            .collect();
+        assert_eq!(tried, ["versions", "t1-v2", "t1-v1"]);
+
+        // Objects which are in no version are cached as missing.
+        let id = block_on(resolver.resolve(&realtime, IdKind::Trip, "t2")).unwrap();
+        assert_eq!(id, None);
+        let requests = paths.lock().unwrap().len();
+        assert_eq!(
+            block_on(resolver.resolve(&realtime, IdKind::Trip, "t2")).unwrap(),
+            None
+        );
+        assert_eq!(paths.lock().unwrap().len(), requests);
+    }
+}
@@ -0,0 +1,305 @@
+//! Retrying of failed requests.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime},
+};
+
+use reqwest::header::HeaderValue;
+
+use crate::error::Error;
+
+/// A policy describing which failed requests are retried, and how long to wait between attempts.
+///
+/// The wait before each retry grows exponentially from `initial_backoff` up to `max_backoff`.
+/// With jitter enabled, a random wait between zero and the computed backoff is used instead, so
+/// that many clients failing at once do not retry in lockstep.
+///
+/// Rate limited requests are only retried when enabled with [`retry_rate_limited`], in which case
+/// the client waits for the duration given by AT's `Retry-After` header instead of the backoff.
+///
+/// [`retry_rate_limited`]: RetryPolicy::retry_rate_limited
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) multiplier: f64,
+    pub(crate) jitter: bool,
+    pub(crate) retry_server_errors: bool,
+    pub(crate) retry_network_errors: bool,
+    pub(crate) retry_timeouts: bool,
+    pub(crate) retry_rate_limited: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Returns a policy making up to 3 attempts, backing off from 500 milliseconds up to 10
+    /// seconds with jitter, and retrying server errors, network errors and timeouts. Rate limited
+    /// requests are not retried.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: true,
+            retry_server_errors: true,
+            retry_network_errors: true,
+            retry_timeouts: true,
+            retry_rate_limited: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns a policy which never retries. This is the policy used by clients by default.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the maximum number of attempts, including the first. A value of 1 disables retries.
+    ///
+    /// # Parameters
+    ///
+    /// * `attempts` - The maximum number of attempts.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Sets the wait before the first retry.
+    ///
+    /// # Parameters
+    ///
+    /// * `backoff` - The initial backoff.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum wait between attempts.
+    ///
+    /// # Parameters
+    ///
+    /// * `backoff` - The maximum backoff.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the factor the backoff is multiplied by after each retry.
+    ///
+    /// # Parameters
+    ///
+    /// * `multiplier` - The backoff multiplier.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets whether a random jitter is applied to the backoff.
+    ///
+    /// # Parameters
+    ///
+    /// * `jitter` - Whether to apply jitter.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets whether responses with a 5xx status code are retried.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry` - Whether to retry server errors.
+    pub fn retry_server_errors(mut self, retry: bool) -> Self {
+        self.retry_server_errors = retry;
+        self
+    }
+
+    /// Sets whether requests which failed to connect or send are retried.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry` - Whether to retry network errors.
+    pub fn retry_network_errors(mut self, retry: bool) -> Self {
+        self.retry_network_errors = retry;
+        self
+    }
+
+    /// Sets whether requests which timed out are retried.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry` - Whether to retry timeouts.
+    pub fn retry_timeouts(mut self, retry: bool) -> Self {
+        self.retry_timeouts = retry;
+        self
+    }
+
+    /// Sets whether requests rejected with `429 Too Many Requests` are retried after waiting for
+    /// the duration given by the `Retry-After` header.
+    ///
+    /// A request is not retried if AT asks the client to wait longer than the maximum backoff.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry` - Whether to retry rate limited requests.
+    pub fn retry_rate_limited(mut self, retry: bool) -> Self {
+        self.retry_rate_limited = retry;
+        self
+    }
+
+    /// Returns whether a request which failed with the given error should be retried.
+    pub(crate) fn should_retry_error(&self, error: &Error) -> bool {
+        match error {
+            Error::RateLimited { retry_after, .. } => {
+                self.retry_rate_limited
+                    && !matches!(retry_after, Some(retry_after) if *retry_after > self.max_backoff)
+            }
+            Error::ServerError { .. } => self.retry_server_errors,
+            e if e.is_timeout() => self.retry_timeouts,
+            e => e.is_retryable() && self.retry_network_errors,
+        }
+    }
+
+    /// Returns how long to wait before the given retry of a request which failed with the given
+    /// error, if any.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry` - The number of the retry, starting from 1.
+    /// * `error` - The error the previous attempt failed with.
+    pub(crate) fn delay(&self, retry: u32, error: Option<&Error>) -> Duration {
+        match error {
+            Some(Error::RateLimited {
+                retry_after: Some(retry_after),
+                ..
+            }) => *retry_after,
+            _ => self.backoff(retry),
+        }
+    }
+
+    /// Returns the exponential backoff before the given retry.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry` - The number of the retry, starting from 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exp = self.multiplier.powi(retry.saturating_sub(1) as i32);
+        let backoff = self.initial_backoff.as_secs_f64() * exp;
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+
+        if self.jitter {
+            Duration::from_secs_f64(backoff * random_fraction())
+        } else {
+            Duration::from_secs_f64(backoff)
+        }
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+///
+/// # Parameters
+///
+/// * `value` - The header value.
+pub(crate) fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Returns a random number in `[0, 1)`, using the randomly seeded keys of the standard library
+/// hasher.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limited(retry_after: Option<Duration>) -> Error {
+        Error::RateLimited {
+            retry_after,
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1))
+            .jitter(false);
+
+        let delays: Vec<Duration> = (1..=6).map(|retry| policy.delay(retry, None)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(policy.delay(1000, None), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_the_backoff() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(400));
+        for retry in 1..=10 {
+            let limit = Duration::from_millis(100 << (retry - 1).min(2));
+            assert!(policy.delay(retry, None) <= limit);
+        }
+    }
+
+    #[test]
+    fn waits_for_retry_after() {
+        let policy = RetryPolicy::default()
+            .max_backoff(Duration::from_secs(10))
+            .jitter(false);
+        let error = rate_limited(Some(Duration::from_secs(7)));
+        assert_eq!(policy.delay(1, Some(&error)), Duration::from_secs(7));
+        assert_eq!(
+            policy.delay(1, Some(&rate_limited(None))),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn caps_retry_after_at_the_maximum_backoff() {
+        let policy = RetryPolicy::default()
+            .max_backoff(Duration::from_secs(10))
+            .retry_rate_limited(true);
+        assert!(policy.should_retry_error(&rate_limited(None)));
+        assert!(policy.should_retry_error(&rate_limited(Some(Duration::from_secs(10)))));
+        assert!(!policy.should_retry_error(&rate_limited(Some(Duration::from_secs(11)))));
+
+        let policy = policy.retry_rate_limited(false);
+        assert!(!policy.should_retry_error(&rate_limited(Some(Duration::from_secs(1)))));
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let parse = |value: &str| parse_retry_after(&HeaderValue::from_str(value).unwrap());
+        assert_eq!(parse(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(parse("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse("soon"), None);
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let wait = parse(&later).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+    }
+}
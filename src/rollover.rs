@@ -0,0 +1,211 @@
+//! Detection of new GTFS versions from the IDs in the realtime feed.
+//!
+//! Every route and trip ID in the feed ends with the version of the GTFS dataset it belongs to.
+//! When AT publishes a new dataset the suffixes change, and anything keyed by full IDs, such as
+//! the [resolvers] or a static schedule cached by the application, is out of date.
+//! A [`VersionWatcher`] watches the suffixes across snapshots and reports when they change.
+//!
+//! [resolvers]: crate::resolver
+
+use std::collections::BTreeSet;
+
+use crate::types::gtfs::{Entity, TripDescriptor};
+
+type ChangeHook = Box<dyn Fn(&VersionChange) + Send + Sync>;
+
+/// Watches the GTFS versions referenced by realtime snapshots.
+///
+/// The first snapshot observed sets the versions to compare against, and each later snapshot
+/// whose versions differ produces a [`VersionChange`]. Snapshots without any route or trip IDs
+/// are ignored, so an empty feed is not mistaken for a change of version.
+#[derive(Default)]
+pub struct VersionWatcher {
+    versions: Option<BTreeSet<String>>,
+    on_change: Vec<ChangeHook>,
+}
+
+/// The GTFS versions referenced by the feed before and after a change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    /// The versions referenced before the change.
+    pub previous: BTreeSet<String>,
+    /// The versions referenced now.
+    pub current: BTreeSet<String>,
+}
+
+impl VersionWatcher {
+    /// Creates a watcher which has not observed a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback which runs whenever the versions change, such as one which
+    /// invalidates a [`RouteResolver`].
+    ///
+    /// # Parameters
+    ///
+    /// * `f` - The callback, called with the change.
+    ///
+    /// [`RouteResolver`]: crate::resolver::RouteResolver
+    pub fn on_change<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&VersionChange) + Send + Sync + 'static,
+    {
+        self.on_change.push(Box::new(f));
+        self
+    }
+
+    /// Returns the versions referenced by the last snapshot which had any, or [`None`] if no
+    /// such snapshot has been observed.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn versions(&self) -> Option<&BTreeSet<String>> {
+        self.versions.as_ref()
+    }
+
+    /// Observes the entities of a snapshot, running the registered callbacks if the versions they
+    /// reference differ from the last snapshot.
+    ///
+    /// # Parameters
+    ///
+    /// * `entities` - The entities of the snapshot.
+    ///
+    /// # Returns
+    ///
+    /// Returns the change, or [`None`] if the versions are the same or this is the first
+    /// snapshot observed.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn observe(&mut self, entities: &[Entity]) -> Option<VersionChange> {
+        let current = versions(entities);
+        if current.is_empty() {
+            return None;
+        }
+
+        let previous = match self.versions.replace(current.clone()) {
+            Some(previous) if previous != current => previous,
+            _ => return None,
+        };
+
+        let change = VersionChange { previous, current };
+        self.on_change.iter().for_each(|f| f(&change));
+        Some(change)
+    }
+}
+
+impl VersionChange {
+    /// Returns the versions referenced now which were not referenced before.
+    pub fn added(&self) -> impl Iterator<Item = &str> {
+        self.current.difference(&self.previous).map(String::as_str)
+    }
+
+    /// Returns the versions which are no longer referenced.
+    pub fn removed(&self) -> impl Iterator<Item = &str> {
+        self.previous.difference(&self.current).map(String::as_str)
+    }
+}
+
+/// Returns the GTFS versions referenced by the route and trip IDs of a set of entities.
+///
+/// # Parameters
+///
+/// * `entities` - The entities to read the IDs of.
+pub fn versions(entities: &[Entity]) -> BTreeSet<String> {
+    let mut versions = BTreeSet::new();
+    let trips = entities.iter().flat_map(|entity| {
+        let trip_update = entity.trip_update.as_ref().map(|tu| &tu.trip);
+        let vehicle = entity.vehicle.as_ref().and_then(|v| v.trip.as_ref());
+        trip_update.into_iter().chain(vehicle)
+    });
+
+    for trip in trips {
+        for id in trip_ids(trip) {
+            if let Some(version) = version_suffix(id) {
+                if !versions.contains(version) {
+                    versions.insert(version.to_string());
+                }
+            }
+        }
+    }
+
+    versions
+}
+
+/// Returns the route and trip IDs of a trip descriptor.
+fn trip_ids(trip: &TripDescriptor) -> impl Iterator<Item = &str> {
+    trip.route_id
+        .as_deref()
+        .into_iter()
+        .chain(trip.trip_id.as_deref())
+}
+
+/// Returns the GTFS version suffix of an ID, which is the part after the first hyphen.
+fn version_suffix(id: &str) -> Option<&str> {
+    id.find('-')
+        .map(|start| &id[start + 1..])
+        .filter(|version| !version.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn entities(route_id: &str, trip_id: &str) -> Vec<Entity> {
+        serde_json::from_value(json!([
+            {"id": "e1", "trip_update": {"trip": {"trip_id": trip_id, "route_id": route_id}}},
+            {"id": "e2", "vehicle": {"trip": {"route_id": route_id}}},
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_version_suffixes() {
+        assert_eq!(version_suffix("82-202"), Some("202"));
+        assert_eq!(
+            version_suffix("1-20240115-20240116120000-v2"),
+            Some("20240115-20240116120000-v2")
+        );
+        assert_eq!(version_suffix("NX2"), None);
+        assert_eq!(version_suffix("NX2-"), None);
+
+        let found: Vec<_> = versions(&entities("82-202", "t1-203"))
+            .into_iter()
+            .collect();
+        assert_eq!(found, ["202", "203"]);
+    }
+
+    #[test]
+    fn reports_changes_of_version() {
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counter = changes.clone();
+        let mut watcher = VersionWatcher::new().on_change(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        assert!(watcher.observe(&entities("82-202", "t1-202")).is_none());
+        assert!(watcher.observe(&entities("82-202", "t2-202")).is_none());
+        // An empty feed, or one without versioned IDs, is not a change.
+        assert!(watcher.observe(&[]).is_none());
+        assert!(watcher.observe(&entities("NX2", "t3")).is_none());
+        assert_eq!(changes.load(Ordering::SeqCst), 0);
+
+        let change = watcher.observe(&entities("82-202", "t1-203")).unwrap();
+        assert_eq!(change.added().collect::<Vec<_>>(), ["203"]);
+        assert_eq!(change.removed().count(), 0);
+
+        let change = watcher.observe(&entities("82-203", "t1-203")).unwrap();
+        assert_eq!(change.added().count(), 0);
+        assert_eq!(change.removed().collect::<Vec<_>>(), ["202"]);
+        assert_eq!(changes.load(Ordering::SeqCst), 2);
+
+        let versions: Vec<_> = watcher.versions().unwrap().iter().collect();
+        assert_eq!(versions, ["203"]);
+    }
+}
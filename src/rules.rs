@@ -0,0 +1,317 @@
+//! Rules which raise alerts from realtime snapshots, such as a route running late.
+//!
+//! Register [`Rule`]s with an [`AlertEngine`] and pass it each snapshot, or use
+//! [`Realtime::stream_alerts`] to have it run from the polling loop. Each alert is raised once
+//! when a trip starts matching a rule, rather than for every snapshot in which it matches, and can
+//! be raised again once the trip has stopped matching.
+//!
+//! [`Realtime::stream_alerts`]: crate::Realtime::stream_alerts
+
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+};
+
+use futures_util::{
+    stream::{self, Stream},
+    StreamExt,
+};
+
+use crate::{
+    error::Result,
+    types::gtfs::{ScheduleRelationshipTripDescriptor, TripUpdate},
+    CombinedResponse,
+};
+
+/// A condition which raises an alert for each trip it matches.
+///
+/// Routes are given by route ID, either the full ID with the GTFS version, as returned by a
+/// [`RouteResolver`], or the truncated ID returned by [`Entity::route_id`].
+///
+/// [`RouteResolver`]: crate::resolver::RouteResolver
+/// [`Entity::route_id`]: crate::types::gtfs::Entity::route_id
+#[derive(Debug, Clone)]
+pub struct Rule {
+    route_id: Option<String>,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    DelayedBy(Duration),
+    Cancelled,
+}
+
+/// The condition an alert was raised for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AlertKind {
+    /// The trip is running later than the threshold of the rule.
+    Delayed {
+        /// The delay of the trip, in seconds.
+        delay: i32,
+    },
+    /// The trip has been cancelled.
+    Cancelled,
+}
+
+/// An alert raised by a rule.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Alert {
+    /// The index of the rule which raised the alert, in the order the rules were added.
+    pub rule: usize,
+    /// The condition the alert was raised for.
+    pub kind: AlertKind,
+    /// The full ID of the trip.
+    pub trip_id: String,
+    /// The full ID of the route of the trip.
+    pub route_id: Option<String>,
+    /// The ID of the vehicle serving the trip, if one has been assigned.
+    pub vehicle_id: Option<String>,
+}
+
+impl Rule {
+    /// Creates a rule which matches trips on a route running later than a threshold.
+    ///
+    /// # Parameters
+    ///
+    /// * `route_id` - The route to watch.
+    /// * `threshold` - The delay above which a trip matches.
+    pub fn delayed<S: Into<String>>(route_id: S, threshold: Duration) -> Self {
+        Self {
+            route_id: Some(route_id.into()),
+            condition: Condition::DelayedBy(threshold),
+        }
+    }
+
+    /// Creates a rule which matches cancelled trips on a route.
+    ///
+    /// # Parameters
+    ///
+    /// * `route_id` - The route to watch.
+    pub fn cancelled<S: Into<String>>(route_id: S) -> Self {
+        Self {
+            route_id: Some(route_id.into()),
+            condition: Condition::Cancelled,
+        }
+    }
+
+    /// Makes the rule match trips on every route.
+    pub fn any_route(mut self) -> Self {
+        self.route_id = None;
+        self
+    }
+
+    /// Returns the alert raised for a trip update if the rule matches it.
+    fn evaluate(&self, trip_update: &TripUpdate) -> Option<AlertKind> {
+        if let Some(route_id) = self.route_id.as_deref() {
+            let trip_route = trip_update.trip.route_id.as_deref()?;
+            let truncated = trip_route.split('-').next().unwrap_or(trip_route);
+            if trip_route != route_id && truncated != route_id {
+                return None;
+            }
+        }
+
+        match self.condition {
+            Condition::DelayedBy(threshold) => {
+                let delay = trip_update.delay?;
+                (i64::from(delay) > threshold.as_secs() as i64)
+                    .then_some(AlertKind::Delayed { delay })
+            }
+            Condition::Cancelled => matches!(
+                trip_update.trip.schedule_relationship,
+                Some(ScheduleRelationshipTripDescriptor::Cancelled)
+            )
+            .then_some(AlertKind::Cancelled),
+        }
+    }
+}
+
+/// Evaluates a set of rules against snapshots and raises alerts as trips start matching them.
+#[derive(Debug, Clone, Default)]
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    /// The rule indexes and trip IDs which matched in the last snapshot.
+    active: HashSet<(usize, String)>,
+}
+
+impl AlertEngine {
+    /// Creates an engine with no rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule to the engine.
+    ///
+    /// # Parameters
+    ///
+    /// * `rule` - The rule to add.
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Evaluates the rules against a snapshot, returning the alerts for trips which match a rule
+    /// and did not match it in the previous snapshot.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to evaluate.
+    pub fn evaluate(&mut self, combined: &CombinedResponse) -> Vec<Alert> {
+        let trip_updates = combined
+            .entities
+            .iter()
+            .chain(combined.unmatched.iter())
+            .filter_map(|entity| entity.trip_update.as_ref());
+
+        let mut alerts = vec![];
+        let mut active = HashSet::new();
+        for trip_update in trip_updates {
+            let trip_id = match trip_update.trip.trip_id.as_deref() {
+                Some(trip_id) => trip_id,
+                None => continue,
+            };
+
+            for (index, rule) in self.rules.iter().enumerate() {
+                let kind = match rule.evaluate(trip_update) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+
+                let key = (index, trip_id.to_string());
+                if !self.active.contains(&key) && !active.contains(&key) {
+                    alerts.push(Alert {
+                        rule: index,
+                        kind,
+                        trip_id: trip_id.to_string(),
+                        route_id: trip_update.trip.route_id.clone(),
+                        vehicle_id: trip_update.vehicle.as_ref().and_then(|v| v.id.clone()),
+                    });
+                }
+                active.insert(key);
+            }
+        }
+
+        self.active = active;
+        alerts
+    }
+}
+
+/// Turns a stream of snapshots, such as [`Realtime::stream`], into a stream of alerts raised by
+/// an engine. Errors from the snapshot stream are passed through.
+///
+/// # Parameters
+///
+/// * `snapshots` - The stream of snapshots to evaluate.
+/// * `engine` - The engine with the rules to evaluate.
+///
+/// [`Realtime::stream`]: crate::Realtime::stream
+pub fn alerts<S>(snapshots: S, engine: AlertEngine) -> impl Stream<Item = Result<Alert>>
+where
+    S: Stream<Item = Result<CombinedResponse>>,
+{
+    struct State<S> {
+        snapshots: S,
+        engine: AlertEngine,
+        pending: VecDeque<Alert>,
+    }
+
+    let state = State {
+        snapshots: Box::pin(snapshots),
+        engine,
+        pending: VecDeque::new(),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(alert) = state.pending.pop_front() {
+                return Some((Ok(alert), state));
+            }
+
+            match state.snapshots.next().await? {
+                Ok(combined) => state.pending.extend(state.engine.evaluate(&combined)),
+                Err(e) => return Some((Err(e), state)),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::combined::tests::{snapshot, unmatched};
+
+    fn trip(trip_id: &str, route_id: &str, delay: i32) -> Value {
+        json!({
+            "id": trip_id,
+            "trip_update": {
+                "trip": {"trip_id": trip_id, "route_id": route_id},
+                "vehicle": {"id": "v1"},
+                "delay": delay
+            }
+        })
+    }
+
+    #[test]
+    fn matches_full_and_truncated_route_ids() {
+        let mut engine = AlertEngine::new()
+            .rule(Rule::delayed("82-202", Duration::from_secs(60)))
+            .rule(Rule::delayed("NX2", Duration::from_secs(60)))
+            .rule(Rule::delayed("70", Duration::from_secs(60)));
+        let alerts = engine.evaluate(&snapshot(json!([
+            trip("t1", "82-202", 120),
+            trip("t2", "NX2-202", 120),
+            trip("t3", "700-202", 120)
+        ])));
+
+        let matched: Vec<(usize, &str)> = alerts
+            .iter()
+            .map(|alert| (alert.rule, alert.trip_id.as_str()))
+            .collect();
+        assert_eq!(matched, [(0, "t1"), (1, "t2")]);
+        assert_eq!(alerts[0].kind, AlertKind::Delayed { delay: 120 });
+        assert_eq!(alerts[0].route_id.as_deref(), Some("82-202"));
+        assert_eq!(alerts[0].vehicle_id.as_deref(), Some("v1"));
+    }
+
+    #[test]
+    fn delays_must_exceed_the_threshold() {
+        let mut engine =
+            AlertEngine::new().rule(Rule::delayed("82", Duration::from_secs(60)).any_route());
+        let alerts = engine.evaluate(&snapshot(json!([
+            trip("t1", "82-202", 60),
+            trip("t2", "83-202", 61)
+        ])));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].trip_id, "t2");
+    }
+
+    #[test]
+    fn raises_each_alert_once_until_the_trip_stops_matching() {
+        let mut engine = AlertEngine::new()
+            .rule(Rule::delayed("82", Duration::from_secs(60)))
+            .rule(Rule::cancelled("82"));
+        let late = snapshot(json!([trip("t1", "82-202", 120)]));
+        let on_time = snapshot(json!([trip("t1", "82-202", 0)]));
+
+        assert_eq!(engine.evaluate(&late).len(), 1);
+        assert!(engine.evaluate(&late).is_empty());
+        assert!(engine.evaluate(&on_time).is_empty());
+        assert_eq!(engine.evaluate(&late).len(), 1);
+
+        // Cancelled trips usually have no vehicle, so they are found among unmatched entities.
+        let cancelled = unmatched(json!([{
+            "id": "t1",
+            "trip_update": {
+                "trip": {"trip_id": "t1", "route_id": "82-202", "schedule_relationship": 3}
+            }
+        }]));
+        let alerts = engine.evaluate(&cancelled);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!((alerts[0].rule, alerts[0].kind), (1, AlertKind::Cancelled));
+        assert!(engine.evaluate(&cancelled).is_empty());
+    }
+}
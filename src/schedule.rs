@@ -0,0 +1,178 @@
+//! Requests to the static GTFS API, which serves the published schedule.
+
+use reqwest::StatusCode;
+use serde::de::IgnoredAny;
+
+use crate::{
+    error::Result,
+    query::QueryEncoder,
+    resolver::IdKind,
+    types::schedule::{GtfsVersion, Route, ShapePoint, Stop, StopTime, Trip},
+    Realtime,
+};
+
+/// Encodes values placed in a path segment, escaping everything but the unreserved characters.
+const PATH_SEGMENT: QueryEncoder = QueryEncoder::new();
+
+impl Realtime {
+    /// Fetches the routes with the given route number from the static GTFS API.
+    ///
+    /// The static API returns the routes of every GTFS version which is currently published, so
+    /// during a change of version the same route number can match a route in each version.
+    ///
+    /// # Parameters
+    ///
+    /// * `short_name` - The route number shown to passengers, such as `82` or `NX2`.
+    pub async fn fetch_routes_by_short_name(&self, short_name: &str) -> Result<Vec<Route>> {
+        self.get_static(&self.static_path("routes/routeShortName", short_name))
+            .await
+    }
+
+    /// Fetches the stops with the given stop code from the static GTFS API. As with routes, a
+    /// stop code can match a stop in each GTFS version which is currently published.
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The code shown on the stop's signage, such as `7036`.
+    pub async fn fetch_stops_by_code(&self, code: &str) -> Result<Vec<Stop>> {
+        self.get_static(&self.static_path("stops/stopCode", code))
+            .await
+    }
+
+    /// Fetches a trip from the static GTFS API. The result is empty if the static API does not
+    /// know the trip.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_id` - The full ID of the trip, including the GTFS version.
+    pub async fn fetch_trip(&self, trip_id: &str) -> Result<Vec<Trip>> {
+        self.get_static(&self.static_path(IdKind::Trip.static_path(), trip_id))
+            .await
+    }
+
+    /// Fetches the scheduled stops of a trip from the static GTFS API, in the order of their
+    /// stop sequence.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_id` - The full ID of the trip, including the GTFS version.
+    pub async fn fetch_stop_times_by_trip_id(&self, trip_id: &str) -> Result<Vec<StopTime>> {
+        let mut stop_times: Vec<StopTime> = self
+            .get_static(&self.static_path("stopTimes/tripId", trip_id))
+            .await?;
+        stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+        Ok(stop_times)
+    }
+
+    /// Fetches the scheduled stops of every trip which serves a stop from the static GTFS API.
+    /// This includes trips of every service, not only those running today.
+    ///
+    /// # Parameters
+    ///
+    /// * `stop_id` - The full ID of the stop, including the GTFS version.
+    pub async fn fetch_stop_times_by_stop_id(&self, stop_id: &str) -> Result<Vec<StopTime>> {
+        self.get_static(&self.static_path("stopTimes/stopId", stop_id))
+            .await
+    }
+
+    /// Fetches the points of a shape from the static GTFS API, in the order of their sequence.
+    ///
+    /// # Parameters
+    ///
+    /// * `shape_id` - The full ID of the shape, including the GTFS version.
+    pub async fn fetch_shape(&self, shape_id: &str) -> Result<Vec<ShapePoint>> {
+        let mut points: Vec<ShapePoint> = self
+            .get_static(&self.static_path("shapes/shapeId", shape_id))
+            .await?;
+        points.sort_by_key(|point| point.shape_pt_sequence);
+        Ok(points)
+    }
+
+    /// Returns whether the static GTFS API knows an object with the given full ID.
+    ///
+    /// # Parameters
+    ///
+    /// * `kind` - The kind of object the ID refers to.
+    /// * `id` - The full ID, including the GTFS version.
+    pub(crate) async fn static_id_exists(&self, kind: IdKind, id: &str) -> Result<bool> {
+        let path = self.static_path(kind.static_path(), id);
+        match self.get_static::<Vec<IgnoredAny>>(&path).await {
+            Ok(found) => Ok(!found.is_empty()),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fetches the GTFS versions which are currently published from the static GTFS API.
+    pub async fn fetch_versions(&self) -> Result<Vec<GtfsVersion>> {
+        self.get_static(&format!("{}/versions", self.api_version.static_gtfs_path()))
+            .await
+    }
+
+    /// Returns the path of a static GTFS API endpoint which looks up objects by a value, under
+    /// the path the configured API version serves the static GTFS API under.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The path of the endpoint, such as `stops/stopCode`.
+    /// * `value` - The value to look up, which is escaped.
+    fn static_path(&self, resource: &str, value: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.api_version.static_gtfs_path(),
+            resource,
+            PATH_SEGMENT.encode(value)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::future;
+
+    use super::*;
+    use crate::{
+        transport::tests::{block_on, response, StubTransport},
+        ApiVersion, Realtime,
+    };
+
+    #[test]
+    fn missing_ids_are_not_found_under_concurrency() {
+        let transport = StubTransport::new(|_| response(404, "")).delay(Duration::from_millis(10));
+        let realtime = Realtime::new("key").with_transport(transport.clone());
+
+        let (a, b) = block_on(future::join(
+            realtime.static_id_exists(IdKind::Route, "11101-20210927110507_v105.39"),
+            realtime.static_id_exists(IdKind::Route, "11101-20210927110507_v105.39"),
+        ));
+        assert!(!a.unwrap());
+        assert!(!b.unwrap());
+        assert_eq!(transport.requests(), 1);
+    }
+
+    #[test]
+    fn paths_follow_the_api_version() {
+        let transport = StubTransport::new(|request| {
+            let body = match request.url().path() {
+                "/gtfs/v3/stops/stopCode/7036" | "/gtfs/v3/versions" => r#"{"response": []}"#,
+                _ => "",
+            };
+            response(if body.is_empty() { 404 } else { 200 }, body)
+        });
+        let realtime = Realtime::builder("key")
+            .api_version(ApiVersion::V3)
+            .build()
+            .with_transport(transport);
+
+        assert!(block_on(realtime.fetch_stops_by_code("7036"))
+            .unwrap()
+            .is_empty());
+        assert!(block_on(realtime.fetch_versions()).unwrap().is_empty());
+        assert!(
+            !block_on(realtime.static_id_exists(IdKind::Stop, "7036-20210927110507_v105.39"))
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! An embedded HTTP server which re-serves the latest merged realtime feed.
+//!
+//! The server polls AT in the background and serves the most recent snapshot to any number of
+//! local consumers, so they can all share a single AT subscription key. The following endpoints
+//! are available:
+//!
+//! * `GET /realtime.json` - The latest header and merged entities as JSON.
+//! * `GET /realtime.pb` - The latest snapshot as a GTFS-RT protobuf `FeedMessage`.
+//!
+//! Both endpoints respond with `503 Service Unavailable` until the first fetch succeeds.
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use futures_util::future::{self, Either};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{error::Result, protobuf, CombinedResponse, Realtime};
+
+/// The latest snapshot, pre-rendered in each format served.
+struct Rendered {
+    json: Vec<u8>,
+    protobuf: Vec<u8>,
+}
+
+impl Rendered {
+    /// Renders a snapshot, returning [`None`] if it cannot be serialized.
+    ///
+    /// [`None`]: std::option::Option::None
+    fn new(combined: &CombinedResponse) -> Option<Self> {
+        Some(Self {
+            json: serde_json::to_vec(&combined.document()).ok()?,
+            protobuf: protobuf::encode_feed(&combined.header, &combined.entities),
+        })
+    }
+}
+
+type State = Arc<RwLock<Option<Arc<Rendered>>>>;
+
+/// Polls AT and serves the latest merged feed over HTTP on the given address.
+///
+/// Failed fetches are ignored, and the previous snapshot keeps being served until a fetch
+/// succeeds again. This function only returns if the server fails.
+///
+/// # Parameters
+///
+/// * `realtime` - The client used to poll AT.
+/// * `addr` - The address to listen on.
+/// * `interval` - How long to wait between polls, at least the client's minimum poll interval.
+pub async fn serve(realtime: Realtime, addr: SocketAddr, interval: Duration) -> Result<()> {
+    serve_until_cancelled(realtime, addr, interval, CancellationToken::new()).await
+}
+
+/// Polls AT and serves the latest merged feed over HTTP in the same way as [`serve`], until the
+/// given token is cancelled.
+///
+/// Once the token is cancelled polling stops, the server stops accepting connections, and this
+/// function returns after the open connections have finished.
+///
+/// # Parameters
+///
+/// * `realtime` - The client used to poll AT.
+/// * `addr` - The address to listen on.
+/// * `interval` - How long to wait between polls, at least the client's minimum poll interval.
+/// * `token` - The token which shuts the server down when cancelled.
+pub async fn serve_until_cancelled(
+    realtime: Realtime,
+    addr: SocketAddr,
+    interval: Duration,
+    token: CancellationToken,
+) -> Result<()> {
+    let state = State::default();
+
+    let make_svc = {
+        let state = state.clone();
+        make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(&state, req)) }
+                }))
+            }
+        })
+    };
+
+    let server = Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(token.cancelled_owned());
+    let poller = poll(realtime, state, interval);
+
+    match future::select(Box::pin(server), Box::pin(poller)).await {
+        Either::Left((result, _)) => Ok(result?),
+        Either::Right((never, _)) => match never {},
+    }
+}
+
+async fn poll(realtime: Realtime, state: State, interval: Duration) -> Infallible {
+    let interval = realtime.poll_interval(interval);
+
+    loop {
+        if let Ok(combined) = realtime.fetch_combined(None, None).await {
+            if let Some(rendered) = Rendered::new(&combined) {
+                *state.write().unwrap() = Some(Arc::new(rendered));
+            }
+        }
+
+        realtime.timer.sleep(interval).await;
+    }
+}
+
+fn handle(state: &State, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET {
+        return status(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let rendered = match state.read().unwrap().clone() {
+        Some(rendered) => rendered,
+        None => return status(StatusCode::SERVICE_UNAVAILABLE),
+    };
+
+    let (content_type, body) = match req.uri().path() {
+        "/realtime.json" => ("application/json", rendered.json.clone()),
+        "/realtime.pb" => ("application/x-protobuf", rendered.protobuf.clone()),
+        _ => return status(StatusCode::NOT_FOUND),
+    };
+
+    Response::builder()
+        .header("Content-Type", content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::{block_on, response, StubTransport};
+
+    const FEED: &str = r#"{
+        "status": "OK",
+        "response": {
+            "header": {"gtfs_realtime_version": "2.0", "timestamp": 1},
+            "entity": [
+                {"id": "t1", "trip_update": {"trip": {"trip_id": "t1"}, "delay": 60}},
+                {"id": "a", "vehicle": {"trip": {"trip_id": "t1"}, "vehicle": {"id": "a"}}}
+            ]
+        }
+    }"#;
+
+    fn get(state: &State, method: Method, path: &str) -> (StatusCode, Option<String>, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        let response = handle(state, request);
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .map(|value| value.to_str().unwrap().to_string());
+        let status = response.status();
+        let body = block_on(hyper::body::to_bytes(response.into_body())).unwrap();
+        (status, content_type, body.to_vec())
+    }
+
+    /// Polls a stub feed until the first snapshot has been rendered.
+    fn fetch_first(state: &State) {
+        let transport = StubTransport::new(|_| response(200, FEED));
+        let realtime = Realtime::new("key").with_transport(transport);
+        block_on(async {
+            let fetched = async {
+                while state.read().unwrap().is_none() {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            };
+            let poller = poll(realtime, state.clone(), Duration::from_secs(60));
+            future::select(Box::pin(poller), Box::pin(fetched)).await;
+        });
+    }
+
+    #[test]
+    fn unavailable_until_the_first_fetch() {
+        let state = State::default();
+        for path in ["/realtime.json", "/realtime.pb", "/other"].iter() {
+            let (status, _, body) = get(&state, Method::GET, path);
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert!(body.is_empty());
+        }
+    }
+
+    #[test]
+    fn serves_the_latest_snapshot() {
+        let state = State::default();
+        fetch_first(&state);
+
+        let (status, content_type, body) = get(&state, Method::GET, "/realtime.json");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(document["header"]["timestamp"], 1.0);
+        assert!(String::from_utf8(body)
+            .unwrap()
+            .contains(r#""trip_id":"t1""#));
+
+        let (status, content_type, body) = get(&state, Method::GET, "/realtime.pb");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type.as_deref(), Some("application/x-protobuf"));
+        let (header, entities) = protobuf::decode_feed(&body).unwrap();
+        assert_eq!(header.timestamp, Some(1.0));
+        assert!(entities.iter().any(|entity| entity.vehicle.is_some()));
+        let delays: Vec<_> = entities
+            .iter()
+            .filter_map(|entity| entity.trip_update.as_ref()?.delay)
+            .collect();
+        assert_eq!(delays, [60]);
+
+        assert_eq!(
+            get(&state, Method::POST, "/realtime.json").0,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(get(&state, Method::GET, "/other").0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,418 @@
+//! Synthesized realtime data for offline development.
+//!
+//! A [`Simulator`] generates plausible merged entities for vehicles moving along supplied route
+//! shapes, and exposes them through the same methods as [`Realtime`], so frontends can be
+//! developed and demonstrated without an API key.
+//!
+//! Generated IDs follow AT's versioned form, so the [`Entity`] helpers such as
+//! [`Entity::route_id`] work as they do on live data. A route `NX1` simulated with the version
+//! `sim` has the route ID `NX1-sim`, and its first vehicle serves the trip `NX1_0-sim`.
+//!
+//! [`Realtime`]: crate::Realtime
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::stream::{self, Stream};
+
+use crate::{
+    error::Result,
+    geo::distance,
+    timer::{Timer, TokioTimer},
+    types::{
+        gtfs::{
+            Entity, OccupancyStatus, Position, TripDescriptor, TripUpdate, VehicleDescriptor,
+            VehiclePosition, VehicleStopStatus,
+        },
+        Header, Incrementality,
+    },
+    CombinedResponse, Ids,
+};
+
+/// A route along which vehicles are simulated.
+#[derive(Debug, Clone)]
+pub struct SimulatedRoute {
+    /// The unversioned route ID, such as `NX1`.
+    pub route_id: String,
+    /// The points of the route shape, as `(latitude, longitude)` pairs.
+    pub shape: Vec<(f32, f32)>,
+    /// The number of vehicles spread evenly along the route.
+    pub vehicles: u32,
+    /// The speed vehicles travel at, in metres per second.
+    pub speed: f32,
+    /// The average delay reported for trips on the route, in seconds.
+    pub delay: i32,
+    /// How far the delays of the vehicles on the route are spread either side of `delay`, in
+    /// seconds. The delays of the vehicles are spaced evenly across the range, from the first
+    /// vehicle running earliest to the last running latest.
+    pub delay_spread: i32,
+    /// The occupancy reported for vehicles on the route.
+    pub occupancy: Option<OccupancyStatus>,
+}
+
+impl SimulatedRoute {
+    /// Creates a simulated route with a single on-time vehicle travelling at 10 m/s. If more
+    /// vehicles are added, their delays are spread up to a minute either side of the route's
+    /// delay.
+    ///
+    /// # Parameters
+    ///
+    /// * `route_id` - The unversioned route ID.
+    /// * `shape` - The points of the route shape, as `(latitude, longitude)` pairs.
+    pub fn new<S: Into<String>>(route_id: S, shape: Vec<(f32, f32)>) -> Self {
+        Self {
+            route_id: route_id.into(),
+            shape,
+            vehicles: 1,
+            speed: 10.0,
+            delay: 0,
+            delay_spread: 60,
+            occupancy: None,
+        }
+    }
+}
+
+/// A source of synthesized realtime data.
+#[derive(Clone)]
+pub struct Simulator {
+    routes: Vec<SimulatedRoute>,
+    version: String,
+    started: Instant,
+    timer: Arc<dyn Timer>,
+}
+
+impl fmt::Debug for Simulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Simulator")
+            .field("routes", &self.routes)
+            .field("version", &self.version)
+            .field("started", &self.started)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Simulator {
+    /// Creates a simulator for the given routes. Vehicles start at the beginning of their
+    /// segment of the route when the simulator is created.
+    ///
+    /// # Parameters
+    ///
+    /// * `routes` - The routes to simulate.
+    pub fn new(routes: Vec<SimulatedRoute>) -> Self {
+        Self {
+            routes,
+            version: "sim".into(),
+            started: Instant::now(),
+            timer: Arc::new(TokioTimer),
+        }
+    }
+
+    /// Sets the GTFS version suffix appended to generated route and trip IDs.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The version suffix to use.
+    pub fn with_version<S: Into<String>>(mut self, version: S) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Sets the timer used to wait between streamed snapshots, instead of tokio.
+    ///
+    /// # Parameters
+    ///
+    /// * `timer` - The timer to wait with.
+    pub fn with_timer<T: Timer + 'static>(mut self, timer: T) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Returns simulated entities in the same form as [`Realtime::fetch_combined`].
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - A list of trip IDs to search for.
+    /// * `vehicle_ids` - A list of vehicle IDs to search for.
+    ///
+    /// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+    pub async fn fetch_combined(
+        &self,
+        trip_ids: impl Into<Ids>,
+        vehicle_ids: impl Into<Ids>,
+    ) -> Result<CombinedResponse> {
+        let (trip_ids, vehicle_ids) = (trip_ids.into(), vehicle_ids.into());
+        let mut combined = self.snapshot_at(self.started.elapsed());
+
+        combined.entities.retain(|e| {
+            let trip_id = e
+                .vehicle
+                .as_ref()
+                .and_then(|v| v.trip.as_ref()?.trip_id.as_deref());
+            let vehicle_id = e
+                .vehicle
+                .as_ref()
+                .and_then(|v| v.vehicle.as_ref()?.id.as_deref());
+
+            let matches = |ids: &Ids, id: Option<&str>| id.is_some_and(|id| ids.contains(id));
+
+            (trip_ids.is_all() && vehicle_ids.is_all())
+                || matches(&trip_ids, trip_id)
+                || matches(&vehicle_ids, vehicle_id)
+        });
+
+        Ok(combined)
+    }
+
+    /// Yields simulated snapshots at a fixed interval, in the same form as [`Realtime::stream`].
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - How long to wait between snapshots.
+    ///
+    /// [`Realtime::stream`]: crate::Realtime::stream
+    pub fn stream(&self, interval: Duration) -> impl Stream<Item = Result<CombinedResponse>> + '_ {
+        stream::unfold(true, move |first| async move {
+            if !first {
+                self.timer.sleep(interval).await;
+            }
+
+            Some((self.fetch_combined(None, None).await, false))
+        })
+    }
+
+    /// Generates the entities for the given time since the simulation started.
+    ///
+    /// # Parameters
+    ///
+    /// * `elapsed` - The simulated time since the start of the simulation.
+    pub fn snapshot_at(&self, elapsed: Duration) -> CombinedResponse {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let header = Header {
+            gtfs_realtime_version: "2.0".into(),
+            incrementality: Incrementality::FullDataset,
+            timestamp: Some(now as f64),
+        };
+
+        let mut entities = vec![];
+        for route in self.routes.iter() {
+            let length = shape_length(&route.shape);
+            if length <= 0.0 || route.vehicles == 0 {
+                continue;
+            }
+
+            for i in 0..route.vehicles {
+                let offset = length * f64::from(i) / f64::from(route.vehicles);
+                let travelled = offset + f64::from(route.speed) * elapsed.as_secs_f64();
+                let (latitude, longitude, bearing) = point_along(&route.shape, travelled % length);
+                entities.push(self.entity(route, i, now, latitude, longitude, bearing));
+            }
+        }
+
+        CombinedResponse::new(header, entities)
+    }
+
+    fn entity(
+        &self,
+        route: &SimulatedRoute,
+        i: u32,
+        timestamp: u64,
+        latitude: f32,
+        longitude: f32,
+        bearing: f32,
+    ) -> Entity {
+        let vehicle_id = format!("sim_{}_{}", route.route_id, i);
+        let trip = TripDescriptor {
+            trip_id: Some(format!("{}_{}-{}", route.route_id, i, self.version)),
+            route_id: Some(format!("{}-{}", route.route_id, self.version)),
+            direction_id: Some(0),
+            start_time: None,
+            start_date: None,
+            schedule_relationship: None,
+        };
+        let vehicle = VehicleDescriptor {
+            id: Some(vehicle_id.clone()),
+            label: Some(vehicle_id.clone()),
+            license_plate: None,
+        };
+
+        Entity {
+            id: vehicle_id,
+            trip_update: Some(TripUpdate {
+                trip: trip.clone(),
+                vehicle: Some(vehicle.clone()),
+                stop_time_update: None,
+                timestamp: Some(timestamp),
+                delay: Some(vehicle_delay(route, i)),
+            }),
+            vehicle: Some(VehiclePosition {
+                trip: Some(trip),
+                vehicle: Some(vehicle),
+                position: Some(Position {
+                    latitude,
+                    longitude,
+                    bearing: Some(bearing),
+                    odometer: None,
+                    speed: Some(route.speed),
+                }),
+                current_stop_sequence: None,
+                stop_id: None,
+                current_status: VehicleStopStatus::InTransitTo,
+                timestamp: Some(timestamp),
+                congestion_level: None,
+                occupancy_status: route.occupancy,
+            }),
+            is_deleted: false,
+        }
+    }
+}
+
+/// Returns the delay of a vehicle on a route, in seconds.
+fn vehicle_delay(route: &SimulatedRoute, i: u32) -> i32 {
+    if route.vehicles < 2 {
+        return route.delay;
+    }
+    let spread = i64::from(route.delay_spread);
+    let offset = -spread + 2 * spread * i64::from(i) / i64::from(route.vehicles - 1);
+    route.delay.saturating_add(offset as i32)
+}
+
+fn shape_length(shape: &[(f32, f32)]) -> f64 {
+    shape.windows(2).map(|w| distance(w[0], w[1])).sum()
+}
+
+/// Returns the point and bearing (in degrees) at the given distance along a shape.
+fn point_along(shape: &[(f32, f32)], mut travelled: f64) -> (f32, f32, f32) {
+    for w in shape.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        let segment = distance(a, b);
+        if travelled <= segment && segment > 0.0 {
+            let t = (travelled / segment) as f32;
+            let latitude = a.0 + (b.0 - a.0) * t;
+            let longitude = a.1 + (b.1 - a.1) * t;
+            let bearing = ((b.1 - a.1) * a.0.to_radians().cos())
+                .atan2(b.0 - a.0)
+                .to_degrees()
+                .rem_euclid(360.0);
+            return (latitude, longitude, bearing);
+        }
+        travelled -= segment;
+    }
+
+    let last = shape.last().copied().unwrap_or_default();
+    (last.0, last.1, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::block_on;
+
+    /// A route running about 1.1 km due south.
+    fn route() -> SimulatedRoute {
+        SimulatedRoute {
+            vehicles: 3,
+            delay: 30,
+            ..SimulatedRoute::new("NX1", vec![(-36.85, 174.76), (-36.86, 174.76)])
+        }
+    }
+
+    fn latitudes(combined: &CombinedResponse) -> Vec<f32> {
+        combined
+            .entities
+            .iter()
+            .map(|e| {
+                e.vehicle
+                    .as_ref()
+                    .unwrap()
+                    .position
+                    .as_ref()
+                    .unwrap()
+                    .latitude
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generates_versioned_entities() {
+        let simulator = Simulator::new(vec![route()]).with_version("v1");
+        let combined = simulator.snapshot_at(Duration::ZERO);
+        assert_eq!(combined.entities.len(), 3);
+
+        let entity = &combined.entities[0];
+        assert_eq!(entity.id, "sim_NX1_0");
+        assert_eq!(entity.route_id().as_deref(), Some("NX1"));
+        assert_eq!(entity.trip_id().as_deref(), Some("NX1_0"));
+        let trip = entity.vehicle.as_ref().unwrap().trip.as_ref().unwrap();
+        assert_eq!(trip.trip_id.as_deref(), Some("NX1_0-v1"));
+
+        let delays: Vec<_> = combined
+            .entities
+            .iter()
+            .map(|e| e.trip_update.as_ref().unwrap().delay.unwrap())
+            .collect();
+        assert_eq!(delays, [-30, 30, 90]);
+
+        let single = Simulator::new(vec![SimulatedRoute {
+            vehicles: 1,
+            ..route()
+        }]);
+        let delay = single.snapshot_at(Duration::ZERO).entities[0]
+            .trip_update
+            .as_ref()
+            .unwrap()
+            .delay;
+        assert_eq!(delay, Some(30));
+    }
+
+    #[test]
+    fn moves_vehicles_along_the_shape() {
+        let simulator = Simulator::new(vec![SimulatedRoute {
+            vehicles: 1,
+            ..route()
+        }]);
+        assert_eq!(latitudes(&simulator.snapshot_at(Duration::ZERO)), [-36.85]);
+
+        // 100 m south is about 0.0009 degrees of latitude.
+        let moved = latitudes(&simulator.snapshot_at(Duration::from_secs(10)))[0];
+        assert!((moved - -36.8509).abs() < 1e-4);
+        let position = simulator.snapshot_at(Duration::from_secs(10)).entities[0]
+            .vehicle
+            .as_ref()
+            .unwrap()
+            .position
+            .clone()
+            .unwrap();
+        assert!((position.bearing.unwrap() - 180.0).abs() < 1e-3);
+
+        // After reaching the end of the shape the vehicle starts from the beginning again.
+        let length = shape_length(&route().shape);
+        let lap = Duration::from_secs_f64(length / 10.0 + 10.0);
+        let wrapped = latitudes(&simulator.snapshot_at(lap))[0];
+        assert!((wrapped - moved).abs() < 1e-4);
+
+        let empty = Simulator::new(vec![SimulatedRoute::new("E", vec![(-36.85, 174.76)])]);
+        assert!(empty.snapshot_at(Duration::ZERO).entities.is_empty());
+    }
+
+    #[test]
+    fn filters_by_trip_and_vehicle() {
+        let simulator = Simulator::new(vec![route()]);
+        let ids = |combined: CombinedResponse| -> Vec<String> {
+            combined.entities.into_iter().map(|e| e.id).collect()
+        };
+
+        let all = block_on(simulator.fetch_combined(None, None)).unwrap();
+        assert_eq!(all.entities.len(), 3);
+        let by_trip = block_on(simulator.fetch_combined(["NX1_1-sim"], None)).unwrap();
+        assert_eq!(ids(by_trip), ["sim_NX1_1"]);
+        let by_vehicle = block_on(simulator.fetch_combined(None, ["sim_NX1_2"])).unwrap();
+        assert_eq!(ids(by_vehicle), ["sim_NX1_2"]);
+    }
+}
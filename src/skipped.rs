@@ -0,0 +1,158 @@
+//! Detection of stops which a trip will not serve.
+//!
+//! AT marks a stop which a trip will pass without stopping with a stop time update whose schedule
+//! relationship is [`Skipped`]. The update only identifies the stop by its ID or stop sequence, so
+//! [`skipped_stops`] combines it with the trip's stops from the static GTFS API to describe each
+//! skipped stop, such as for "your stop is being skipped" notifications.
+//!
+//! [`Skipped`]: crate::types::gtfs::ScheduleRelationship::Skipped
+
+use crate::types::{
+    gtfs::{ScheduleRelationship, StopTimeUpdate, TripUpdate},
+    schedule::{Stop, StopTime},
+};
+
+/// A stop which a trip will not serve.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SkippedStop {
+    /// The full ID of the stop.
+    pub stop_id: String,
+    /// The position of the stop in the trip, if known.
+    pub stop_sequence: Option<u32>,
+    /// The stop from the static schedule, if it was in the list of stops given.
+    pub stop: Option<Stop>,
+}
+
+/// Returns the stops of a trip update which will be skipped.
+///
+/// # Parameters
+///
+/// * `trip_update` - The trip update to check.
+/// * `stop_times` - The scheduled stops of the trip, as returned by
+///   [`Realtime::fetch_stop_times_by_trip_id`]. These are used to find the stop of an update which
+///   only has a stop sequence, and the stop sequence of an update which only has a stop ID.
+/// * `stops` - The static stops to describe the skipped stops with.
+///
+/// # Returns
+///
+/// Returns the skipped stops in the order of the stop time updates. Updates which cannot be
+/// matched to a stop are left out.
+///
+/// [`Realtime::fetch_stop_times_by_trip_id`]: crate::Realtime::fetch_stop_times_by_trip_id
+pub fn skipped_stops(
+    trip_update: &TripUpdate,
+    stop_times: &[StopTime],
+    stops: &[Stop],
+) -> Vec<SkippedStop> {
+    trip_update
+        .stop_time_update
+        .iter()
+        .filter(|update| matches!(update.schedule_relationship, ScheduleRelationship::Skipped))
+        .filter_map(|update| {
+            let stop_time = scheduled_stop(update, stop_times);
+            let stop_id = update
+                .stop_id
+                .clone()
+                .or_else(|| Some(stop_time?.stop_id.clone()))?;
+            let stop = stops.iter().find(|stop| stop.stop_id == stop_id).cloned();
+
+            Some(SkippedStop {
+                stop_sequence: update
+                    .stop_sequence
+                    .or_else(|| Some(stop_time?.stop_sequence)),
+                stop_id,
+                stop,
+            })
+        })
+        .collect()
+}
+
+/// Returns the scheduled stop a stop time update refers to, matching by stop sequence if the
+/// update has one, or by stop ID otherwise.
+fn scheduled_stop<'a>(update: &StopTimeUpdate, stop_times: &'a [StopTime]) -> Option<&'a StopTime> {
+    match (update.stop_sequence, update.stop_id.as_deref()) {
+        (Some(sequence), _) => stop_times.iter().find(|st| st.stop_sequence == sequence),
+        (None, Some(stop_id)) => stop_times.iter().find(|st| st.stop_id == stop_id),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn trip_update(update: Value) -> TripUpdate {
+        serde_json::from_value(json!({"trip": {"trip_id": "t1"}, "stop_time_update": update}))
+            .unwrap()
+    }
+
+    fn stop_times() -> Vec<StopTime> {
+        serde_json::from_value(json!([
+            {"trip_id": "t1", "stop_id": "s1", "stop_sequence": 1},
+            {"trip_id": "t1", "stop_id": "s2", "stop_sequence": 2},
+            {"trip_id": "t1", "stop_id": "s3", "stop_sequence": 3},
+        ]))
+        .unwrap()
+    }
+
+    fn stops() -> Vec<Stop> {
+        serde_json::from_value(json!([
+            {"stop_id": "s2", "stop_name": "Second", "stop_lat": -36.85, "stop_lon": 174.76},
+        ]))
+        .unwrap()
+    }
+
+    fn summary(skipped: &[SkippedStop]) -> Vec<(&str, Option<u32>, Option<&str>)> {
+        skipped
+            .iter()
+            .map(|s| {
+                let name = s.stop.as_ref().map(|stop| stop.stop_name.as_str());
+                (s.stop_id.as_str(), s.stop_sequence, name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_updates_by_stop_sequence() {
+        let update = trip_update(json!({"stop_sequence": 2, "schedule_relationship": 1}));
+        let skipped = skipped_stops(&update, &stop_times(), &stops());
+        assert_eq!(summary(&skipped), [("s2", Some(2), Some("Second"))]);
+
+        // The stop sequence is preferred over the stop ID when an update has both.
+        let update = trip_update(json!({
+            "stop_sequence": 3,
+            "stop_id": "s3",
+            "schedule_relationship": 1
+        }));
+        let skipped = skipped_stops(&update, &stop_times(), &stops());
+        assert_eq!(summary(&skipped), [("s3", Some(3), None)]);
+
+        let update = trip_update(json!({"stop_sequence": 9, "schedule_relationship": 1}));
+        assert!(skipped_stops(&update, &stop_times(), &stops()).is_empty());
+    }
+
+    #[test]
+    fn matches_updates_by_stop_id() {
+        let update = trip_update(json!({"stop_id": "s2", "schedule_relationship": 1}));
+        let skipped = skipped_stops(&update, &stop_times(), &stops());
+        assert_eq!(summary(&skipped), [("s2", Some(2), Some("Second"))]);
+
+        // A stop which is not in the schedule is still reported, without a stop sequence.
+        let update = trip_update(json!({"stop_id": "s9", "schedule_relationship": 1}));
+        let skipped = skipped_stops(&update, &stop_times(), &stops());
+        assert_eq!(summary(&skipped), [("s9", None, None)]);
+    }
+
+    #[test]
+    fn ignores_stops_which_are_served() {
+        let update = trip_update(json!({"stop_id": "s2", "schedule_relationship": 0}));
+        assert!(skipped_stops(&update, &stop_times(), &stops()).is_empty());
+        let update = trip_update(json!({"stop_id": "s2"}));
+        assert!(skipped_stops(&update, &stop_times(), &stops()).is_empty());
+        let update = trip_update(Value::Null);
+        assert!(skipped_stops(&update, &stop_times(), &stops()).is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+//! An in-process fake of the AT API, for testing code which uses a [`Realtime`] client.
+//!
+//! A [`MockTransport`] answers requests with the responses of the [`Mock`]s registered on it,
+//! without a network or an API key. Mocks match the AT endpoints by path, and can require the
+//! `tripid` and `vehicleid` query parameters to hold a set of IDs. AT only accepts those IDs
+//! separated by unescaped commas, so a request which escapes the commas does not match.
+//!
+//! ```no_run
+//! use at_api_rs::{
+//!     testing::{mock::{Mock, MockTransport}, REALTIME_COMBINED},
+//!     ApiVersion,
+//! };
+//!
+//! # async fn run() -> at_api_rs::error::Result<()> {
+//! let mock = MockTransport::new();
+//! mock.register(Mock::realtime(ApiVersion::V2).respond_with(200, REALTIME_COMBINED));
+//!
+//! let combined = mock.client(ApiVersion::V2).fetch_combined(None, None).await?;
+//! assert_eq!(mock.requests().len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Realtime`]: crate::Realtime
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::{Method, Request, Response};
+use url::{form_urlencoded, Url};
+
+use crate::{
+    transport::{Transport, TransportFuture},
+    ApiVersion, Realtime,
+};
+
+/// A canned response to requests to an endpoint.
+#[derive(Debug, Clone)]
+pub struct Mock {
+    path: String,
+    trip_ids: Option<BTreeSet<String>>,
+    vehicle_ids: Option<BTreeSet<String>>,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Mock {
+    /// Creates a mock of the endpoint at a path, such as `/v2/gtfs/versions`, which responds
+    /// with `200 OK` and an empty body.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the endpoint, relative to the base URL.
+    pub fn path<S: Into<String>>(path: S) -> Self {
+        Self {
+            path: path.into(),
+            trip_ids: None,
+            vehicle_ids: None,
+            status: 200,
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+
+    /// Creates a mock of the combined realtime endpoint.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The version of the API the endpoint belongs to.
+    pub fn realtime(version: ApiVersion) -> Self {
+        Self::path(version.realtime_path())
+    }
+
+    /// Creates a mock of the trip updates endpoint.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The version of the API the endpoint belongs to.
+    pub fn trip_updates(version: ApiVersion) -> Self {
+        Self::path(version.trip_updates_path())
+    }
+
+    /// Creates a mock of the vehicle positions endpoint.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The version of the API the endpoint belongs to.
+    pub fn vehicle_positions(version: ApiVersion) -> Self {
+        Self::path(version.vehicle_positions_path())
+    }
+
+    /// Only matches requests for exactly these trip IDs, in any order. By default requests match
+    /// whichever trip IDs they ask for.
+    ///
+    /// # Parameters
+    ///
+    /// * `trip_ids` - The trip IDs the `tripid` query parameter must hold.
+    pub fn trip_ids<I, S>(mut self, trip_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.trip_ids = Some(trip_ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only matches requests for exactly these vehicle IDs, in any order. By default requests
+    /// match whichever vehicle IDs they ask for.
+    ///
+    /// # Parameters
+    ///
+    /// * `vehicle_ids` - The vehicle IDs the `vehicleid` query parameter must hold.
+    pub fn vehicle_ids<I, S>(mut self, vehicle_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.vehicle_ids = Some(vehicle_ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the status and body of the response.
+    ///
+    /// # Parameters
+    ///
+    /// * `status` - The status code of the response.
+    /// * `body` - The body of the response, such as one of the fixtures of this module.
+    pub fn respond_with<S: Into<String>>(mut self, status: u16, body: S) -> Self {
+        self.status = status;
+        self.body = body.into();
+        self
+    }
+
+    /// Adds a header to the response, such as `Retry-After` or a rate limit header.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the header.
+    /// * `value` - The value of the header.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Returns whether a request is for the endpoint and IDs of the mock.
+    fn matches(&self, request: &Request) -> bool {
+        let url = request.url();
+        let ids_match = |name, expected: &Option<BTreeSet<String>>| match expected {
+            Some(expected) => query_ids(url, name).as_ref() == Some(expected),
+            None => true,
+        };
+
+        request.method() == Method::GET
+            && url.path() == self.path
+            && ids_match("tripid", &self.trip_ids)
+            && ids_match("vehicleid", &self.vehicle_ids)
+    }
+
+    fn response(&self) -> Response {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let response = builder
+            .body(self.body.clone())
+            .unwrap_or_else(|_| status_response(500, "the mock has an invalid status or header"));
+        Response::from(response)
+    }
+}
+
+/// A [`Transport`] which answers requests from registered [`Mock`]s, and records the URL of each
+/// request it receives.
+///
+/// Each request is answered by the first registered mock which matches it, and requests which
+/// match no mock are answered with `404 Not Found`. Clones share their mocks and requests, so a
+/// clone can be given to a client while the test keeps another to inspect it.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    mocks: Arc<Mutex<Vec<Mock>>>,
+    requests: Arc<Mutex<Vec<Url>>>,
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("mocks", &self.mocks.lock().unwrap().len())
+            .field("requests", &self.requests.lock().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MockTransport {
+    /// Creates a transport without any mocks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a mock, which answers the requests it matches unless a mock registered earlier
+    /// also matches them.
+    ///
+    /// # Parameters
+    ///
+    /// * `mock` - The mock to register.
+    pub fn register(&self, mock: Mock) -> &Self {
+        self.mocks.lock().unwrap().push(mock);
+        self
+    }
+
+    /// Returns the URLs of the requests received so far, in the order they were received.
+    pub fn requests(&self) -> Vec<Url> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Creates a client which sends its requests to this transport.
+    ///
+    /// # Parameters
+    ///
+    /// * `version` - The version of the API the client uses.
+    pub fn client(&self, version: ApiVersion) -> Realtime {
+        Realtime::builder("test-key")
+            .api_version(version)
+            .build()
+            .with_transport(self.clone())
+    }
+}
+
+impl Transport for MockTransport {
+    fn execute(&self, request: Request) -> TransportFuture<'_> {
+        self.requests.lock().unwrap().push(request.url().clone());
+        let response = self
+            .mocks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|mock| mock.matches(&request))
+            .map(Mock::response)
+            .unwrap_or_else(|| {
+                let message = format!("no mock matches GET {}", request.url());
+                Response::from(status_response(404, &message))
+            });
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+/// Returns a response with a status and a plain body.
+fn status_response(status: u16, body: &str) -> http::Response<String> {
+    let mut response = http::Response::new(body.to_string());
+    *response.status_mut() = http::StatusCode::from_u16(status).unwrap_or_default();
+    response
+}
+
+/// Returns the IDs of a comma separated query parameter, or [`None`] if the request does not
+/// have the parameter.
+///
+/// The raw query is split before decoding, so IDs separated by escaped commas are read as one.
+///
+/// # Parameters
+///
+/// * `url` - The URL of the request.
+/// * `name` - The name of the parameter.
+///
+/// [`None`]: std::option::Option::None
+fn query_ids(url: &Url, name: &str) -> Option<BTreeSet<String>> {
+    url.query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.split(',').map(decode).collect())
+    })
+}
+
+/// Decodes a percent encoded query value.
+fn decode(value: &str) -> String {
+    form_urlencoded::parse(value.as_bytes())
+        .next()
+        .map(|(decoded, _)| decoded.into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::REALTIME_COMBINED, transport::tests::block_on};
+
+    #[test]
+    fn answers_from_the_matching_mock() {
+        let mock = MockTransport::new();
+        mock.register(Mock::realtime(ApiVersion::V3).respond_with(500, ""))
+            .register(Mock::realtime(ApiVersion::V2).respond_with(200, REALTIME_COMBINED));
+        let realtime = mock.client(ApiVersion::V2);
+
+        let combined = block_on(realtime.fetch_combined(None, None)).unwrap();
+        assert_eq!(combined.entities.len() + combined.unmatched.len(), 3);
+        assert_eq!(mock.requests()[0].path(), "/v2/public/realtime");
+
+        let missing = block_on(realtime.fetch_versions()).unwrap_err();
+        assert_eq!(missing.status(), Some(reqwest::StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn matches_comma_separated_ids() {
+        let mock = MockTransport::new();
+        mock.register(
+            Mock::realtime(ApiVersion::V2)
+                .trip_ids(vec!["t 2", "t1"])
+                .respond_with(200, REALTIME_COMBINED),
+        )
+        .register(
+            Mock::realtime(ApiVersion::V2)
+                .respond_with(429, "")
+                .header("Retry-After", "7"),
+        );
+        let realtime = mock.client(ApiVersion::V2);
+
+        assert!(block_on(realtime.fetch_combined(["t1", "t 2"], None)).is_ok());
+        let query = mock.requests()[0].query().unwrap().to_string();
+        assert!(query.contains("tripid=t1,t%202") || query.contains("tripid=t%202,t1"));
+
+        let other = block_on(realtime.fetch_combined(["t1"], None)).unwrap_err();
+        assert_eq!(other.retry_after(), Some(std::time::Duration::from_secs(7)));
+
+        let url = Url::parse("https://api.at.govt.nz/x?tripid=t1%2Ct%202").unwrap();
+        assert_eq!(query_ids(&url, "tripid").unwrap().len(), 1);
+        assert_eq!(query_ids(&url, "vehicleid"), None);
+    }
+}
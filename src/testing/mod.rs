@@ -0,0 +1,59 @@
+//! Canned AT responses and helpers for writing tests against realistic payloads.
+//!
+//! The fixtures follow the shape of responses returned by AT's realtime endpoint, including its
+//! quirks such as bearings sent as strings or integers, and versioned trip, route and stop IDs.
+//! IDs and licence plates are made up. The [`mock`] module serves them to a client in place of
+//! the AT API.
+
+pub mod mock;
+
+use crate::{
+    decode::decode_merged,
+    error::{Error, Result},
+    types::{gtfs::Entity, ATResponse},
+    ApiVersion, CombinedResponse,
+};
+
+/// A combined realtime response containing:
+///
+/// * Vehicle `59708` serving a scheduled, delayed trip on route `11101`.
+/// * Vehicle `22114` stopped on a cancelled trip on route `25002`.
+/// * Vehicle `31520` reporting a position without an active trip.
+pub const REALTIME_COMBINED: &str = include_str!("fixtures/realtime_combined.json");
+
+/// A realtime response with a header but no entities.
+pub const REALTIME_EMPTY: &str = include_str!("fixtures/realtime_empty.json");
+
+/// Deserializes a raw realtime response.
+///
+/// # Parameters
+///
+/// * `json` - The raw response body, such as one of the fixtures in this module.
+pub fn parse_response(json: &str) -> Result<ATResponse> {
+    serde_json::from_str(json).map_err(|e| Error::decode(e, json.as_bytes()))
+}
+
+/// Deserializes and merges a raw realtime response in the same way as
+/// [`Realtime::fetch_combined`].
+///
+/// # Parameters
+///
+/// * `json` - The raw response body, such as one of the fixtures in this module.
+///
+/// [`Realtime::fetch_combined`]: crate::Realtime::fetch_combined
+pub fn parse_combined(json: &str) -> Result<CombinedResponse> {
+    decode_merged(ApiVersion::V2, json.as_bytes())
+}
+
+/// Returns the unmerged entities of [`REALTIME_COMBINED`].
+pub fn combined_entities() -> Vec<Entity> {
+    parse_response(REALTIME_COMBINED)
+        .expect("fixture is valid")
+        .response
+        .entity
+}
+
+/// Returns the merged entities of [`REALTIME_COMBINED`].
+pub fn merged_entities() -> CombinedResponse {
+    parse_combined(REALTIME_COMBINED).expect("fixture is valid")
+}
@@ -0,0 +1,66 @@
+//! The timers used by the clients to wait between requests.
+//!
+//! Backoff, rate limiting, read timeouts and polling all wait through the [`Timer`] trait, which
+//! is implemented by [`TokioTimer`] by default. Implementing the trait (or passing a closure)
+//! allows the streaming APIs to run on another async runtime, such as async-std or smol, when
+//! combined with a [`Transport`] which does not require tokio.
+//!
+//! [`Transport`]: crate::transport::Transport
+
+use std::{future::Future, pin::Pin, time::Duration};
+
+use futures_util::future::{self, Either};
+
+/// The future returned by [`Timer::sleep`].
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Creates futures which complete after a duration has passed.
+pub trait Timer: Send + Sync {
+    /// Returns a future which completes once the given duration has passed.
+    ///
+    /// # Parameters
+    ///
+    /// * `duration` - How long to wait.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// A timer backed by the tokio runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+impl<F> Timer for F
+where
+    F: Fn(Duration) -> Sleep + Send + Sync,
+{
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self(duration)
+    }
+}
+
+/// Runs a future to completion, or returns [`None`] if it does not complete within the given
+/// duration.
+///
+/// # Parameters
+///
+/// * `timer` - The timer to wait with.
+/// * `duration` - The maximum time to wait for the future.
+/// * `fut` - The future to run.
+///
+/// [`None`]: std::option::Option::None
+pub(crate) async fn timeout<F: Future>(
+    timer: &dyn Timer,
+    duration: Duration,
+    fut: F,
+) -> Option<F::Output> {
+    let fut = Box::pin(fut);
+    match future::select(fut, timer.sleep(duration)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
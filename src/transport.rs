@@ -0,0 +1,113 @@
+//! The HTTP layer used by the clients in this library.
+//!
+//! Requests are executed through the [`Transport`] trait, which is implemented for
+//! [`reqwest::Client`] by default. Implementing the trait allows plugging in another HTTP stack
+//! (by converting to and from reqwest's request and response types), a custom TLS
+//! configuration, or an in-process fake for testing. A fake response can be built from an
+//! [`http::Response`] with `reqwest::Response::from`.
+//!
+//! [`http::Response`]: https://docs.rs/http/0.2/http/response/struct.Response.html
+
+use std::{future::Future, pin::Pin};
+
+use reqwest::{Client, Request, Response};
+
+use crate::error::Result;
+
+/// The future returned by [`Transport::execute`].
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Response>> + Send + 'a>>;
+
+/// Executes HTTP requests on behalf of a client.
+pub trait Transport: Send + Sync {
+    /// Executes a request, returning the response received.
+    ///
+    /// Implementations should only return an error if no response was received. Responses with
+    /// non-success status codes are returned as responses.
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The request to execute, with authentication headers already set.
+    fn execute(&self, request: Request) -> TransportFuture<'_>;
+}
+
+impl Transport for Client {
+    fn execute(&self, request: Request) -> TransportFuture<'_> {
+        Box::pin(async move { Ok(Client::execute(self, request).await?) })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    type Respond = dyn Fn(&Request) -> http::Response<String> + Send + Sync;
+
+    /// A transport which answers every request with a closure, counting the requests.
+    #[derive(Clone)]
+    pub(crate) struct StubTransport {
+        respond: Arc<Respond>,
+        delay: Duration,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl StubTransport {
+        pub(crate) fn new<F>(respond: F) -> Self
+        where
+            F: Fn(&Request) -> http::Response<String> + Send + Sync + 'static,
+        {
+            Self {
+                respond: Arc::new(respond),
+                delay: Duration::ZERO,
+                requests: Arc::default(),
+            }
+        }
+
+        /// Answers each request after a delay, so concurrent requests overlap.
+        pub(crate) fn delay(mut self, delay: Duration) -> Self {
+            self.delay = delay;
+            self
+        }
+
+        /// Returns the number of requests executed so far.
+        pub(crate) fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Transport for StubTransport {
+        fn execute(&self, request: Request) -> TransportFuture<'_> {
+            Box::pin(async move {
+                self.requests.fetch_add(1, Ordering::SeqCst);
+                if !self.delay.is_zero() {
+                    tokio::time::sleep(self.delay).await;
+                }
+                Ok(Response::from((self.respond)(&request)))
+            })
+        }
+    }
+
+    /// Returns a response with the given status and body.
+    pub(crate) fn response(status: u16, body: &str) -> http::Response<String> {
+        http::Response::builder()
+            .status(status)
+            .body(body.to_string())
+            .unwrap()
+    }
+
+    /// Runs a future to completion on a single threaded runtime.
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+}
@@ -0,0 +1,218 @@
+//! Borrowed versions of the GTFS types, which reference strings in the response body instead of
+//! allocating them.
+//!
+//! These are intended for callers which process a response and throw it away, such as exporters
+//! which write a few fields of each vehicle somewhere else. Strings are only allocated when the
+//! JSON contains escape sequences. Use [`decode_borrowed`] to deserialize a response into these
+//! types, and [`EntityRef::into_owned`] to keep an entity past the lifetime of the body.
+//!
+//! [`decode_borrowed`]: crate::decode::decode_borrowed
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use super::gtfs::{
+    CongestionLevel, Entity, OccupancyStatus, Position, ScheduleRelationship,
+    ScheduleRelationshipTripDescriptor, StopTimeEvent, StopTimeUpdate, TripDescriptor, TripUpdate,
+    VehicleDescriptor, VehiclePosition, VehicleStopStatus,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EntityRef<'a> {
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+    #[serde(borrow)]
+    pub trip_update: Option<TripUpdateRef<'a>>,
+    #[serde(borrow)]
+    pub vehicle: Option<VehiclePositionRef<'a>>,
+    #[serde(default)]
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TripUpdateRef<'a> {
+    #[serde(borrow)]
+    pub trip: TripDescriptorRef<'a>,
+    #[serde(borrow)]
+    pub vehicle: Option<VehicleDescriptorRef<'a>>,
+    #[serde(borrow)]
+    pub stop_time_update: Option<StopTimeUpdateRef<'a>>,
+    pub timestamp: Option<u64>,
+    pub delay: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StopTimeUpdateRef<'a> {
+    pub stop_sequence: Option<u32>,
+    #[serde(borrow)]
+    pub stop_id: Option<Cow<'a, str>>,
+    pub arrival: Option<StopTimeEvent>,
+    pub departure: Option<StopTimeEvent>,
+    #[serde(default)]
+    pub schedule_relationship: ScheduleRelationship,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VehiclePositionRef<'a> {
+    #[serde(borrow)]
+    pub trip: Option<TripDescriptorRef<'a>>,
+    #[serde(borrow)]
+    pub vehicle: Option<VehicleDescriptorRef<'a>>,
+    pub position: Option<Position>,
+    pub current_stop_sequence: Option<u32>,
+    #[serde(borrow)]
+    pub stop_id: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub current_status: VehicleStopStatus,
+    pub timestamp: Option<u64>,
+    pub congestion_level: Option<CongestionLevel>,
+    pub occupancy_status: Option<OccupancyStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TripDescriptorRef<'a> {
+    #[serde(borrow)]
+    pub trip_id: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub route_id: Option<Cow<'a, str>>,
+    pub direction_id: Option<u32>,
+    #[serde(borrow)]
+    pub start_time: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub start_date: Option<Cow<'a, str>>,
+    pub schedule_relationship: Option<ScheduleRelationshipTripDescriptor>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VehicleDescriptorRef<'a> {
+    #[serde(borrow)]
+    pub id: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub label: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub license_plate: Option<Cow<'a, str>>,
+}
+
+impl EntityRef<'_> {
+    /// Returns the trip ID with the GTFS version truncated, without allocating.
+    pub fn trip_id(&self) -> Option<&str> {
+        truncate_version(self.trip_update.as_ref()?.trip.trip_id.as_ref()?)
+    }
+
+    /// Returns the route ID with the GTFS version truncated, without allocating.
+    pub fn route_id(&self) -> Option<&str> {
+        truncate_version(self.trip_update.as_ref()?.trip.route_id.as_ref()?)
+    }
+
+    /// Returns the current stop ID with the GTFS version truncated, without allocating.
+    pub fn stop_id(&self) -> Option<&str> {
+        truncate_version(
+            self.trip_update
+                .as_ref()?
+                .stop_time_update
+                .as_ref()?
+                .stop_id
+                .as_ref()?,
+        )
+    }
+
+    /// Converts the entity into an owned [`Entity`], allocating its strings.
+    ///
+    /// [`Entity`]: crate::types::gtfs::Entity
+    pub fn into_owned(self) -> Entity {
+        Entity {
+            id: self.id.into_owned(),
+            trip_update: self.trip_update.map(TripUpdateRef::into_owned),
+            vehicle: self.vehicle.map(VehiclePositionRef::into_owned),
+            is_deleted: self.is_deleted,
+        }
+    }
+}
+
+impl TripUpdateRef<'_> {
+    /// Converts the trip update into an owned [`TripUpdate`], allocating its strings.
+    ///
+    /// [`TripUpdate`]: crate::types::gtfs::TripUpdate
+    pub fn into_owned(self) -> TripUpdate {
+        TripUpdate {
+            trip: self.trip.into_owned(),
+            vehicle: self.vehicle.map(VehicleDescriptorRef::into_owned),
+            stop_time_update: self.stop_time_update.map(StopTimeUpdateRef::into_owned),
+            timestamp: self.timestamp,
+            delay: self.delay,
+        }
+    }
+}
+
+impl StopTimeUpdateRef<'_> {
+    /// Converts the stop time update into an owned [`StopTimeUpdate`], allocating its strings.
+    ///
+    /// [`StopTimeUpdate`]: crate::types::gtfs::StopTimeUpdate
+    pub fn into_owned(self) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_sequence: self.stop_sequence,
+            stop_id: self.stop_id.map(Cow::into_owned),
+            arrival: self.arrival,
+            departure: self.departure,
+            schedule_relationship: self.schedule_relationship,
+        }
+    }
+}
+
+impl VehiclePositionRef<'_> {
+    /// Converts the vehicle position into an owned [`VehiclePosition`], allocating its strings.
+    ///
+    /// [`VehiclePosition`]: crate::types::gtfs::VehiclePosition
+    pub fn into_owned(self) -> VehiclePosition {
+        VehiclePosition {
+            trip: self.trip.map(TripDescriptorRef::into_owned),
+            vehicle: self.vehicle.map(VehicleDescriptorRef::into_owned),
+            position: self.position,
+            current_stop_sequence: self.current_stop_sequence,
+            stop_id: self.stop_id.map(Cow::into_owned),
+            current_status: self.current_status,
+            timestamp: self.timestamp,
+            congestion_level: self.congestion_level,
+            occupancy_status: self.occupancy_status,
+        }
+    }
+}
+
+impl TripDescriptorRef<'_> {
+    /// Converts the trip descriptor into an owned [`TripDescriptor`], allocating its strings.
+    ///
+    /// [`TripDescriptor`]: crate::types::gtfs::TripDescriptor
+    pub fn into_owned(self) -> TripDescriptor {
+        TripDescriptor {
+            trip_id: self.trip_id.map(Cow::into_owned),
+            route_id: self.route_id.map(Cow::into_owned),
+            direction_id: self.direction_id,
+            start_time: self.start_time.map(Cow::into_owned),
+            start_date: self.start_date.map(Cow::into_owned),
+            schedule_relationship: self.schedule_relationship,
+        }
+    }
+}
+
+impl VehicleDescriptorRef<'_> {
+    /// Converts the vehicle descriptor into an owned [`VehicleDescriptor`], allocating its
+    /// strings.
+    ///
+    /// [`VehicleDescriptor`]: crate::types::gtfs::VehicleDescriptor
+    pub fn into_owned(self) -> VehicleDescriptor {
+        VehicleDescriptor {
+            id: self.id.map(Cow::into_owned),
+            label: self.label.map(Cow::into_owned),
+            license_plate: self.license_plate.map(Cow::into_owned),
+        }
+    }
+}
+
+/// Returns the part of an ID before the GTFS version suffix, in the same way as
+/// [`Entity::trip_id`].
+///
+/// [`Entity::trip_id`]: crate::types::gtfs::Entity::trip_id
+fn truncate_version(id: &str) -> Option<&str> {
+    id.find('-').map(|end| &id[..end])
+}
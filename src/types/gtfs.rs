@@ -41,20 +41,15 @@ pub struct StopTimeEvent {
     pub uncertainty: Option<i32>,
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy)]
+#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy, Default)]
 #[repr(u8)]
 pub enum ScheduleRelationship {
+    #[default]
     Scheduled = 0,
     Skipped = 1,
     NoData = 2,
 }
 
-impl Default for ScheduleRelationship {
-    fn default() -> Self {
-        Self::Scheduled
-    }
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VehiclePosition {
     pub trip: Option<TripDescriptor>,
@@ -69,7 +64,7 @@ pub struct VehiclePosition {
     pub occupancy_status: Option<OccupancyStatus>,
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy)]
+#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy, Default)]
 #[repr(u8)]
 pub enum VehicleStopStatus {
     // The vehicle is just about to arrive at the stop (on a stop display, the vehicle symbol
@@ -80,15 +75,10 @@ pub enum VehicleStopStatus {
     StoppedAt = 1,
 
     // The vehicle has departed and is in transit to the next stop.
+    #[default]
     InTransitTo = 2,
 }
 
-impl Default for VehicleStopStatus {
-    fn default() -> Self {
-        Self::InTransitTo
-    }
-}
-
 #[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy)]
 #[repr(u8)]
 pub enum CongestionLevel {
@@ -148,6 +138,19 @@ pub struct VehicleDescriptor {
     pub license_plate: Option<String>,
 }
 
+/// Whether a vehicle reporting its position is carrying passengers on a trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VehicleService {
+    /// The vehicle is serving a trip with a realtime trip update.
+    InService,
+    /// The vehicle is assigned to a trip which has no realtime trip update, such as a trip it
+    /// has finished or is waiting to start.
+    BetweenTrips,
+    /// The vehicle is not assigned to a trip, such as when it is running to or from the depot.
+    OutOfService,
+}
+
 impl Entity {
     /// Returns the trip ID with the GTFS version truncated.
     pub fn trip_id(&self) -> Option<String> {
@@ -172,6 +175,49 @@ impl Entity {
         )
     }
 
+    /// Returns true if the entity is for a trip which is not in the static schedule, that is a
+    /// trip whose schedule relationship is [`Added`] or [`Unscheduled`].
+    ///
+    /// [`Added`]: ScheduleRelationshipTripDescriptor::Added
+    /// [`Unscheduled`]: ScheduleRelationshipTripDescriptor::Unscheduled
+    pub fn is_extra_service(&self) -> bool {
+        let trip = self
+            .trip_update
+            .as_ref()
+            .map(|tu| &tu.trip)
+            .or_else(|| self.vehicle.as_ref()?.trip.as_ref());
+        matches!(
+            trip.and_then(|t| t.schedule_relationship),
+            Some(
+                ScheduleRelationshipTripDescriptor::Added
+                    | ScheduleRelationshipTripDescriptor::Unscheduled
+            )
+        )
+    }
+
+    /// Returns whether the vehicle of the entity is in service, or [`None`] if the entity has no
+    /// vehicle position.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn vehicle_service(&self) -> Option<VehicleService> {
+        let vehicle = self.vehicle.as_ref()?;
+        let trip_id = vehicle.trip.as_ref().and_then(|t| t.trip_id.as_ref());
+        Some(match (trip_id, &self.trip_update) {
+            (None, _) => VehicleService::OutOfService,
+            (Some(_), None) => VehicleService::BetweenTrips,
+            (Some(_), Some(_)) => VehicleService::InService,
+        })
+    }
+
+    /// Returns true if the entity has a vehicle position for a vehicle which is not serving a
+    /// trip, also called deadheading. See [`Entity::vehicle_service`].
+    pub fn is_deadhead(&self) -> bool {
+        matches!(
+            self.vehicle_service(),
+            Some(VehicleService::BetweenTrips | VehicleService::OutOfService)
+        )
+    }
+
     #[inline]
     fn substr_to_char<T: AsRef<str>>(str: T, c: char) -> Option<String> {
         let str = str.as_ref();
@@ -216,6 +262,13 @@ where
             Ok(v)
         }
 
+        fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(v as f32)
+        }
+
         fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
         where
             E: serde::de::Error,
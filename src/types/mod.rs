@@ -1,9 +1,11 @@
 //! Types and structures returned from the Auckland Transport API.
 
+pub mod borrowed;
 pub mod gtfs;
+pub mod schedule;
 
-use serde::Deserialize;
-use serde_repr::Deserialize_repr;
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use self::gtfs::Entity;
 
@@ -17,10 +19,19 @@ pub struct ATResponse {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Response {
     pub header: Header,
+    #[serde(default, deserialize_with = "deserialize_entities")]
     pub entity: Vec<Entity>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Deserializes a list of entities, treating `null` as an empty list.
+fn deserialize_entities<'de, D>(deserializer: D) -> std::result::Result<Vec<Entity>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Header {
     pub gtfs_realtime_version: String,
     #[serde(default)]
@@ -28,15 +39,10 @@ pub struct Header {
     pub timestamp: Option<f64>,
 }
 
-#[derive(Debug, Deserialize_repr, Clone, Copy)]
+#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy, Default)]
 #[repr(u8)]
 pub enum Incrementality {
+    #[default]
     FullDataset = 0,
     Differential = 1,
 }
-
-impl Default for Incrementality {
-    fn default() -> Self {
-        Self::FullDataset
-    }
-}
@@ -0,0 +1,78 @@
+//! Types returned from the static GTFS API, which serves the published schedule rather than the
+//! realtime feed.
+//!
+//! IDs returned by the static API are the full IDs including the GTFS version, in the same form
+//! as they appear in the realtime feed.
+
+use serde::{Deserialize, Serialize};
+
+/// A route in the published schedule.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Route {
+    pub route_id: String,
+    pub agency_id: Option<String>,
+    /// The route number shown to passengers, such as `82` or `NX2`.
+    pub route_short_name: String,
+    pub route_long_name: Option<String>,
+    pub route_type: Option<u16>,
+}
+
+/// A stop or station in the published schedule.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Stop {
+    pub stop_id: String,
+    /// The code shown on the stop's signage, such as `7036`.
+    pub stop_code: Option<String>,
+    pub stop_name: String,
+    pub stop_lat: f64,
+    pub stop_lon: f64,
+}
+
+/// A trip in the published schedule.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Trip {
+    pub trip_id: String,
+    pub route_id: String,
+    pub service_id: Option<String>,
+    pub trip_headsign: Option<String>,
+    pub direction_id: Option<u32>,
+    /// The block the trip belongs to. A block is a sequence of trips served back-to-back by the
+    /// same vehicle.
+    pub block_id: Option<String>,
+    pub shape_id: Option<String>,
+}
+
+/// A scheduled stop of a trip in the published schedule.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StopTime {
+    pub trip_id: String,
+    pub stop_id: String,
+    /// The position of the stop in the trip. Sequence numbers increase along the trip, but are
+    /// not necessarily consecutive.
+    pub stop_sequence: u32,
+    pub arrival_time: Option<String>,
+    pub departure_time: Option<String>,
+}
+
+/// A point on the path a vehicle travels along in the published schedule.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShapePoint {
+    pub shape_id: String,
+    pub shape_pt_lat: f64,
+    pub shape_pt_lon: f64,
+    /// The position of the point in the shape. Sequence numbers increase along the shape, but
+    /// are not necessarily consecutive.
+    pub shape_pt_sequence: u32,
+    /// The distance along the shape to the point, in metres.
+    pub shape_dist_traveled: Option<f64>,
+}
+
+/// A published version of the GTFS dataset.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GtfsVersion {
+    pub version: String,
+    /// The date the version comes into effect.
+    pub startdate: String,
+    /// The date the version stops being in effect.
+    pub enddate: String,
+}
@@ -0,0 +1,420 @@
+//! Validation of snapshots against the semantics of GTFS-RT.
+//!
+//! The decoder accepts anything AT sends which has the right shape, so responses can include
+//! entities which GTFS-RT does not allow, such as trip descriptors which identify no trip. A
+//! [`Validator`] checks each snapshot and returns a [`ValidationReport`] alongside it, for users
+//! who feed the data into systems which reject such input. Use
+//! [`Realtime::fetch_combined_validated`] or [`Realtime::stream_validated`] to validate every
+//! response.
+//!
+//! Timestamps are checked against the time the snapshot was fetched, so recorded snapshots can
+//! be validated after the fact.
+//!
+//! [`Realtime::fetch_combined_validated`]: crate::Realtime::fetch_combined_validated
+//! [`Realtime::stream_validated`]: crate::Realtime::stream_validated
+
+use std::{
+    collections::HashMap,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use futures_util::{stream::Stream, StreamExt};
+
+use crate::{
+    error::Result,
+    types::gtfs::{Entity, Position, StopTimeUpdate, TripDescriptor},
+    CombinedResponse,
+};
+
+/// How far ahead of the fetch time a timestamp can be, to allow for clock skew.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// How far behind the fetch time a timestamp can be before it is considered stale.
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// What is wrong with a part of a snapshot.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum IssueKind {
+    /// The header has no timestamp, which GTFS-RT requires.
+    MissingHeaderTimestamp,
+    /// A timestamp is further in the future than clock skew explains.
+    FutureTimestamp {
+        /// The UNIX timestamp.
+        timestamp: u64,
+    },
+    /// A timestamp is more than an hour older than the snapshot.
+    StaleTimestamp {
+        /// The UNIX timestamp.
+        timestamp: u64,
+    },
+    /// The entity has neither a trip update nor a vehicle position, and is not deleted.
+    ///
+    /// Merging drops such entities, so this is only found in snapshots which were built by hand
+    /// rather than fetched or decoded by the crate.
+    EmptyEntity,
+    /// A trip descriptor has neither a trip ID nor a route ID, so it identifies no trip.
+    MissingTripIdentifier,
+    /// A stop time update has neither a stop sequence nor a stop ID.
+    MissingStopIdentifier,
+    /// A stop time update has neither an arrival nor a departure.
+    MissingStopTimeEvent,
+    /// The departure time of a stop time update is before its arrival time.
+    DepartureBeforeArrival,
+    /// The stop sequence of a trip went backwards since the previous snapshot.
+    StopSequenceDecreased {
+        /// The stop sequence in the previous snapshot.
+        previous: u32,
+        /// The stop sequence in this snapshot.
+        current: u32,
+    },
+    /// A vehicle position has neither a position nor a trip.
+    EmptyVehiclePosition,
+    /// A position is outside the range of latitudes and longitudes, or is at 0, 0.
+    InvalidCoordinates {
+        /// The latitude of the position.
+        latitude: f32,
+        /// The longitude of the position.
+        longitude: f32,
+    },
+}
+
+/// A problem found in a snapshot.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ValidationIssue {
+    /// The ID of the entity with the problem, or [`None`] for problems with the header.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub entity_id: Option<String>,
+    /// What is wrong.
+    pub kind: IssueKind,
+}
+
+/// The problems found in a snapshot.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ValidationReport {
+    /// The problems, in the order they were found.
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns true if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, entity: Option<&Entity>, kind: IssueKind) {
+        self.issues.push(ValidationIssue {
+            entity_id: entity.map(|entity| entity.id.clone()),
+            kind,
+        });
+    }
+}
+
+/// Validates snapshots, keeping the stop sequence of each trip between snapshots so trips
+/// which go backwards can be found.
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    stop_sequences: HashMap<String, u32>,
+}
+
+impl Validator {
+    /// Creates a validator which has not validated a snapshot yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validates a snapshot, checking both the merged and the unmatched entities.
+    ///
+    /// # Parameters
+    ///
+    /// * `combined` - The snapshot to validate.
+    pub fn validate(&mut self, combined: &CombinedResponse) -> ValidationReport {
+        let now = combined
+            .fetched_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let mut report = ValidationReport::default();
+
+        match combined.header.timestamp {
+            Some(timestamp) => check_timestamp(&mut report, None, timestamp as u64, now),
+            None => report.push(None, IssueKind::MissingHeaderTimestamp),
+        }
+
+        let mut stop_sequences = HashMap::new();
+        let entities = combined.entities.iter().chain(combined.unmatched.iter());
+        for entity in entities {
+            if entity.trip_update.is_none() && entity.vehicle.is_none() {
+                if !entity.is_deleted {
+                    report.push(Some(entity), IssueKind::EmptyEntity);
+                }
+                continue;
+            }
+
+            if let Some(trip_update) = &entity.trip_update {
+                check_trip(&mut report, entity, &trip_update.trip);
+                if let Some(timestamp) = trip_update.timestamp {
+                    check_timestamp(&mut report, Some(entity), timestamp, now);
+                }
+                if let Some(update) = &trip_update.stop_time_update {
+                    check_stop_time_update(&mut report, entity, update);
+
+                    if let (Some(trip_id), Some(sequence)) =
+                        (trip_update.trip.trip_id.as_deref(), update.stop_sequence)
+                    {
+                        match self.stop_sequences.get(trip_id) {
+                            Some(&previous) if sequence < previous => report.push(
+                                Some(entity),
+                                IssueKind::StopSequenceDecreased {
+                                    previous,
+                                    current: sequence,
+                                },
+                            ),
+                            _ => {}
+                        }
+                        stop_sequences.insert(trip_id.to_string(), sequence);
+                    }
+                }
+            }
+
+            if let Some(vehicle) = &entity.vehicle {
+                if vehicle.position.is_none() && vehicle.trip.is_none() {
+                    report.push(Some(entity), IssueKind::EmptyVehiclePosition);
+                }
+                if let Some(trip) = &vehicle.trip {
+                    check_trip(&mut report, entity, trip);
+                }
+                if let Some(position) = &vehicle.position {
+                    check_position(&mut report, entity, position);
+                }
+                if let Some(timestamp) = vehicle.timestamp {
+                    check_timestamp(&mut report, Some(entity), timestamp, now);
+                }
+            }
+        }
+
+        self.stop_sequences = stop_sequences;
+        report
+    }
+}
+
+fn check_timestamp(
+    report: &mut ValidationReport,
+    entity: Option<&Entity>,
+    timestamp: u64,
+    now: u64,
+) {
+    if timestamp > now + MAX_CLOCK_SKEW.as_secs() {
+        report.push(entity, IssueKind::FutureTimestamp { timestamp });
+    } else if timestamp + MAX_AGE.as_secs() < now {
+        report.push(entity, IssueKind::StaleTimestamp { timestamp });
+    }
+}
+
+fn check_trip(report: &mut ValidationReport, entity: &Entity, trip: &TripDescriptor) {
+    if trip.trip_id.is_none() && trip.route_id.is_none() {
+        report.push(Some(entity), IssueKind::MissingTripIdentifier);
+    }
+}
+
+fn check_stop_time_update(report: &mut ValidationReport, entity: &Entity, update: &StopTimeUpdate) {
+    if update.stop_sequence.is_none() && update.stop_id.is_none() {
+        report.push(Some(entity), IssueKind::MissingStopIdentifier);
+    }
+
+    let arrival = update.arrival.as_ref().and_then(|event| event.time);
+    let departure = update.departure.as_ref().and_then(|event| event.time);
+    if update.arrival.is_none() && update.departure.is_none() {
+        report.push(Some(entity), IssueKind::MissingStopTimeEvent);
+    } else if let (Some(arrival), Some(departure)) = (arrival, departure) {
+        if departure < arrival {
+            report.push(Some(entity), IssueKind::DepartureBeforeArrival);
+        }
+    }
+}
+
+fn check_position(report: &mut ValidationReport, entity: &Entity, position: &Position) {
+    let (latitude, longitude) = (position.latitude, position.longitude);
+    let valid = (-90.0..=90.0).contains(&latitude)
+        && (-180.0..=180.0).contains(&longitude)
+        && (latitude, longitude) != (0.0, 0.0);
+    if !valid {
+        report.push(
+            Some(entity),
+            IssueKind::InvalidCoordinates {
+                latitude,
+                longitude,
+            },
+        );
+    }
+}
+
+/// Turns a stream of snapshots, such as [`Realtime::stream`], into a stream of snapshots with
+/// their validation reports. Errors from the snapshot stream are passed through.
+///
+/// # Parameters
+///
+/// * `snapshots` - The stream of snapshots to validate.
+///
+/// [`Realtime::stream`]: crate::Realtime::stream
+pub fn validated<S>(
+    snapshots: S,
+) -> impl Stream<Item = Result<(CombinedResponse, ValidationReport)>>
+where
+    S: Stream<Item = Result<CombinedResponse>>,
+{
+    let mut validator = Validator::new();
+    snapshots.map(move |result| {
+        result.map(|combined| {
+            let report = validator.validate(&combined);
+            (combined, report)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::combined::tests::{header, snapshot, unmatched};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn kinds(report: &ValidationReport) -> Vec<&IssueKind> {
+        report.issues.iter().map(|issue| &issue.kind).collect()
+    }
+
+    fn at_now(mut combined: CombinedResponse) -> CombinedResponse {
+        combined.header = header(NOW);
+        combined.fetched_at = UNIX_EPOCH + Duration::from_secs(NOW);
+        combined
+    }
+
+    fn trip_at(sequence: u32) -> CombinedResponse {
+        at_now(unmatched(json!([{
+            "id": "t1",
+            "trip_update": {
+                "trip": {"trip_id": "t1"},
+                "stop_time_update": {"stop_sequence": sequence, "arrival": {"time": NOW}}
+            }
+        }])))
+    }
+
+    #[test]
+    fn finds_stop_sequences_going_backwards() {
+        let mut validator = Validator::new();
+        assert!(validator.validate(&trip_at(5)).is_clean());
+        assert!(validator.validate(&trip_at(5)).is_clean());
+
+        let report = validator.validate(&trip_at(3));
+        assert_eq!(
+            kinds(&report),
+            [&IssueKind::StopSequenceDecreased {
+                previous: 5,
+                current: 3
+            }]
+        );
+        assert_eq!(report.issues[0].entity_id.as_deref(), Some("t1"));
+        assert!(validator.validate(&trip_at(4)).is_clean());
+    }
+
+    #[test]
+    fn checks_timestamps_against_the_fetch_time() {
+        let vehicle = |timestamp: u64| {
+            json!({
+                "id": "v1",
+                "vehicle": {"trip": {"trip_id": "t1"}, "timestamp": timestamp}
+            })
+        };
+        let combined = at_now(unmatched(json!([
+            vehicle(NOW + 60),
+            vehicle(NOW + 61),
+            vehicle(NOW - 3600),
+            vehicle(NOW - 3601)
+        ])));
+
+        let report = Validator::new().validate(&combined);
+        assert_eq!(
+            kinds(&report),
+            [
+                &IssueKind::FutureTimestamp {
+                    timestamp: NOW + 61
+                },
+                &IssueKind::StaleTimestamp {
+                    timestamp: NOW - 3601
+                },
+            ]
+        );
+
+        let mut combined = at_now(snapshot(json!([])));
+        combined.header.timestamp = None;
+        let report = Validator::new().validate(&combined);
+        assert_eq!(kinds(&report), [&IssueKind::MissingHeaderTimestamp]);
+    }
+
+    #[test]
+    fn rejects_invalid_coordinates() {
+        let vehicle = |latitude: f32, longitude: f32| {
+            json!({
+                "id": "v1",
+                "vehicle": {"position": {"latitude": latitude, "longitude": longitude}}
+            })
+        };
+        let combined = at_now(unmatched(json!([
+            vehicle(-36.85, 174.76),
+            vehicle(0.0, 0.0),
+            vehicle(-91.0, 174.0),
+            vehicle(-36.0, 181.0)
+        ])));
+
+        let report = Validator::new().validate(&combined);
+        let invalid: Vec<(f32, f32)> = report
+            .issues
+            .iter()
+            .map(|issue| match issue.kind {
+                IssueKind::InvalidCoordinates {
+                    latitude,
+                    longitude,
+                } => (latitude, longitude),
+                ref kind => panic!("unexpected issue {:?}", kind),
+            })
+            .collect();
+        assert_eq!(invalid, [(0.0, 0.0), (-91.0, 174.0), (-36.0, 181.0)]);
+    }
+
+    #[test]
+    fn checks_stop_time_updates() {
+        let update = |stop_time_update: serde_json::Value| {
+            json!({
+                "id": "t1",
+                "trip_update": {"trip": {"route_id": "r1"}, "stop_time_update": stop_time_update}
+            })
+        };
+        let combined = at_now(unmatched(json!([
+            update(json!({"stop_id": "s1", "arrival": {"time": NOW}, "departure": {"time": NOW}})),
+            update(json!({
+                "stop_id": "s1",
+                "arrival": {"time": NOW},
+                "departure": {"time": NOW - 1}
+            })),
+            update(json!({"arrival": {"delay": 0}})),
+            update(json!({"stop_sequence": 1})),
+            {"id": "e1"},
+            {"id": "e2", "is_deleted": true}
+        ])));
+
+        let report = Validator::new().validate(&combined);
+        assert_eq!(
+            kinds(&report),
+            [
+                &IssueKind::DepartureBeforeArrival,
+                &IssueKind::MissingStopIdentifier,
+                &IssueKind::MissingStopTimeEvent,
+                &IssueKind::EmptyEntity,
+            ]
+        );
+    }
+}
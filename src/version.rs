@@ -0,0 +1,68 @@
+//! Versions and endpoints of the AT API used by the clients.
+
+use serde::Deserialize;
+
+/// A version of the AT API.
+///
+/// The version determines the paths requests are sent to, and how responses are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ApiVersion {
+    /// The original API, served under `/v2`.
+    #[default]
+    V2,
+    /// The newer API, which serves the realtime feed under `/realtime/legacy` and the static GTFS
+    /// API under `/gtfs/v3`.
+    V3,
+}
+
+impl ApiVersion {
+    /// Returns the path of the combined realtime endpoint, relative to the base URL.
+    pub(crate) fn realtime_path(self) -> &'static str {
+        match self {
+            ApiVersion::V2 => "/v2/public/realtime",
+            ApiVersion::V3 => "/realtime/legacy",
+        }
+    }
+
+    /// Returns the path of the trip updates endpoint, relative to the base URL.
+    pub(crate) fn trip_updates_path(self) -> &'static str {
+        match self {
+            ApiVersion::V2 => "/v2/public/realtime/tripupdates",
+            ApiVersion::V3 => "/realtime/legacy/tripupdates",
+        }
+    }
+
+    /// Returns the path of the vehicle positions endpoint, relative to the base URL.
+    pub(crate) fn vehicle_positions_path(self) -> &'static str {
+        match self {
+            ApiVersion::V2 => "/v2/public/realtime/vehiclelocations",
+            ApiVersion::V3 => "/realtime/legacy/vehiclelocations",
+        }
+    }
+
+    /// Returns the path the static GTFS API is served under, relative to the base URL.
+    pub(crate) fn static_gtfs_path(self) -> &'static str {
+        match self {
+            ApiVersion::V2 => "/v2/gtfs",
+            ApiVersion::V3 => "/gtfs/v3",
+        }
+    }
+}
+
+/// The endpoints used to fetch the combined feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum FetchStrategy {
+    /// Fetch trip updates and vehicle positions together from the combined realtime endpoint.
+    #[default]
+    Combined,
+    /// Fetch trip updates and vehicle positions concurrently from their separate endpoints, and
+    /// merge them. This does not rely on the shape of the combined endpoint's payload, and is
+    /// usually faster.
+    ///
+    /// With a recorder attached, the two responses are recorded separately and replayed together.
+    Split,
+}